@@ -0,0 +1,238 @@
+// Music analysis for audio reactive visuals.
+//
+// The analyzer steps through the decoded music in fixed hops up to the current
+// playback position, so onset detection behaves the same at any frame rate.
+
+use std::collections::VecDeque;
+
+use crate::audio::AudioBuffer;
+
+pub const SPECTRUM_BANDS: usize = 64;
+
+const FFT_SIZE: usize = 1024;
+const HOP: usize = 512;
+// Larger jumps than this (seeking) restart the analysis instead of catching up.
+const MAX_CATCH_UP_HOPS: usize = 32;
+
+const MIN_FREQUENCY: f32 = 30.0;
+const MAX_FREQUENCY: f32 = 16000.0;
+const DB_RANGE: f32 = 70.0;
+
+// Spectral flux history used as the adaptive onset threshold, in hops.
+const FLUX_HISTORY: usize = 43;
+const ONSET_SENSITIVITY: f32 = 1.5;
+const MIN_BEAT_INTERVAL: f32 = 0.25;
+const BEAT_DECAY: f32 = 0.15;
+
+#[derive(Clone, Debug)]
+pub struct AudioFeatures {
+    /// Log spaced bands from 30 Hz to 16 kHz, scaled to 0..1 over a 70 dB range.
+    pub spectrum: [f32; SPECTRUM_BANDS],
+    pub rms: f32,
+    pub bass: f32,
+    pub mid: f32,
+    pub treble: f32,
+    /// 1 on a detected beat, decaying towards 0.
+    pub beat: f32,
+    pub beats: u32,
+}
+
+impl Default for AudioFeatures {
+    fn default() -> Self {
+        Self {
+            spectrum: [0.0; SPECTRUM_BANDS],
+            rms: 0.0,
+            bass: 0.0,
+            mid: 0.0,
+            treble: 0.0,
+            beat: 0.0,
+            beats: 0,
+        }
+    }
+}
+
+pub struct Analyzer {
+    window: Vec<f32>,
+    magnitudes: Vec<f32>,
+    previous_magnitudes: Vec<f32>,
+    flux_history: VecDeque<f32>,
+    // Frame the next analysis window ends at.
+    next_frame: usize,
+    last_beat_frame: Option<usize>,
+    features: AudioFeatures,
+}
+
+impl Analyzer {
+    pub fn new() -> Self {
+        let window = (0..FFT_SIZE)
+            .map(|i| {
+                let x = i as f32 / (FFT_SIZE - 1) as f32;
+                0.5 - 0.5 * (std::f32::consts::TAU * x).cos()
+            })
+            .collect();
+
+        Self {
+            window,
+            magnitudes: vec![0.0; FFT_SIZE / 2],
+            previous_magnitudes: vec![0.0; FFT_SIZE / 2],
+            flux_history: VecDeque::with_capacity(FLUX_HISTORY),
+            next_frame: FFT_SIZE,
+            last_beat_frame: None,
+            features: AudioFeatures::default(),
+        }
+    }
+
+    /// Analyzes `buffer` up to `frame`, the current playback position.
+    pub fn update(&mut self, buffer: &AudioBuffer, frame: usize) -> &AudioFeatures {
+        let behind = frame.saturating_sub(self.next_frame) / HOP;
+        if frame + HOP < self.next_frame.saturating_sub(FFT_SIZE) || behind > MAX_CATCH_UP_HOPS {
+            self.reset(frame);
+        }
+
+        while self.next_frame <= frame {
+            self.analyze_hop(buffer, self.next_frame);
+            self.next_frame += HOP;
+        }
+
+        if let Some(last_beat) = self.last_beat_frame {
+            let seconds = frame.saturating_sub(last_beat) as f32 / buffer.sample_rate as f32;
+            self.features.beat = (-seconds / BEAT_DECAY).exp();
+        }
+
+        &self.features
+    }
+
+    fn reset(&mut self, frame: usize) {
+        self.next_frame = frame.max(FFT_SIZE);
+        self.flux_history.clear();
+        self.previous_magnitudes.iter_mut().for_each(|m| *m = 0.0);
+        self.last_beat_frame = None;
+        self.features.beat = 0.0;
+    }
+
+    fn analyze_hop(&mut self, buffer: &AudioBuffer, end_frame: usize) {
+        let start = end_frame - FFT_SIZE;
+
+        let mut re: Vec<f32> = (0..FFT_SIZE).map(|i| buffer.mono(start + i)).collect();
+        let mut im = vec![0.0; FFT_SIZE];
+
+        self.features.rms = (re.iter().map(|s| s * s).sum::<f32>() / FFT_SIZE as f32).sqrt();
+
+        for (sample, weight) in re.iter_mut().zip(&self.window) {
+            *sample *= weight;
+        }
+        fft(&mut re, &mut im);
+
+        // The Hann window halves the amplitude.
+        let scale = 4.0 / FFT_SIZE as f32;
+        for (bin, magnitude) in self.magnitudes.iter_mut().enumerate() {
+            *magnitude = (re[bin] * re[bin] + im[bin] * im[bin]).sqrt() * scale;
+        }
+
+        self.update_spectrum(buffer.sample_rate as f32);
+        self.detect_onset(end_frame, buffer.sample_rate as f32);
+
+        std::mem::swap(&mut self.magnitudes, &mut self.previous_magnitudes);
+    }
+
+    fn update_spectrum(&mut self, sample_rate: f32) {
+        let bin_width = sample_rate / FFT_SIZE as f32;
+        let ratio = (MAX_FREQUENCY / MIN_FREQUENCY).powf(1.0 / SPECTRUM_BANDS as f32);
+
+        let (mut bass, mut mid, mut treble) = ((0.0, 0), (0.0, 0), (0.0, 0));
+        for band in 0..SPECTRUM_BANDS {
+            let low = MIN_FREQUENCY * ratio.powi(band as i32);
+            let high = low * ratio;
+
+            let first = ((low / bin_width) as usize).min(FFT_SIZE / 2 - 1);
+            let last = ((high / bin_width) as usize).clamp(first + 1, FFT_SIZE / 2);
+            let magnitude = self.magnitudes[first..last]
+                .iter()
+                .copied()
+                .fold(0.0, f32::max);
+
+            let db = 20.0 * magnitude.max(1e-6).log10();
+            let value = ((db + DB_RANGE) / DB_RANGE).clamp(0.0, 1.0);
+            self.features.spectrum[band] = value;
+
+            let range = match low {
+                f if f < 250.0 => &mut bass,
+                f if f < 4000.0 => &mut mid,
+                _ => &mut treble,
+            };
+            range.0 += value;
+            range.1 += 1;
+        }
+
+        let average = |(sum, count): (f32, i32)| if count > 0 { sum / count as f32 } else { 0.0 };
+        self.features.bass = average(bass);
+        self.features.mid = average(mid);
+        self.features.treble = average(treble);
+    }
+
+    fn detect_onset(&mut self, frame: usize, sample_rate: f32) {
+        // Spectral flux: how much energy was added since the previous hop.
+        let flux: f32 = self
+            .magnitudes
+            .iter()
+            .zip(&self.previous_magnitudes)
+            .map(|(current, previous)| (current - previous).max(0.0))
+            .sum();
+
+        let threshold = if self.flux_history.is_empty() {
+            f32::MAX
+        } else {
+            self.flux_history.iter().sum::<f32>() / self.flux_history.len() as f32
+                * ONSET_SENSITIVITY
+        };
+
+        let since_last_beat = match self.last_beat_frame {
+            Some(last_beat) => (frame - last_beat) as f32 / sample_rate,
+            None => f32::MAX,
+        };
+
+        if flux > threshold && flux > 1e-3 && since_last_beat >= MIN_BEAT_INTERVAL {
+            self.last_beat_frame = Some(frame);
+            self.features.beats += 1;
+        }
+
+        if self.flux_history.len() == FLUX_HISTORY {
+            self.flux_history.pop_front();
+        }
+        self.flux_history.push_back(flux);
+    }
+}
+
+/// In place iterative radix-2 FFT, the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    debug_assert!(n.is_power_of_two() && im.len() == n);
+
+    let bits = n.trailing_zeros();
+    for i in 0..n {
+        let j = i.reverse_bits() >> (usize::BITS - bits);
+        if j > i {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+
+    let mut size = 2;
+    while size <= n {
+        let angle = -std::f32::consts::TAU / size as f32;
+        for start in (0..n).step_by(size) {
+            for k in 0..size / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let a = start + k;
+                let b = a + size / 2;
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        size *= 2;
+    }
+}
@@ -0,0 +1,266 @@
+// Music playback from memory.
+//
+// Tracks are fully decoded (or synthesized) up front so the analysis can look
+// at the samples around the current playback position.
+
+use anyhow::*;
+use rodio::Source;
+use std::io::BufReader;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const VOLUME_STEP: f32 = 0.1;
+const MAX_VOLUME: f32 = 2.0;
+// How far the clock runs ahead of the last buffer before it waits for the next.
+const MAX_EXTRAPOLATION: f32 = 0.1;
+
+/// Interleaved samples.
+#[derive(Clone)]
+pub struct AudioBuffer {
+    pub samples: Arc<[f32]>,
+    pub channels: u16,
+    pub sample_rate: u32,
+}
+
+impl AudioBuffer {
+    pub fn decode(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let file =
+            std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?;
+        let decoder = rodio::Decoder::new(BufReader::new(file))
+            .with_context(|| format!("decoding {}", path.display()))?;
+        let channels = decoder.channels();
+        let sample_rate = decoder.sample_rate();
+        let samples: Vec<f32> = decoder.convert_samples().collect();
+
+        Ok(Self {
+            samples: samples.into(),
+            channels,
+            sample_rate,
+        })
+    }
+
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels as usize
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_secs_f32(self.frames() as f32 / self.sample_rate as f32)
+    }
+
+    /// Average of all channels, silence outside the buffer.
+    pub fn mono(&self, frame: usize) -> f32 {
+        let channels = self.channels as usize;
+        match self.samples.get(frame * channels..(frame + 1) * channels) {
+            Some(samples) => samples.iter().sum::<f32>() / channels as f32,
+            None => 0.0,
+        }
+    }
+}
+
+/// How far playback of a buffer has progressed, shared with its source.
+#[derive(Clone)]
+pub struct PlaybackPosition {
+    sample: Arc<AtomicUsize>,
+    channels: u16,
+    sample_rate: u32,
+}
+
+impl PlaybackPosition {
+    pub fn frame(&self) -> usize {
+        self.sample.load(Ordering::Relaxed) / self.channels as usize
+    }
+
+    pub fn seconds(&self) -> f32 {
+        self.frame() as f32 / self.sample_rate as f32
+    }
+}
+
+/// Playback time that moves smoothly. The position only advances when the
+/// output pulls the next buffer, in between the clock follows wall time.
+pub struct PlaybackClock {
+    position: PlaybackPosition,
+    // Position at the last change and when it was seen.
+    frame: usize,
+    changed: Instant,
+    seconds: f32,
+}
+
+impl PlaybackClock {
+    pub fn new(position: PlaybackPosition) -> Self {
+        Self {
+            frame: position.frame(),
+            changed: Instant::now(),
+            seconds: position.seconds(),
+            position,
+        }
+    }
+
+    pub fn seconds(&mut self, now: Instant) -> f32 {
+        let frame = self.position.frame();
+        if frame != self.frame {
+            self.frame = frame;
+            self.changed = now;
+        }
+        let since = now.saturating_duration_since(self.changed).as_secs_f32();
+        let seconds = self.position.seconds() + since.min(MAX_EXTRAPOLATION);
+        // A buffer landing behind the estimate would step time back, a larger
+        // jump is the music looping.
+        let behind = self.seconds - seconds;
+        if behind <= 0.0 || behind > MAX_EXTRAPOLATION {
+            self.seconds = seconds;
+        }
+        self.seconds
+    }
+}
+
+pub struct BufferSource {
+    buffer: AudioBuffer,
+    sample: Arc<AtomicUsize>,
+    looping: bool,
+}
+
+impl Iterator for BufferSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let mut sample = self.sample.load(Ordering::Relaxed);
+        if self.looping && sample >= self.buffer.samples.len() {
+            sample = 0;
+        }
+        let value = *self.buffer.samples.get(sample)?;
+        self.sample.store(sample + 1, Ordering::Relaxed);
+        Some(value)
+    }
+}
+
+impl rodio::Source for BufferSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        self.buffer.channels
+    }
+
+    fn sample_rate(&self) -> u32 {
+        self.buffer.sample_rate
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        if self.looping {
+            None
+        } else {
+            Some(self.buffer.duration())
+        }
+    }
+}
+
+/// Returns a source that plays `buffer` from `start` together with its position.
+pub fn play(
+    buffer: AudioBuffer,
+    start: Duration,
+    looping: bool,
+) -> (BufferSource, PlaybackPosition) {
+    let frame = (start.as_secs_f64() * buffer.sample_rate as f64) as usize;
+    let sample = Arc::new(AtomicUsize::new(frame * buffer.channels as usize));
+    let position = PlaybackPosition {
+        sample: sample.clone(),
+        channels: buffer.channels,
+        sample_rate: buffer.sample_rate,
+    };
+    let source = BufferSource {
+        buffer,
+        sample,
+        looping,
+    };
+    (source, position)
+}
+
+struct Fade {
+    from: f32,
+    to: f32,
+    start: Instant,
+    duration: Duration,
+}
+
+/// User volume, mute and fades, combined into the gain for the music sink.
+pub struct Volume {
+    volume: f32,
+    muted: bool,
+    fade: Option<Fade>,
+}
+
+impl Volume {
+    pub fn new(volume: f32, muted: bool) -> Self {
+        Self {
+            volume,
+            muted,
+            fade: None,
+        }
+    }
+
+    pub fn increase(&mut self) {
+        self.volume = (self.volume + VOLUME_STEP).min(MAX_VOLUME);
+    }
+
+    pub fn decrease(&mut self) {
+        self.volume = (self.volume - VOLUME_STEP).max(0.0);
+    }
+
+    pub fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.volume
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted
+    }
+
+    pub fn fade_in(&mut self, duration: Duration) {
+        self.fade_between(0.0, 1.0, duration);
+    }
+
+    /// Fades out from wherever the current fade is.
+    pub fn fade_out(&mut self, duration: Duration) {
+        let level = self.fade_level(Instant::now());
+        self.fade_between(level, 0.0, duration);
+    }
+
+    pub fn is_fading_out(&self) -> bool {
+        matches!(&self.fade, Some(fade) if fade.to < fade.from)
+    }
+
+    fn fade_between(&mut self, from: f32, to: f32, duration: Duration) {
+        self.fade = Some(Fade {
+            from,
+            to,
+            start: Instant::now(),
+            duration,
+        });
+    }
+
+    fn fade_level(&self, now: Instant) -> f32 {
+        match &self.fade {
+            Some(fade) => {
+                let t = now.duration_since(fade.start).as_secs_f32()
+                    / fade.duration.as_secs_f32().max(f32::EPSILON);
+                fade.from + (fade.to - fade.from) * t.min(1.0)
+            }
+            None => 1.0,
+        }
+    }
+
+    pub fn gain(&self, now: Instant) -> f32 {
+        if self.muted {
+            0.0
+        } else {
+            self.volume * self.fade_level(now)
+        }
+    }
+}
@@ -0,0 +1,172 @@
+// Camera shared by the raymarched passes, meshes and particles.
+//
+// The raymarcher turns the view and projection back into a ray per pixel, so
+// all of them see the same field of view. The projection stores reversed
+// depth, near / z, which keeps precision far out and is what the raymarcher
+// writes too.
+
+/// Column major, like WGSL's mat4x4.
+pub type Matrix = [[f32; 4]; 4];
+
+/// Distance of the near plane, also in the shaders.
+pub const NEAR: f32 = 0.05;
+
+pub const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Vertical field of view when nothing sets one, 90 degrees across a 16:9 width.
+pub const DEFAULT_FOV: f32 = 1.0248;
+
+pub struct Camera {
+    pub position: [f32; 3],
+    pub forward: [f32; 3],
+    pub right: [f32; 3],
+    pub up: [f32; 3],
+    /// Vertical field of view in radians.
+    pub fov: f32,
+    /// Sub-pixel offset of the projection in NDC, for temporal anti-aliasing.
+    pub jitter: [f32; 2],
+}
+
+impl Camera {
+    /// Looks from `position` at `target`, with `roll` radians banking to the right.
+    pub fn look_at(position: [f32; 3], target: [f32; 3], roll: f32, fov: f32) -> Self {
+        let forward = normalize([0, 1, 2].map(|axis| target[axis] - position[axis]));
+        let mut right = cross([0.0, 1.0, 0.0], forward);
+        if dot(right, right) < 1e-6 {
+            // Straight up or down.
+            right = [1.0, 0.0, 0.0];
+        }
+        let right = normalize(right);
+        let up = cross(forward, right);
+
+        let (sin, cos) = roll.sin_cos();
+        Self {
+            position,
+            forward,
+            right: [0, 1, 2].map(|axis| right[axis] * cos - up[axis] * sin),
+            up: [0, 1, 2].map(|axis| up[axis] * cos + right[axis] * sin),
+            fov,
+            jitter: [0.0; 2],
+        }
+    }
+
+    /// World to camera space, x right, y up and z forward.
+    pub fn view(&self) -> Matrix {
+        let translation = |axis: [f32; 3]| -dot(axis, self.position);
+        let [r, u, f] = [self.right, self.up, self.forward];
+        [
+            [r[0], u[0], f[0], 0.0],
+            [r[1], u[1], f[1], 0.0],
+            [r[2], u[2], f[2], 0.0],
+            [translation(r), translation(u), translation(f), 1.0],
+        ]
+    }
+
+    /// Camera to world space, the inverse of `view`.
+    pub fn inverse_view(&self) -> Matrix {
+        let [r, u, f, p] = [self.right, self.up, self.forward, self.position];
+        [
+            [r[0], r[1], r[2], 0.0],
+            [u[0], u[1], u[2], 0.0],
+            [f[0], f[1], f[2], 0.0],
+            [p[0], p[1], p[2], 1.0],
+        ]
+    }
+
+    /// Camera space to clip space for a target of `aspect` width over height.
+    /// The jitter shifts x and y after the divide by z.
+    pub fn projection(&self, aspect: f32) -> Matrix {
+        let focal = 1.0 / (self.fov * 0.5).tan();
+        let [x, y] = self.jitter;
+        [
+            [focal / aspect, 0.0, 0.0, 0.0],
+            [0.0, focal, 0.0, 0.0],
+            [x, y, 0.0, 1.0],
+            [0.0, 0.0, NEAR, 0.0],
+        ]
+    }
+}
+
+pub fn multiply(a: Matrix, b: Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (column, b_column) in result.iter_mut().zip(b) {
+        for (row, value) in column.iter_mut().enumerate() {
+            *value = (0..4).map(|i| a[i][row] * b_column[i]).sum();
+        }
+    }
+    result
+}
+
+pub fn translation([x, y, z]: [f32; 3]) -> Matrix {
+    let mut matrix = IDENTITY;
+    matrix[3] = [x, y, z, 1.0];
+    matrix
+}
+
+/// Inverse transpose of the upper 3x3 of `model`, which keeps normals
+/// perpendicular to surfaces under non-uniform scales.
+pub fn normal_matrix(model: Matrix) -> Matrix {
+    let [x, y, z] = [0, 1, 2].map(|column| [model[column][0], model[column][1], model[column][2]]);
+    let determinant = dot(x, cross(y, z));
+    let mut matrix = IDENTITY;
+    for (column, axis) in matrix
+        .iter_mut()
+        .zip([cross(y, z), cross(z, x), cross(x, y)])
+    {
+        column[..3].copy_from_slice(&axis.map(|value| value / determinant));
+    }
+    matrix
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+// Left handed, cross(right, up) is forward.
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = dot(v, v).sqrt();
+    v.map(|component| component / length)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normal_matrix_undoes_non_uniform_scales() {
+        let mut stretched = IDENTITY;
+        stretched[0][0] = 4.0;
+        let rotated = Camera::look_at([0.0; 3], [0.6, 0.3, 0.8], 0.3, DEFAULT_FOV).view();
+        let model = multiply(rotated, stretched);
+        let normal = normal_matrix(model);
+        // A tangent of a surface and its normal stay perpendicular.
+        let tangent = [1.0, -1.0, 0.0];
+        let surface_normal = [1.0, 1.0, 0.0];
+        let transform = |matrix: Matrix, v: [f32; 3]| {
+            [0, 1, 2].map(|row| (0..3).map(|column| matrix[column][row] * v[column]).sum())
+        };
+        let product = dot(transform(model, tangent), transform(normal, surface_normal));
+        assert!(product.abs() < 1e-5, "{}", product);
+        // Rotations are their own inverse transpose.
+        for (a, b) in normal_matrix(rotated)
+            .iter()
+            .flatten()
+            .zip(rotated.iter().flatten())
+        {
+            assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+        }
+    }
+}
@@ -0,0 +1,272 @@
+// Camera moves keyed in time: position, target, field of view and roll,
+// interpolated along a spline. Effects declare a path in main.rs, a
+// camera.toml next to the binary replaces it and the debug camera can add keys
+// to that file.
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+use crate::camera::Camera;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Spline {
+    /// Passes through every key without stopping.
+    #[default]
+    CatmullRom,
+    /// Eases in and out of every key, positions follow the key handles.
+    Bezier,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Key {
+    /// Seconds into the demo.
+    pub time: f32,
+    pub position: [f32; 3],
+    pub target: [f32; 3],
+    /// Vertical field of view in degrees.
+    #[serde(default = "default_fov")]
+    pub fov: f32,
+    /// Degrees, banking to the right.
+    #[serde(default)]
+    pub roll: f32,
+    /// Bezier control point after the position, relative to it. The one
+    /// before is mirrored.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub handle: Option<[f32; 3]>,
+}
+
+fn default_fov() -> f32 {
+    crate::camera::DEFAULT_FOV.to_degrees()
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CameraPath {
+    #[serde(default)]
+    pub spline: Spline,
+    #[serde(rename = "key")]
+    keys: Vec<Key>,
+}
+
+impl CameraPath {
+    pub fn new(spline: Spline, mut keys: Vec<Key>) -> Self {
+        assert!(!keys.is_empty(), "camera paths need a key");
+        keys.sort_by(|a, b| a.time.total_cmp(&b.time));
+        Self { spline, keys }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let camera_path: CameraPath = toml::from_str(text)?;
+        ensure!(!camera_path.keys.is_empty(), "no camera keys");
+        let camera_path = Self::new(camera_path.spline, camera_path.keys);
+        // Two keys at the same time leave no span to interpolate over.
+        if let Some(pair) = camera_path
+            .keys
+            .windows(2)
+            .find(|pair| pair[0].time == pair[1].time)
+        {
+            bail!("two camera keys at {} seconds", pair[0].time);
+        }
+        Ok(camera_path)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        std::fs::write(path, toml::to_string(self)?)?;
+        Ok(())
+    }
+
+    /// Adds `key`, replacing a key at the same time.
+    pub fn insert(&mut self, key: Key) {
+        let index = self.keys.partition_point(|other| other.time < key.time);
+        match self.keys.get_mut(index) {
+            Some(other) if other.time == key.time => *other = key,
+            _ => self.keys.insert(index, key),
+        }
+    }
+
+    /// The camera at `time` seconds, holding the first and last keys outside of the path.
+    pub fn camera(&self, time: f32) -> Camera {
+        let keys = &self.keys;
+        let last = keys.len() - 1;
+        let key = if time <= keys[0].time {
+            keys[0].clone()
+        } else if time >= keys[last].time {
+            keys[last].clone()
+        } else {
+            let next = keys.partition_point(|key| key.time <= time);
+            self.interpolate(next - 1, time)
+        };
+        Camera::look_at(
+            key.position,
+            key.target,
+            key.roll.to_radians(),
+            key.fov.to_radians(),
+        )
+    }
+
+    // Cubic Hermite between keys `index` and `index + 1`, with tangents
+    // scaled to the length of the segment.
+    fn interpolate(&self, index: usize, time: f32) -> Key {
+        let (a, b) = (&self.keys[index], &self.keys[index + 1]);
+        let span = b.time - a.time;
+        let s = (time - a.time) / span;
+        let weights = [
+            2.0 * s * s * s - 3.0 * s * s + 1.0,
+            s * s * s - 2.0 * s * s + s,
+            -2.0 * s * s * s + 3.0 * s * s,
+            s * s * s - s * s,
+        ];
+
+        let channel = |value: fn(&Key) -> [f32; 3], handles: bool| -> [f32; 3] {
+            let (tangent_a, tangent_b) = match self.spline {
+                Spline::CatmullRom => (
+                    self.tangent(index, value).map(|v| v * span),
+                    self.tangent(index + 1, value).map(|v| v * span),
+                ),
+                // A handle a third of the way along is the same curve as a cubic Bezier.
+                Spline::Bezier if handles => (
+                    a.handle.unwrap_or_default().map(|v| v * 3.0),
+                    b.handle.unwrap_or_default().map(|v| v * 3.0),
+                ),
+                Spline::Bezier => ([0.0; 3], [0.0; 3]),
+            };
+            let points = [value(a), tangent_a, value(b), tangent_b];
+            [0, 1, 2].map(|axis| {
+                weights
+                    .iter()
+                    .zip(points)
+                    .map(|(weight, point)| weight * point[axis])
+                    .sum()
+            })
+        };
+
+        let [fov, roll, _] = channel(|key| [key.fov, key.roll, 0.0], false);
+        Key {
+            time,
+            position: channel(|key| key.position, true),
+            target: channel(|key| key.target, false),
+            fov,
+            roll,
+            handle: None,
+        }
+    }
+
+    // Catmull-Rom tangent per second at key `index`, one sided at the ends.
+    fn tangent(&self, index: usize, value: fn(&Key) -> [f32; 3]) -> [f32; 3] {
+        let previous = &self.keys[index.saturating_sub(1)];
+        let next = &self.keys[(index + 1).min(self.keys.len() - 1)];
+        let (from, to) = (value(previous), value(next));
+        let span = next.time - previous.time;
+        [0, 1, 2].map(|axis| (to[axis] - from[axis]) / span)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(time: f32, x: f32) -> Key {
+        Key {
+            time,
+            position: [x, 0.0, 0.0],
+            target: [x, 0.0, 1.0],
+            fov: 60.0,
+            roll: 0.0,
+            handle: None,
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn holds_the_end_keys() {
+        let path = CameraPath::new(Spline::CatmullRom, vec![key(1.0, 2.0), key(3.0, 5.0)]);
+        assert_close(path.camera(-10.0).position[0], 2.0);
+        assert_close(path.camera(1.0).position[0], 2.0);
+        assert_close(path.camera(3.0).position[0], 5.0);
+        assert_close(path.camera(100.0).position[0], 5.0);
+    }
+
+    #[test]
+    fn catmull_rom_passes_through_keys() {
+        let keys = vec![key(0.0, 0.0), key(1.0, 3.0), key(2.0, 1.0), key(4.0, 2.0)];
+        let path = CameraPath::new(Spline::CatmullRom, keys.clone());
+        for key in &keys {
+            assert_close(path.camera(key.time).position[0], key.position[0]);
+        }
+        // Evenly spaced keys on a line move at a constant speed.
+        let path = CameraPath::new(
+            Spline::CatmullRom,
+            vec![key(0.0, 0.0), key(1.0, 1.0), key(2.0, 2.0)],
+        );
+        assert_close(path.camera(0.5).position[0], 0.5);
+        assert_close(path.camera(1.25).position[0], 1.25);
+    }
+
+    #[test]
+    fn bezier_eases_without_handles() {
+        let path = CameraPath::new(Spline::Bezier, vec![key(0.0, 0.0), key(1.0, 1.0)]);
+        assert_close(path.camera(0.5).position[0], 0.5);
+        // Smoothstep, slow at both ends.
+        assert_close(path.camera(0.25).position[0], 0.15625);
+        assert_close(path.camera(0.75).position[0], 0.84375);
+    }
+
+    #[test]
+    fn interpolates_fov_and_roll() {
+        let mut end = key(1.0, 0.0);
+        end.fov = 90.0;
+        end.roll = 20.0;
+        let path = CameraPath::new(Spline::CatmullRom, vec![key(0.0, 0.0), end]);
+        let camera = path.camera(0.5);
+        assert_close(camera.fov, 75f32.to_radians());
+        // Banking right tilts the right axis down.
+        assert_close(camera.right[1], -(10f32.to_radians().sin()));
+    }
+
+    #[test]
+    fn keys_are_sorted_and_replaced() {
+        let mut path = CameraPath::new(Spline::CatmullRom, vec![key(2.0, 2.0), key(0.0, 0.0)]);
+        path.insert(key(1.0, 10.0));
+        path.insert(key(2.0, 4.0));
+        let times: Vec<f32> = path.keys.iter().map(|key| key.time).collect();
+        assert_eq!(times, [0.0, 1.0, 2.0]);
+        assert_close(path.camera(2.0).position[0], 4.0);
+    }
+
+    #[test]
+    fn rejects_keys_at_the_same_time() {
+        let text = r#"
+            [[key]]
+            time = 1.0
+            position = [0.0, 0.0, 0.0]
+            target = [0.0, 0.0, 1.0]
+
+            [[key]]
+            time = 1.0
+            position = [1.0, 0.0, 0.0]
+            target = [1.0, 0.0, 1.0]
+        "#;
+        let error = CameraPath::parse(text).unwrap_err();
+        assert_eq!(error.to_string(), "two camera keys at 1 seconds");
+        assert!(CameraPath::parse("spline = \"bezier\"").is_err());
+    }
+
+    #[test]
+    fn round_trips_through_toml() {
+        let mut handled = key(1.0, 1.0);
+        handled.handle = Some([0.0, 1.0, 0.0]);
+        let path = CameraPath::new(Spline::Bezier, vec![key(0.0, 0.0), handled]);
+        let text = toml::to_string(&path).unwrap();
+        assert_eq!(toml::from_str::<CameraPath>(&text).unwrap(), path);
+    }
+}
@@ -1,15 +1,22 @@
+use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Command {
     Play,
     Pause,
     PlayForward,
     PlayReverse,
     DebugDraw,
-    /*
-        IncreaseVolume,
-        DecreaseVolume,
-    */
+    IncreaseVolume,
+    DecreaseVolume,
+    ToggleMute,
+    ToggleDebugCamera,
+    AddCameraKey,
+    ToggleFullscreen,
+    ToggleTweaks,
+    LearnControl,
+    ReloadShaders,
     Quit,
 }
 
@@ -0,0 +1,691 @@
+// Startup options from the command line and demo.toml.
+//
+// Every option can be given in both places, the command line wins:
+//
+//     resolution = "1920x1080"
+//     fullscreen = true
+//     backend = "vulkan"
+//     present_mode = "mailbox"
+//     start = 12.5
+//
+// Run with --help for the full list.
+
+use anyhow::*;
+use clap::{Parser, ValueEnum};
+use serde::Deserialize;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use winit::monitor::MonitorHandle;
+use winit::window::Fullscreen;
+
+use crate::post;
+
+pub const CONFIG_PATH: &str = "demo.toml";
+pub const EFFECTS: &[&str] = &["not_menger"];
+pub const DEFAULT_MUSIC: &str = "music.mp3";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Resolution {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl FromStr for Resolution {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let (width, height) = text
+            .split_once('x')
+            .ok_or_else(|| anyhow!("expected WIDTHxHEIGHT, got '{}'", text))?;
+        let resolution = Self {
+            width: width.trim().parse()?,
+            height: height.trim().parse()?,
+        };
+        ensure!(
+            resolution.width > 0 && resolution.height > 0,
+            "resolution must not be zero"
+        );
+        Ok(resolution)
+    }
+}
+
+impl fmt::Display for Resolution {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}x{}", self.width, self.height)
+    }
+}
+
+impl<'de> Deserialize<'de> for Resolution {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Width over height like "16:9" or "2.39", or "free" to fill the window.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AspectRatio(pub Option<f32>);
+
+impl FromStr for AspectRatio {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        if text.trim() == "free" {
+            return Ok(Self(None));
+        }
+        let (width, height) = text.split_once(':').unwrap_or((text, "1"));
+        let ratio = width.trim().parse::<f32>()? / height.trim().parse::<f32>()?;
+        ensure!(
+            ratio.is_finite() && ratio > 0.0,
+            "invalid aspect ratio '{}'",
+            text
+        );
+        Ok(Self(Some(ratio)))
+    }
+}
+
+impl<'de> Deserialize<'de> for AspectRatio {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        text.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Backend {
+    /// Whatever the platform supports.
+    #[default]
+    Auto,
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl Backend {
+    pub fn backends(self) -> wgpu::Backends {
+        match self {
+            Backend::Auto => wgpu::Backends::all(),
+            Backend::Vulkan => wgpu::Backends::VULKAN,
+            Backend::Metal => wgpu::Backends::METAL,
+            Backend::Dx12 => wgpu::Backends::DX12,
+            Backend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+/// Curve taking the HDR scene to the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ToneMap {
+    /// Filmic, a fit of the ACES reference rendering and output transforms.
+    #[default]
+    Aces,
+    /// Soft roll-off, keeps the colors of the scene the most.
+    Reinhard,
+    /// Bright colors desaturate towards white instead of skewing in hue.
+    Agx,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PowerPreference {
+    #[default]
+    Default,
+    LowPower,
+    HighPerformance,
+}
+
+impl PowerPreference {
+    pub fn wgpu(self) -> wgpu::PowerPreference {
+        match self {
+            PowerPreference::Default => wgpu::PowerPreference::default(),
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum FullscreenMode {
+    /// A borderless window covering the monitor at its current video mode.
+    #[default]
+    Borderless,
+    /// Switches the monitor to the video mode closest to the resolution.
+    Exclusive,
+}
+
+impl FullscreenMode {
+    pub fn fullscreen(self, monitor: Option<MonitorHandle>, resolution: Resolution) -> Fullscreen {
+        let video_mode = monitor.as_ref().and_then(|monitor| {
+            monitor.video_modes().min_by_key(|mode| {
+                let size = mode.size();
+                let distance =
+                    size.width.abs_diff(resolution.width) + size.height.abs_diff(resolution.height);
+                (distance, std::cmp::Reverse(mode.refresh_rate_millihertz()))
+            })
+        });
+
+        match (self, video_mode) {
+            (FullscreenMode::Exclusive, Some(video_mode)) => Fullscreen::Exclusive(video_mode),
+            _ => Fullscreen::Borderless(monitor),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PresentMode {
+    /// Vsync, picking the best mode available.
+    #[default]
+    AutoVsync,
+    /// No vsync, picking the best mode available.
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Mailbox,
+    Immediate,
+}
+
+impl PresentMode {
+    pub fn wgpu(self) -> wgpu::PresentMode {
+        match self {
+            PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+        }
+    }
+
+    /// The requested mode if the surface supports it, otherwise the closest one that it does.
+    /// Without vsync that prefers another mode without tearing limits before falling back to
+    /// Fifo, which every surface supports.
+    pub fn select(self, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+        let preferences: &[wgpu::PresentMode] = match self {
+            PresentMode::AutoVsync => return wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => return wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => &[],
+            PresentMode::FifoRelaxed => &[wgpu::PresentMode::FifoRelaxed],
+            PresentMode::Mailbox => &[wgpu::PresentMode::Mailbox, wgpu::PresentMode::Immediate],
+            PresentMode::Immediate => &[wgpu::PresentMode::Immediate, wgpu::PresentMode::Mailbox],
+        };
+        preferences
+            .iter()
+            .copied()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or(wgpu::PresentMode::Fifo)
+    }
+}
+
+/// Options as given, unset ones fall back to the config file and then the defaults.
+#[derive(Debug, Default, Parser, Deserialize)]
+#[command(about = "Solar Assembly 2024 winner demo")]
+#[serde(default, deny_unknown_fields)]
+struct Options {
+    /// Config file, demo.toml is used when present.
+    #[arg(long)]
+    #[serde(skip)]
+    config: Option<PathBuf>,
+
+    /// Print the available adapters and exit.
+    #[arg(long)]
+    #[serde(skip)]
+    list_adapters: bool,
+
+    /// Show the setup dialog before the demo starts.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    setup: Option<bool>,
+
+    /// Window size or fullscreen resolution, like 1920x1080.
+    #[arg(long)]
+    resolution: Option<Resolution>,
+
+    /// Start in fullscreen, Alt+Enter or F11 toggle it.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    fullscreen: Option<bool>,
+
+    #[arg(long, value_enum)]
+    fullscreen_mode: Option<FullscreenMode>,
+
+    /// Design aspect ratio like 16:9, other window shapes get black bars. "free" fills the window.
+    #[arg(long)]
+    aspect: Option<AspectRatio>,
+
+    /// Size of the scene render targets relative to the window, like 0.5.
+    #[arg(long)]
+    render_scale: Option<f32>,
+
+    /// Lower the render scale when frames take too long, up to --render-scale.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    dynamic_resolution: Option<bool>,
+
+    /// Frame rate the dynamic resolution aims for.
+    #[arg(long)]
+    target_fps: Option<f32>,
+
+    #[arg(long, value_enum)]
+    tone_map: Option<ToneMap>,
+
+    /// Exposure in stops, applied before tone mapping.
+    #[arg(long, allow_negative_numbers = true)]
+    exposure: Option<f32>,
+
+    /// Temporal anti-aliasing, jitters the projection and blends with the last frames.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    taa: Option<bool>,
+
+    /// Samples per pixel for the scene, meshes and particles, falls back to what the adapter supports.
+    #[arg(long)]
+    msaa: Option<u32>,
+
+    /// Hide the mouse cursor while the demo runs, unless a debug view is open.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    hide_cursor: Option<bool>,
+
+    #[arg(long, value_enum)]
+    backend: Option<Backend>,
+
+    /// Adapter index from --list-adapters or part of its name.
+    #[arg(long)]
+    adapter: Option<String>,
+
+    /// Used to pick an adapter when none is given.
+    #[arg(long, value_enum)]
+    power_preference: Option<PowerPreference>,
+
+    /// Falls back to the closest supported mode.
+    #[arg(long, value_enum)]
+    present_mode: Option<PresentMode>,
+
+    /// Limit the frame rate, independent of the present mode.
+    #[arg(long)]
+    max_fps: Option<f32>,
+
+    /// Seconds into the demo to start at.
+    #[arg(long)]
+    start: Option<f32>,
+
+    /// Start over at the end instead of quitting.
+    #[arg(long = "loop", num_args = 0..=1, default_missing_value = "true")]
+    #[serde(rename = "loop")]
+    looping: Option<bool>,
+
+    /// Start with the music muted.
+    #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+    mute: Option<bool>,
+
+    /// Effect to run, not_menger is the only one so far.
+    #[arg(long)]
+    effect: Option<String>,
+
+    /// Soundtrack to play, or "synth" for the built-in synthesizer. Without one music.mp3 plays,
+    /// or the synth when there is none.
+    #[arg(long)]
+    music: Option<PathBuf>,
+
+    /// glTF scene to draw, its camera drives the demo camera when it has one.
+    #[arg(long)]
+    scene: Option<PathBuf>,
+
+    /// Render to numbered PNGs in this directory instead of playing, for encoding a video.
+    #[arg(long)]
+    offline: Option<PathBuf>,
+
+    /// Frame rate of the offline render.
+    #[arg(long)]
+    offline_fps: Option<f32>,
+
+    /// Jittered samples averaged into each frame of the offline render.
+    #[arg(long)]
+    offline_samples: Option<u32>,
+
+    /// Window title.
+    #[arg(long)]
+    title: Option<String>,
+
+    /// Post-processing chain replacing the effect's, only in the config file.
+    #[arg(skip)]
+    post: Option<Vec<post::Effect>>,
+}
+
+impl Options {
+    /// Fills options unset in `self` from `other`.
+    fn or(self, other: Options) -> Options {
+        Options {
+            config: self.config.or(other.config),
+            list_adapters: self.list_adapters || other.list_adapters,
+            setup: self.setup.or(other.setup),
+            resolution: self.resolution.or(other.resolution),
+            fullscreen: self.fullscreen.or(other.fullscreen),
+            fullscreen_mode: self.fullscreen_mode.or(other.fullscreen_mode),
+            aspect: self.aspect.or(other.aspect),
+            render_scale: self.render_scale.or(other.render_scale),
+            dynamic_resolution: self.dynamic_resolution.or(other.dynamic_resolution),
+            target_fps: self.target_fps.or(other.target_fps),
+            tone_map: self.tone_map.or(other.tone_map),
+            exposure: self.exposure.or(other.exposure),
+            taa: self.taa.or(other.taa),
+            msaa: self.msaa.or(other.msaa),
+            hide_cursor: self.hide_cursor.or(other.hide_cursor),
+            backend: self.backend.or(other.backend),
+            adapter: self.adapter.or(other.adapter),
+            power_preference: self.power_preference.or(other.power_preference),
+            present_mode: self.present_mode.or(other.present_mode),
+            max_fps: self.max_fps.or(other.max_fps),
+            start: self.start.or(other.start),
+            looping: self.looping.or(other.looping),
+            mute: self.mute.or(other.mute),
+            effect: self.effect.or(other.effect),
+            music: self.music.or(other.music),
+            scene: self.scene.or(other.scene),
+            offline: self.offline.or(other.offline),
+            offline_fps: self.offline_fps.or(other.offline_fps),
+            offline_samples: self.offline_samples.or(other.offline_samples),
+            title: self.title.or(other.title),
+            post: self.post.or(other.post),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub list_adapters: bool,
+    pub setup: bool,
+    pub resolution: Resolution,
+    pub fullscreen: bool,
+    pub fullscreen_mode: FullscreenMode,
+    /// Width over height, `None` fills the window.
+    pub aspect: Option<f32>,
+    pub render_scale: f32,
+    pub dynamic_resolution: bool,
+    pub target_fps: f32,
+    pub tone_map: ToneMap,
+    pub exposure: f32,
+    pub taa: bool,
+    pub msaa: u32,
+    pub hide_cursor: bool,
+    pub backend: Backend,
+    pub adapter: Option<String>,
+    pub power_preference: PowerPreference,
+    pub present_mode: PresentMode,
+    pub max_fps: Option<f32>,
+    pub start: f32,
+    pub looping: bool,
+    pub mute: bool,
+    pub effect: String,
+    pub music: Music,
+    pub scene: Option<PathBuf>,
+    /// Output directory of the offline render, `None` plays in real time.
+    pub offline: Option<PathBuf>,
+    pub offline_fps: f32,
+    pub offline_samples: u32,
+    pub title: String,
+    pub post: Option<Vec<post::Effect>>,
+}
+
+/// Where the soundtrack comes from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Music {
+    /// `DEFAULT_MUSIC` when there is one, the synth otherwise.
+    Default,
+    File(PathBuf),
+    /// The song from `soundtrack`, even when there is a file.
+    Synth,
+}
+
+impl Config {
+    /// Parses the command line and the config file, exits on `--help` and usage errors.
+    pub fn load() -> Result<Self> {
+        let arguments = Options::parse();
+
+        let path = arguments.config.clone();
+        let file = match &path {
+            Some(path) => read_options(path)?,
+            None if Path::new(CONFIG_PATH).exists() => read_options(CONFIG_PATH)?,
+            None => Options::default(),
+        };
+
+        Self::from_options(arguments.or(file))
+    }
+
+    fn from_options(options: Options) -> Result<Self> {
+        let effect = options.effect.unwrap_or_else(|| EFFECTS[0].to_string());
+        ensure!(
+            EFFECTS.contains(&effect.as_str()),
+            "unknown effect '{}', available: {}",
+            effect,
+            EFFECTS.join(", ")
+        );
+
+        let render_scale = options.render_scale.unwrap_or(1.0);
+        ensure!(
+            render_scale > 0.0 && render_scale <= 2.0,
+            "render scale must be above 0 and at most 2"
+        );
+        let target_fps = options.target_fps.unwrap_or(60.0);
+        ensure!(target_fps > 0.0, "target frame rate must be positive");
+
+        if let Some(max_fps) = options.max_fps {
+            ensure!(max_fps > 0.0, "maximum frame rate must be positive");
+        }
+
+        let start = options.start.unwrap_or(0.0);
+        ensure!(
+            start.is_finite() && start >= 0.0,
+            "start time must be a non-negative number of seconds"
+        );
+
+        let msaa = options.msaa.unwrap_or(1);
+        ensure!(
+            msaa.is_power_of_two() && msaa <= 16,
+            "MSAA sample count must be 1, 2, 4, 8 or 16"
+        );
+
+        let offline_fps = options.offline_fps.unwrap_or(60.0);
+        ensure!(offline_fps > 0.0, "offline frame rate must be positive");
+        let offline_samples = options.offline_samples.unwrap_or(16);
+        ensure!(
+            offline_samples > 0,
+            "offline render needs at least one sample"
+        );
+
+        Ok(Self {
+            list_adapters: options.list_adapters,
+            setup: options.setup.unwrap_or(false),
+            resolution: options.resolution.unwrap_or(Resolution {
+                width: 960,
+                height: 540,
+            }),
+            fullscreen: options.fullscreen.unwrap_or(false),
+            fullscreen_mode: options.fullscreen_mode.unwrap_or_default(),
+            aspect: options.aspect.map_or(Some(16.0 / 9.0), |aspect| aspect.0),
+            render_scale,
+            dynamic_resolution: options.dynamic_resolution.unwrap_or(false),
+            target_fps,
+            tone_map: options.tone_map.unwrap_or_default(),
+            exposure: options.exposure.unwrap_or(0.0),
+            taa: options.taa.unwrap_or(true),
+            msaa,
+            hide_cursor: options.hide_cursor.unwrap_or(true),
+            backend: options.backend.unwrap_or_default(),
+            adapter: options.adapter,
+            power_preference: options.power_preference.unwrap_or_default(),
+            present_mode: options.present_mode.unwrap_or_default(),
+            max_fps: options.max_fps,
+            start,
+            looping: options.looping.unwrap_or(false),
+            mute: options.mute.unwrap_or(false),
+            effect,
+            music: match options.music {
+                None => Music::Default,
+                Some(path) if path == Path::new("synth") => Music::Synth,
+                Some(path) => Music::File(path),
+            },
+            scene: options.scene,
+            offline: options.offline,
+            offline_fps,
+            offline_samples,
+            title: options
+                .title
+                .unwrap_or_else(|| "Solar Assembly 2024 Winner Demo".to_string()),
+            post: options.post,
+        })
+    }
+}
+
+fn read_options(path: impl AsRef<Path>) -> Result<Options> {
+    let path = path.as_ref();
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))
+}
+
+/// Picks the adapter from `--adapter`, or lets wgpu choose by power preference.
+pub fn select_adapter(
+    instance: &wgpu::Instance,
+    surface: &wgpu::Surface,
+    config: &Config,
+) -> Result<wgpu::Adapter> {
+    let Some(wanted) = &config.adapter else {
+        return pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: config.power_preference.wgpu(),
+            compatible_surface: Some(surface),
+            force_fallback_adapter: false,
+        }))
+        .ok_or_else(|| anyhow!("no compatible adapter"));
+    };
+
+    let adapters = instance.enumerate_adapters(config.backend.backends());
+    let index: Option<usize> = wanted.parse().ok();
+    let wanted_name = wanted.to_lowercase();
+    adapters
+        .enumerate()
+        .find(|(i, adapter)| {
+            let matches = match index {
+                Some(index) => *i == index,
+                None => adapter
+                    .get_info()
+                    .name
+                    .to_lowercase()
+                    .contains(&wanted_name),
+            };
+            matches && adapter.is_surface_supported(surface)
+        })
+        .map(|(_, adapter)| adapter)
+        .ok_or_else(|| anyhow!("no compatible adapter matching '{}'", wanted))
+}
+
+pub fn print_adapters(instance: &wgpu::Instance, config: &Config) {
+    for (i, adapter) in instance
+        .enumerate_adapters(config.backend.backends())
+        .enumerate()
+    {
+        let info = adapter.get_info();
+        println!(
+            "{}: {} ({:?}, {:?})",
+            i, info.name, info.backend, info.device_type
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn arguments(arguments: &[&str]) -> Options {
+        Options::try_parse_from(std::iter::once("demo").chain(arguments.iter().copied())).unwrap()
+    }
+
+    fn file(text: &str) -> Options {
+        toml::from_str(text).unwrap()
+    }
+
+    #[test]
+    fn command_line_overrides_the_file() {
+        let options = arguments(&["--msaa", "4", "--fullscreen"]).or(file(
+            r#"
+                msaa = 2
+                fullscreen = false
+                resolution = "1280x720"
+                loop = true
+            "#,
+        ));
+        let config = Config::from_options(options).unwrap();
+        assert_eq!(config.msaa, 4);
+        assert!(config.fullscreen);
+        assert_eq!(
+            config.resolution,
+            Resolution {
+                width: 1280,
+                height: 720
+            }
+        );
+        assert!(config.looping);
+    }
+
+    #[test]
+    fn defaults_fill_the_rest() {
+        let config = Config::from_options(Options::default()).unwrap();
+        assert_eq!(config.effect, EFFECTS[0]);
+        assert_eq!(config.resolution.to_string(), "960x540");
+        assert_eq!(config.aspect, Some(16.0 / 9.0));
+        assert_eq!(config.msaa, 1);
+        assert_eq!(config.start, 0.0);
+        assert!(config.taa && !config.fullscreen);
+        assert_eq!(config.music, Music::Default);
+    }
+
+    #[test]
+    fn music_can_be_a_file_or_the_synth() {
+        let config = Config::from_options(file(r#"music = "synth""#)).unwrap();
+        assert_eq!(config.music, Music::Synth);
+        let config = Config::from_options(arguments(&["--music", "song.ogg"])).unwrap();
+        assert_eq!(config.music, Music::File("song.ogg".into()));
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for text in [
+            "start = -1.0",
+            "start = inf",
+            "msaa = 3",
+            "render_scale = 0.0",
+            r#"effect = "missing""#,
+        ] {
+            assert!(Config::from_options(file(text)).is_err(), "{}", text);
+        }
+        assert!(toml::from_str::<Options>("unknown = 1").is_err());
+    }
+
+    #[test]
+    fn parses_resolutions_and_aspect_ratios() {
+        assert_eq!(
+            "1920x1080".parse::<Resolution>().unwrap(),
+            Resolution {
+                width: 1920,
+                height: 1080
+            }
+        );
+        for text in ["1920", "0x1080", "axb"] {
+            assert!(text.parse::<Resolution>().is_err(), "{}", text);
+        }
+
+        assert_eq!("free".parse::<AspectRatio>().unwrap(), AspectRatio(None));
+        assert_eq!(
+            "16:9".parse::<AspectRatio>().unwrap(),
+            AspectRatio(Some(16.0 / 9.0))
+        );
+        assert_eq!(
+            "2.39".parse::<AspectRatio>().unwrap(),
+            AspectRatio(Some(2.39))
+        );
+        for text in ["0:1", "16:0", "wide"] {
+            assert!(text.parse::<AspectRatio>().is_err(), "{}", text);
+        }
+    }
+}
@@ -0,0 +1,446 @@
+// MIDI controllers and gamepads.
+//
+// Every knob, fader, key, stick and button becomes a `Control` with a value
+// from 0 to 1. The keymap turns control events into commands and parameter
+// changes. With the `controllers` feature every MIDI input port and gamepad is
+// opened on startup, and on Linux and macOS a virtual MIDI input port is
+// created so other software can play the demo.
+
+// Without the `controllers` feature only the loopback port feeds events.
+#![cfg_attr(not(feature = "controllers"), allow(dead_code))]
+
+use anyhow::*;
+use std::fmt;
+use std::sync::mpsc::{channel, Receiver, Sender};
+
+#[cfg(feature = "controllers")]
+const CLIENT_NAME: &str = "wgpu-spielerei";
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Control {
+    /// MIDI channel from 1 to 16 and controller number.
+    MidiCc { channel: u8, number: u8 },
+    /// MIDI channel from 1 to 16 and note number.
+    MidiNote { channel: u8, note: u8 },
+    /// gilrs axis name like "LeftStickX".
+    GamepadAxis(String),
+    /// gilrs button name like "South".
+    GamepadButton(String),
+}
+
+impl Control {
+    /// Parses "cc:1:21", "note:10:36", "axis:LeftStickX" or "button:South".
+    pub fn parse(text: &str) -> Result<Self> {
+        let parts: Vec<&str> = text.split(':').map(str::trim).collect();
+        let midi = |channel: &str, number: &str| -> Result<(u8, u8)> {
+            let channel: u8 = channel.parse()?;
+            let number: u8 = number.parse()?;
+            ensure!((1..=16).contains(&channel), "channel must be 1 to 16");
+            ensure!(number < 128, "number must be below 128");
+            Ok((channel, number))
+        };
+
+        let control = match parts.as_slice() {
+            ["cc", channel, number] => {
+                let (channel, number) = midi(channel, number)?;
+                Control::MidiCc { channel, number }
+            }
+            ["note", channel, note] => {
+                let (channel, note) = midi(channel, note)?;
+                Control::MidiNote { channel, note }
+            }
+            ["axis", name] if !name.is_empty() => Control::GamepadAxis(name.to_string()),
+            ["button", name] if !name.is_empty() => Control::GamepadButton(name.to_string()),
+            _ => bail!("unknown control"),
+        };
+        Ok(control)
+    }
+}
+
+impl fmt::Display for Control {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Control::MidiCc { channel, number } => write!(f, "cc:{}:{}", channel, number),
+            Control::MidiNote { channel, note } => write!(f, "note:{}:{}", channel, note),
+            Control::GamepadAxis(name) => write!(f, "axis:{}", name),
+            Control::GamepadButton(name) => write!(f, "button:{}", name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct ControlEvent {
+    pub control: Control,
+    /// From 0 to 1. Axes rest at 0.5, released notes and buttons are 0.
+    pub value: f32,
+}
+
+/// Decodes control changes, note on and note off, other messages are ignored.
+pub fn parse_midi(message: &[u8]) -> Option<ControlEvent> {
+    let [status, data_1, data_2] = *message else {
+        return None;
+    };
+    let channel = (status & 0x0f) + 1;
+    let value = data_2 as f32 / 127.0;
+
+    let (control, value) = match status & 0xf0 {
+        0x80 => (
+            Control::MidiNote {
+                channel,
+                note: data_1,
+            },
+            0.0,
+        ),
+        0x90 => (
+            Control::MidiNote {
+                channel,
+                note: data_1,
+            },
+            value,
+        ),
+        0xb0 => (
+            Control::MidiCc {
+                channel,
+                number: data_1,
+            },
+            value,
+        ),
+        _ => return None,
+    };
+    Some(ControlEvent { control, value })
+}
+
+/// Sends raw MIDI messages as if they came from a connected port.
+#[derive(Clone)]
+pub struct MidiLoopback {
+    events: Sender<ControlEvent>,
+}
+
+impl MidiLoopback {
+    pub fn send(&self, message: &[u8]) {
+        if let Some(event) = parse_midi(message) {
+            // The receiver only goes away on shutdown.
+            let _ = self.events.send(event);
+        }
+    }
+}
+
+pub struct Controllers {
+    events: Receiver<ControlEvent>,
+    loopback: MidiLoopback,
+    #[cfg(feature = "controllers")]
+    midi_connections: Vec<midir::MidiInputConnection<()>>,
+    #[cfg(feature = "controllers")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Controllers {
+    /// Without any devices, only the loopback port sends events.
+    pub fn new() -> Self {
+        let (sender, events) = channel();
+        Self {
+            events,
+            loopback: MidiLoopback { events: sender },
+            #[cfg(feature = "controllers")]
+            midi_connections: Vec::new(),
+            #[cfg(feature = "controllers")]
+            gilrs: None,
+        }
+    }
+
+    /// Opens every MIDI input port and gamepad, failures are reported and skipped.
+    pub fn connect() -> Self {
+        #[allow(unused_mut)]
+        let mut controllers = Self::new();
+
+        #[cfg(feature = "controllers")]
+        {
+            if let Err(error) = controllers.connect_midi() {
+                eprintln!("MIDI unavailable: {:#}", error);
+            }
+            #[cfg(unix)]
+            if let Err(error) = controllers.open_virtual_port(CLIENT_NAME) {
+                eprintln!("No virtual MIDI port: {:#}", error);
+            }
+            match gilrs::Gilrs::new() {
+                Result::Ok(gilrs) => controllers.gilrs = Some(gilrs),
+                Err(error) => eprintln!("Gamepads unavailable: {}", error),
+            }
+        }
+
+        controllers
+    }
+
+    #[cfg(feature = "controllers")]
+    fn connect_midi(&mut self) -> Result<()> {
+        let ports = midir::MidiInput::new(CLIENT_NAME)?.ports();
+        for port in ports {
+            let input = midir::MidiInput::new(CLIENT_NAME)?;
+            let name = input.port_name(&port)?;
+            let loopback = self.loopback.clone();
+            match input.connect(
+                &port,
+                CLIENT_NAME,
+                move |_, message, _| loopback.send(message),
+                (),
+            ) {
+                Result::Ok(connection) => {
+                    println!("Connected MIDI input '{}'", name);
+                    self.midi_connections.push(connection);
+                }
+                Err(error) => eprintln!("Could not open MIDI input '{}': {}", name, error),
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates a MIDI input port other programs can connect to.
+    #[cfg(all(feature = "controllers", unix))]
+    pub fn open_virtual_port(&mut self, name: &str) -> Result<()> {
+        use midir::os::unix::VirtualInput;
+
+        let loopback = self.loopback.clone();
+        let connection = midir::MidiInput::new(CLIENT_NAME)?
+            .create_virtual(name, move |_, message, _| loopback.send(message), ())
+            .map_err(|error| anyhow!("{}", error))?;
+        self.midi_connections.push(connection);
+        Ok(())
+    }
+
+    /// Port that feeds raw MIDI messages straight into `poll`.
+    #[cfg(test)]
+    pub fn loopback(&self) -> MidiLoopback {
+        self.loopback.clone()
+    }
+
+    /// Returns the events received since the last call.
+    pub fn poll(&mut self) -> Vec<ControlEvent> {
+        #[allow(unused_mut)]
+        let mut events: Vec<ControlEvent> = self.events.try_iter().collect();
+
+        #[cfg(feature = "controllers")]
+        if let Some(gilrs) = &mut self.gilrs {
+            while let Some(gilrs::Event { event, .. }) = gilrs.next_event() {
+                match event {
+                    gilrs::EventType::AxisChanged(axis, value, _) => events.push(ControlEvent {
+                        control: Control::GamepadAxis(format!("{:?}", axis)),
+                        value: (value + 1.0) * 0.5,
+                    }),
+                    gilrs::EventType::ButtonChanged(button, value, _) => {
+                        events.push(ControlEvent {
+                            control: Control::GamepadButton(format!("{:?}", button)),
+                            value,
+                        })
+                    }
+                    _ => (),
+                }
+            }
+        }
+
+        events
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commandbuffer::Command;
+    use crate::keymap::{ControlAction, Keymap};
+
+    const KEYMAP: &str = r#"
+        [[bindings]]
+        command = "ToggleMute"
+        keys = ["M"]
+        controls = ["note:1:36", "button:South"]
+
+        [[bindings]]
+        command = "IncreaseVolume"
+        controls = ["cc:2:64"]
+        trigger = "press"
+
+        [[parameters]]
+        parameter = "scale"
+        control = "cc:1:21"
+    "#;
+
+    fn actions(keymap: &mut Keymap, controllers: &mut Controllers) -> Vec<ControlAction> {
+        controllers
+            .poll()
+            .iter()
+            .flat_map(|event| keymap.handle_control(event))
+            .collect()
+    }
+
+    #[test]
+    fn parses_controls() {
+        for text in ["cc:1:21", "note:16:127", "axis:LeftStickX", "button:South"] {
+            assert_eq!(Control::parse(text).unwrap().to_string(), text);
+        }
+        for text in ["cc:0:21", "cc:1:128", "note:1", "key:A", "axis:"] {
+            assert!(Control::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parses_midi_messages() {
+        let cc = parse_midi(&[0xb1, 21, 127]).unwrap();
+        assert_eq!(
+            cc.control,
+            Control::MidiCc {
+                channel: 2,
+                number: 21
+            }
+        );
+        assert_eq!(cc.value, 1.0);
+
+        let note_off = parse_midi(&[0x80, 36, 64]).unwrap();
+        assert_eq!(
+            note_off.control,
+            Control::MidiNote {
+                channel: 1,
+                note: 36
+            }
+        );
+        assert_eq!(note_off.value, 0.0);
+
+        // Program change and clock.
+        assert_eq!(parse_midi(&[0xc0, 5]), None);
+        assert_eq!(parse_midi(&[0xf8]), None);
+    }
+
+    #[test]
+    fn loopback_drives_commands_and_parameters() {
+        let mut keymap = Keymap::parse(KEYMAP).unwrap();
+        let mut controllers = Controllers::new();
+        let port = controllers.loopback();
+
+        port.send(&[0xb0, 21, 127]);
+        port.send(&[0x90, 36, 100]);
+        assert_eq!(
+            actions(&mut keymap, &mut controllers),
+            [ControlAction::SetParameter {
+                name: "scale".to_string(),
+                value: 1.0
+            }]
+        );
+
+        // Commands fire on release by default.
+        port.send(&[0x80, 36, 0]);
+        assert_eq!(
+            actions(&mut keymap, &mut controllers),
+            [ControlAction::Command(Command::ToggleMute)]
+        );
+
+        // Press triggers fire once when crossing half way.
+        port.send(&[0xb1, 64, 100]);
+        port.send(&[0xb1, 64, 127]);
+        port.send(&[0xb1, 64, 0]);
+        assert_eq!(
+            actions(&mut keymap, &mut controllers),
+            [ControlAction::Command(Command::IncreaseVolume)]
+        );
+
+        // Other channels are not bound.
+        port.send(&[0xb5, 21, 127]);
+        assert_eq!(actions(&mut keymap, &mut controllers), []);
+    }
+
+    #[test]
+    fn learn_binds_the_next_control() {
+        let mut keymap = Keymap::parse(KEYMAP).unwrap();
+        let mut controllers = Controllers::new();
+        let port = controllers.loopback();
+
+        keymap.learn(Some("sheen".to_string()));
+        port.send(&[0xb3, 7, 90]);
+        assert_eq!(
+            actions(&mut keymap, &mut controllers),
+            [ControlAction::Learned {
+                name: "sheen".to_string(),
+                control: Control::MidiCc {
+                    channel: 4,
+                    number: 7
+                }
+            }]
+        );
+        assert_eq!(keymap.learning(), None);
+
+        port.send(&[0xb3, 7, 127]);
+        assert_eq!(
+            actions(&mut keymap, &mut controllers),
+            [ControlAction::SetParameter {
+                name: "sheen".to_string(),
+                value: 1.0
+            }]
+        );
+
+        // Learning moves a control that was already bound.
+        keymap.learn(Some("steps".to_string()));
+        port.send(&[0xb3, 7, 0]);
+        port.send(&[0xb3, 7, 127]);
+        assert_eq!(
+            actions(&mut keymap, &mut controllers)[1],
+            ControlAction::SetParameter {
+                name: "steps".to_string(),
+                value: 1.0
+            }
+        );
+
+        // The learned binding survives saving.
+        let text = keymap.to_toml().unwrap();
+        let mut reloaded = Keymap::parse(&text).unwrap();
+        port.send(&[0xb3, 7, 0]);
+        assert_eq!(
+            actions(&mut reloaded, &mut controllers),
+            [ControlAction::SetParameter {
+                name: "steps".to_string(),
+                value: 0.0
+            }]
+        );
+    }
+
+    // Needs a running MIDI system, which containers often lack (ALSA without
+    // /dev/snd/seq, for example). Run with `cargo test -- --ignored`.
+    #[cfg(all(feature = "controllers", unix))]
+    #[test]
+    #[ignore = "needs a MIDI system"]
+    fn virtual_port_receives_midi() {
+        use std::time::{Duration, Instant};
+
+        let mut controllers = Controllers::new();
+        controllers
+            .open_virtual_port("wgpu-spielerei test")
+            .expect("MIDI system is available");
+
+        let output = midir::MidiOutput::new("wgpu-spielerei test output").unwrap();
+        let port = output
+            .ports()
+            .into_iter()
+            .find(|port| {
+                output
+                    .port_name(port)
+                    .unwrap()
+                    .contains("wgpu-spielerei test")
+            })
+            .expect("virtual port is listed");
+        let mut connection = output.connect(&port, "test").unwrap();
+        connection.send(&[0xb0, 21, 127]).unwrap();
+
+        let deadline = Instant::now() + Duration::from_secs(2);
+        let mut events = Vec::new();
+        while events.is_empty() && Instant::now() < deadline {
+            events = controllers.poll();
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(
+            events,
+            [ControlEvent {
+                control: Control::MidiCc {
+                    channel: 1,
+                    number: 21
+                },
+                value: 1.0
+            }]
+        );
+    }
+}
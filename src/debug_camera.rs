@@ -0,0 +1,110 @@
+// Free-fly camera for looking around scenes while developing.
+//
+// WASD moves, Q/E go down/up, Shift moves faster, dragging with the right
+// mouse button looks around and the scroll wheel changes the speed.
+
+use std::collections::HashSet;
+use winit::event::{ElementState, KeyboardInput, VirtualKeyCode};
+
+const LOOK_SENSITIVITY: f32 = 0.004;
+const FAST_MULTIPLIER: f32 = 4.0;
+
+pub struct DebugCamera {
+    pub enabled: bool,
+    pub position: [f32; 3],
+    // Position before the last update, for interpolating between updates.
+    previous_position: [f32; 3],
+    yaw: f32,
+    pitch: f32,
+    // Units per second.
+    speed: f32,
+    held: HashSet<VirtualKeyCode>,
+}
+
+impl DebugCamera {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            position: [0.0, 0.0, 0.0],
+            previous_position: [0.0, 0.0, 0.0],
+            yaw: 0.0,
+            pitch: 0.0,
+            speed: 0.5,
+            held: HashSet::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.enabled = !self.enabled;
+        self.held.clear();
+    }
+
+    pub fn key(&mut self, input: KeyboardInput) {
+        let Some(key) = input.virtual_keycode else {
+            return;
+        };
+        match input.state {
+            ElementState::Pressed => self.held.insert(key),
+            ElementState::Released => self.held.remove(&key),
+        };
+    }
+
+    /// Rotates by a mouse movement in pixels.
+    pub fn look(&mut self, delta: [f32; 2]) {
+        let limit = std::f32::consts::FRAC_PI_2 - 0.01;
+        self.yaw += delta[0] * LOOK_SENSITIVITY;
+        self.pitch = (self.pitch - delta[1] * LOOK_SENSITIVITY).clamp(-limit, limit);
+    }
+
+    /// Position `alpha` of the way from the previous update to the last one.
+    pub fn interpolated_position(&self, alpha: f32) -> [f32; 3] {
+        let [a, b] = [self.previous_position, self.position];
+        [0, 1, 2].map(|axis| a[axis] + (b[axis] - a[axis]) * alpha)
+    }
+
+    pub fn scroll(&mut self, lines: f32) {
+        self.speed = (self.speed * 1.2f32.powf(lines)).clamp(0.01, 100.0);
+    }
+
+    pub fn update(&mut self, seconds: f32) {
+        self.previous_position = self.position;
+        if !self.enabled {
+            return;
+        }
+
+        let held = |key| self.held.contains(&key);
+        let axis = |positive, negative| (held(positive) as i32 - held(negative) as i32) as f32;
+        let forward_amount = axis(VirtualKeyCode::W, VirtualKeyCode::S);
+        let right_amount = axis(VirtualKeyCode::D, VirtualKeyCode::A);
+        let up_amount = axis(VirtualKeyCode::E, VirtualKeyCode::Q);
+
+        let mut distance = self.speed * seconds;
+        if held(VirtualKeyCode::LShift) || held(VirtualKeyCode::RShift) {
+            distance *= FAST_MULTIPLIER;
+        }
+
+        let (forward, right, up) = self.basis();
+        for axis in 0..3 {
+            self.position[axis] += (forward[axis] * forward_amount
+                + right[axis] * right_amount
+                + up[axis] * up_amount)
+                * distance;
+        }
+    }
+
+    /// Forward, right and up vectors, looking down +z at zero yaw and pitch.
+    pub fn basis(&self) -> ([f32; 3], [f32; 3], [f32; 3]) {
+        let (sin_yaw, cos_yaw) = self.yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = self.pitch.sin_cos();
+
+        let forward = [sin_yaw * cos_pitch, sin_pitch, cos_yaw * cos_pitch];
+        let right = [cos_yaw, 0.0, -sin_yaw];
+        // up = forward x right
+        let up = [
+            forward[1] * right[2] - forward[2] * right[1],
+            forward[2] * right[0] - forward[0] * right[2],
+            forward[0] * right[1] - forward[1] * right[0],
+        ];
+        (forward, right, up)
+    }
+}
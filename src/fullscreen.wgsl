@@ -0,0 +1,18 @@
+// Fullscreen triangle for the passes that work on whole render targets, put
+// in front of their shaders.
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    // Texture space, v grows downwards.
+    @location(0) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32) -> VertexOutput {
+    var out: VertexOutput;
+    let u = f32((vertex << 1u) & 2u);
+    let v = f32(vertex & 2u);
+    out.position = vec4<f32>(u * 2.0 - 1.0, v * 2.0 - 1.0, 0.0, 1.0);
+    out.uv = vec2<f32>(u, 1.0 - v);
+    return out;
+}
@@ -0,0 +1,105 @@
+// Mouse state as seen by the shaders.
+
+use winit::dpi::PhysicalPosition;
+use winit::event::{ElementState, MouseButton, MouseScrollDelta};
+
+use crate::targets::Viewport;
+
+pub const LEFT_BUTTON: u32 = 1;
+pub const RIGHT_BUTTON: u32 = 2;
+pub const MIDDLE_BUTTON: u32 = 4;
+
+// Pixel deltas from touchpads are converted to roughly one line per notch.
+const PIXELS_PER_LINE: f32 = 20.0;
+
+#[derive(Default)]
+pub struct MouseState {
+    /// Pixels from the top left of the window.
+    pub position: [f32; 2],
+    /// Position of the last press of the left button.
+    pub click_position: [f32; 2],
+    /// Bitmask of `LEFT_BUTTON`, `RIGHT_BUTTON` and `MIDDLE_BUTTON`.
+    pub buttons: u32,
+    /// Lines scrolled since startup, up is positive.
+    pub scroll: f32,
+    // Position while the left button was last held, like Shadertoy's iMouse.xy.
+    drag_position: [f32; 2],
+    clicked: bool,
+}
+
+impl MouseState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cursor_moved(&mut self, position: PhysicalPosition<f64>) {
+        self.position = [position.x as f32, position.y as f32];
+        if self.buttons & LEFT_BUTTON != 0 {
+            self.drag_position = self.position;
+        }
+    }
+
+    pub fn button(&mut self, button: MouseButton, state: ElementState) {
+        let mask = match button {
+            MouseButton::Left => LEFT_BUTTON,
+            MouseButton::Right => RIGHT_BUTTON,
+            MouseButton::Middle => MIDDLE_BUTTON,
+            MouseButton::Other(_) => return,
+        };
+
+        match state {
+            ElementState::Pressed => {
+                self.buttons |= mask;
+                if mask == LEFT_BUTTON {
+                    self.click_position = self.position;
+                    self.drag_position = self.position;
+                    self.clicked = true;
+                }
+            }
+            ElementState::Released => self.buttons &= !mask,
+        }
+    }
+
+    /// Returns the scrolled amount in lines.
+    pub fn wheel(&mut self, delta: MouseScrollDelta) -> f32 {
+        let lines = match delta {
+            MouseScrollDelta::LineDelta(_, y) => y,
+            MouseScrollDelta::PixelDelta(position) => position.y as f32 / PIXELS_PER_LINE,
+        };
+        self.scroll += lines;
+        lines
+    }
+
+    /// Shadertoy's iMouse: pixels from the bottom left of the viewport, xy is
+    /// the drag position, zw the click position, z is negative while the button
+    /// is up and w is only positive on the frame of the click.
+    pub fn shadertoy(&self, viewport: &Viewport) -> [f32; 4] {
+        let flip = |p: [f32; 2]| [p[0] - viewport.x, viewport.height - (p[1] - viewport.y)];
+        let drag = flip(self.drag_position);
+        let click = flip(self.click_position);
+
+        let down = self.buttons & LEFT_BUTTON != 0;
+        [
+            drag[0],
+            drag[1],
+            if down { click[0] } else { -click[0] },
+            if self.clicked { click[1] } else { -click[1] },
+        ]
+    }
+
+    /// Normalized position with 0,0 at the bottom left of the viewport, the
+    /// button mask and the scroll.
+    pub fn cursor(&self, viewport: &Viewport) -> [f32; 4] {
+        [
+            (self.position[0] - viewport.x) / viewport.width,
+            1.0 - (self.position[1] - viewport.y) / viewport.height,
+            self.buttons as f32,
+            self.scroll,
+        ]
+    }
+
+    /// Call once the current state has been uploaded.
+    pub fn end_frame(&mut self) {
+        self.clicked = false;
+    }
+}
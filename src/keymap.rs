@@ -0,0 +1,521 @@
+// Key bindings, loaded from keymap.toml when present.
+//
+// Every binding maps one or more key combinations to a command:
+//
+//     [[bindings]]
+//     command = "IncreaseVolume"
+//     keys = ["Up", "Ctrl+K"]
+//     trigger = "repeat"
+//
+// A command listed in the file replaces all of its default keys, the commands
+// it doesn't list keep theirs.
+//
+// Keys use winit's `VirtualKeyCode` names, modifiers are Shift, Ctrl, Alt and
+// Logo. The trigger is "press", "release" (the default) or "repeat", which
+// fires on press and again for every key repeat while held.
+//
+// Bindings can also list MIDI and gamepad controls (see `Control::parse`),
+// which count as pressed above half way. Controls can set parameters too:
+//
+//     [[bindings]]
+//     command = "ToggleMute"
+//     controls = ["note:10:36", "button:South"]
+//
+//     [[parameters]]
+//     parameter = "scale"
+//     control = "cc:1:21"
+
+use anyhow::*;
+use serde::de::IntoDeserializer;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use winit::event::{ElementState, KeyboardInput, ModifiersState, VirtualKeyCode};
+
+use crate::commandbuffer::Command;
+use crate::controller::{Control, ControlEvent};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    Press,
+    #[default]
+    Release,
+    Repeat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct KeyCombination {
+    pub key: VirtualKeyCode,
+    pub modifiers: ModifiersState,
+}
+
+impl KeyCombination {
+    pub fn parse(text: &str) -> Result<Self> {
+        let mut parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let key = parts.pop().filter(|key| !key.is_empty());
+        let key = key.ok_or_else(|| anyhow!("missing key in '{}'", text))?;
+
+        let mut modifiers = ModifiersState::empty();
+        for modifier in parts {
+            modifiers |= match modifier.to_ascii_lowercase().as_str() {
+                "shift" => ModifiersState::SHIFT,
+                "ctrl" | "control" => ModifiersState::CTRL,
+                "alt" => ModifiersState::ALT,
+                "logo" | "super" | "cmd" => ModifiersState::LOGO,
+                _ => bail!("unknown modifier '{}' in '{}'", modifier, text),
+            };
+        }
+
+        let key = VirtualKeyCode::deserialize(key.into_deserializer())
+            .map_err(|_: serde::de::value::Error| anyhow!("unknown key '{}' in '{}'", key, text))?;
+
+        Ok(Self { key, modifiers })
+    }
+}
+
+impl fmt::Display for KeyCombination {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (modifier, name) in [
+            (ModifiersState::SHIFT, "Shift"),
+            (ModifiersState::CTRL, "Ctrl"),
+            (ModifiersState::ALT, "Alt"),
+            (ModifiersState::LOGO, "Logo"),
+        ] {
+            if self.modifiers.contains(modifier) {
+                write!(f, "{}+", name)?;
+            }
+        }
+        match toml::Value::try_from(self.key) {
+            Result::Ok(toml::Value::String(key)) => write!(f, "{}", key),
+            _ => Err(fmt::Error),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Binding {
+    pub keys: Vec<KeyCombination>,
+    pub controls: Vec<Control>,
+    pub trigger: Trigger,
+    pub command: Command,
+}
+
+#[derive(Clone, Debug)]
+pub struct ParameterBinding {
+    pub parameter: String,
+    pub control: Control,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum ControlAction {
+    Command(Command),
+    /// From 0 to 1 across the parameter's range.
+    SetParameter {
+        name: String,
+        value: f32,
+    },
+    /// Learn mode bound a control to the parameter.
+    Learned {
+        name: String,
+        control: Control,
+    },
+}
+
+#[derive(Serialize, Deserialize)]
+struct BindingFile {
+    command: Command,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    keys: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    controls: Vec<String>,
+    #[serde(default)]
+    trigger: Trigger,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ParameterBindingFile {
+    parameter: String,
+    control: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct KeymapFile {
+    #[serde(default)]
+    bindings: Vec<BindingFile>,
+    #[serde(default)]
+    parameters: Vec<ParameterBindingFile>,
+}
+
+pub struct Keymap {
+    bindings: Vec<Binding>,
+    parameters: Vec<ParameterBinding>,
+    modifiers: ModifiersState,
+    held: HashSet<VirtualKeyCode>,
+    pressed_controls: HashSet<Control>,
+    learning: Option<String>,
+    // The file these came from failed to load, saving would replace it.
+    load_failed: bool,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let binding = |key, command| Binding {
+            keys: vec![KeyCombination {
+                key,
+                modifiers: ModifiersState::empty(),
+            }],
+            controls: Vec::new(),
+            trigger: Trigger::Release,
+            command,
+        };
+
+        Self::new(vec![
+            binding(VirtualKeyCode::Escape, Command::Quit),
+            binding(VirtualKeyCode::J, Command::PlayReverse),
+            binding(VirtualKeyCode::K, Command::Pause),
+            binding(VirtualKeyCode::L, Command::PlayForward),
+            binding(VirtualKeyCode::Space, Command::Play),
+            binding(VirtualKeyCode::Grave, Command::DebugDraw),
+            binding(VirtualKeyCode::Up, Command::IncreaseVolume),
+            binding(VirtualKeyCode::Down, Command::DecreaseVolume),
+            binding(VirtualKeyCode::M, Command::ToggleMute),
+            binding(VirtualKeyCode::F1, Command::ToggleTweaks),
+            binding(VirtualKeyCode::F2, Command::ToggleDebugCamera),
+            binding(VirtualKeyCode::F3, Command::LearnControl),
+            binding(VirtualKeyCode::F4, Command::AddCameraKey),
+            binding(VirtualKeyCode::F5, Command::ReloadShaders),
+            binding(VirtualKeyCode::F11, Command::ToggleFullscreen),
+            Binding {
+                keys: vec![KeyCombination {
+                    key: VirtualKeyCode::Return,
+                    modifiers: ModifiersState::ALT,
+                }],
+                controls: Vec::new(),
+                trigger: Trigger::Press,
+                command: Command::ToggleFullscreen,
+            },
+        ])
+    }
+}
+
+impl Keymap {
+    pub fn new(bindings: Vec<Binding>) -> Self {
+        Self {
+            bindings,
+            parameters: Vec::new(),
+            modifiers: ModifiersState::empty(),
+            held: HashSet::new(),
+            pressed_controls: HashSet::new(),
+            learning: None,
+            load_failed: false,
+        }
+    }
+
+    /// Bindings in `text` replace the default ones of the same command.
+    pub fn parse(text: &str) -> Result<Self> {
+        let file: KeymapFile = toml::from_str(text)?;
+
+        let bindings: Vec<Binding> = file
+            .bindings
+            .into_iter()
+            .map(|binding| {
+                Ok(Binding {
+                    keys: binding
+                        .keys
+                        .iter()
+                        .map(|key| KeyCombination::parse(key))
+                        .collect::<Result<_>>()?,
+                    controls: binding
+                        .controls
+                        .iter()
+                        .map(|control| parse_control(control))
+                        .collect::<Result<_>>()?,
+                    trigger: binding.trigger,
+                    command: binding.command,
+                })
+            })
+            .collect::<Result<_>>()?;
+        let mut merged: Vec<Binding> = Self::default()
+            .bindings
+            .into_iter()
+            .filter(|default| {
+                !bindings
+                    .iter()
+                    .any(|binding| binding.command == default.command)
+            })
+            .collect();
+        merged.extend(bindings);
+
+        let mut keymap = Self::new(merged);
+        keymap.parameters = file
+            .parameters
+            .into_iter()
+            .map(|binding| {
+                Ok(ParameterBinding {
+                    control: parse_control(&binding.control)?,
+                    parameter: binding.parameter,
+                })
+            })
+            .collect::<Result<_>>()?;
+        Ok(keymap)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        Self::parse(&text).with_context(|| format!("parsing {}", path.display()))
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        let file = KeymapFile {
+            bindings: self
+                .bindings
+                .iter()
+                .map(|binding| BindingFile {
+                    command: binding.command,
+                    keys: binding.keys.iter().map(|key| key.to_string()).collect(),
+                    controls: binding.controls.iter().map(|c| c.to_string()).collect(),
+                    trigger: binding.trigger,
+                })
+                .collect(),
+            parameters: self
+                .parameters
+                .iter()
+                .map(|binding| ParameterBindingFile {
+                    parameter: binding.parameter.clone(),
+                    control: binding.control.to_string(),
+                })
+                .collect(),
+        };
+        Ok(toml::to_string(&file)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        ensure!(
+            !self.load_failed,
+            "{} failed to load, not overwriting it",
+            path.display()
+        );
+        std::fs::write(path, self.to_toml()?)?;
+        Ok(())
+    }
+
+    /// Loads `path` if it exists, falling back to the default bindings.
+    pub fn load_or_default(path: impl AsRef<Path>) -> Self {
+        let path = path.as_ref();
+        if !path.exists() {
+            return Self::default();
+        }
+        Self::load(path).unwrap_or_else(|error| {
+            eprintln!("Using default key bindings: {:#}", error);
+            Self {
+                load_failed: true,
+                ..Self::default()
+            }
+        })
+    }
+
+    pub fn set_modifiers(&mut self, modifiers: ModifiersState) {
+        self.modifiers = modifiers;
+    }
+
+    /// Forgets the held keys, their releases go to whichever window has focus.
+    pub fn focus_lost(&mut self) {
+        self.held.clear();
+        self.modifiers = ModifiersState::empty();
+    }
+
+    /// Returns the commands bound to this key event.
+    pub fn handle(&mut self, input: KeyboardInput) -> Vec<Command> {
+        let Some(key) = input.virtual_keycode else {
+            return Vec::new();
+        };
+
+        // winit reports key repeat as more presses without a release in between.
+        let fired = match input.state {
+            ElementState::Pressed if self.held.insert(key) => [Trigger::Press, Trigger::Repeat],
+            ElementState::Pressed => [Trigger::Repeat, Trigger::Repeat],
+            ElementState::Released => {
+                self.held.remove(&key);
+                [Trigger::Release, Trigger::Release]
+            }
+        };
+
+        let combination = KeyCombination {
+            key,
+            modifiers: self.modifiers,
+        };
+        self.bindings
+            .iter()
+            .filter(|binding| {
+                fired.contains(&binding.trigger) && binding.keys.contains(&combination)
+            })
+            .map(|binding| binding.command)
+            .collect()
+    }
+
+    /// Binds the next control that moves to `parameter`, `None` stops learning.
+    pub fn learn(&mut self, parameter: Option<String>) {
+        self.learning = parameter;
+    }
+
+    pub fn learning(&self) -> Option<&str> {
+        self.learning.as_deref()
+    }
+
+    pub fn handle_control(&mut self, event: &ControlEvent) -> Vec<ControlAction> {
+        if let Some(name) = self.learning.take() {
+            // Sticks and buttons report small changes while at rest.
+            let deliberate = match event.control {
+                Control::GamepadAxis(_) => (event.value - 0.5).abs() > 0.25,
+                Control::GamepadButton(_) => event.value > 0.5,
+                _ => true,
+            };
+            if deliberate {
+                self.parameters
+                    .retain(|binding| binding.control != event.control);
+                self.parameters.push(ParameterBinding {
+                    parameter: name.clone(),
+                    control: event.control.clone(),
+                });
+                return vec![ControlAction::Learned {
+                    name,
+                    control: event.control.clone(),
+                }];
+            }
+            self.learning = Some(name);
+        }
+
+        let pressed = event.value >= 0.5;
+        let fired: &[Trigger] = if pressed && self.pressed_controls.insert(event.control.clone()) {
+            &[Trigger::Press, Trigger::Repeat]
+        } else if !pressed && self.pressed_controls.remove(&event.control) {
+            &[Trigger::Release]
+        } else {
+            &[]
+        };
+
+        let commands = self
+            .bindings
+            .iter()
+            .filter(|binding| {
+                fired.contains(&binding.trigger) && binding.controls.contains(&event.control)
+            })
+            .map(|binding| ControlAction::Command(binding.command));
+        let parameters = self
+            .parameters
+            .iter()
+            .filter(|binding| binding.control == event.control)
+            .map(|binding| ControlAction::SetParameter {
+                name: binding.parameter.clone(),
+                value: event.value,
+            });
+        commands.chain(parameters).collect()
+    }
+}
+
+fn parse_control(text: &str) -> Result<Control> {
+    Control::parse(text).with_context(|| format!("invalid control '{}'", text))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(key: VirtualKeyCode, state: ElementState) -> KeyboardInput {
+        #[allow(deprecated)]
+        KeyboardInput {
+            scancode: 0,
+            state,
+            virtual_keycode: Some(key),
+            modifiers: ModifiersState::empty(),
+        }
+    }
+
+    fn bound_keys(keymap: &Keymap, command: Command) -> Vec<String> {
+        keymap
+            .bindings
+            .iter()
+            .filter(|binding| binding.command == command)
+            .flat_map(|binding| binding.keys.iter().map(|key| key.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_key_combinations() {
+        let combination = KeyCombination::parse("ctrl + Shift+K").unwrap();
+        assert_eq!(combination.key, VirtualKeyCode::K);
+        assert_eq!(
+            combination.modifiers,
+            ModifiersState::CTRL | ModifiersState::SHIFT
+        );
+        assert_eq!(combination.to_string(), "Shift+Ctrl+K");
+        assert_eq!(
+            KeyCombination::parse("Super+F5").unwrap().to_string(),
+            "Logo+F5"
+        );
+
+        for text in ["", "Ctrl+", "Hyper+K", "Ctrl+Nope", "k"] {
+            assert!(KeyCombination::parse(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn listed_commands_replace_their_default_keys() {
+        let keymap = Keymap::parse(
+            r#"
+                [[bindings]]
+                command = "ToggleFullscreen"
+                keys = ["F"]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(bound_keys(&keymap, Command::ToggleFullscreen), ["F"]);
+        assert_eq!(bound_keys(&keymap, Command::Quit), ["Escape"]);
+        assert!(Keymap::parse("[[bindings]]\ncommand = \"Quit\"\nkeys = [\"Nope\"]").is_err());
+    }
+
+    #[test]
+    fn triggers_fire_on_press_release_and_repeat() {
+        let mut keymap = Keymap::parse(
+            r#"
+                [[bindings]]
+                command = "Play"
+                keys = ["P"]
+                trigger = "press"
+
+                [[bindings]]
+                command = "Pause"
+                keys = ["P"]
+
+                [[bindings]]
+                command = "IncreaseVolume"
+                keys = ["P"]
+                trigger = "repeat"
+            "#,
+        )
+        .unwrap();
+        use ElementState::{Pressed, Released};
+
+        assert_eq!(
+            keymap.handle(key(VirtualKeyCode::P, Pressed)),
+            [Command::Play, Command::IncreaseVolume]
+        );
+        // Key repeat sends more presses.
+        assert_eq!(
+            keymap.handle(key(VirtualKeyCode::P, Pressed)),
+            [Command::IncreaseVolume]
+        );
+        assert_eq!(
+            keymap.handle(key(VirtualKeyCode::P, Released)),
+            [Command::Pause]
+        );
+
+        // Modifiers have to match exactly.
+        keymap.set_modifiers(ModifiersState::SHIFT);
+        assert!(keymap.handle(key(VirtualKeyCode::P, Pressed)).is_empty());
+    }
+}
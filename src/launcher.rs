@@ -0,0 +1,424 @@
+// Setup dialog shown before the demo when enabled with --setup.
+//
+// Drawn with the debug overlay in the demo window on a temporary device, which
+// is dropped again before the demo creates its own on the chosen adapter. The
+// last choice is saved to setup.toml and preselected next time.
+
+use anyhow::*;
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use winit::{
+    event::*,
+    event_loop::{ControlFlow, EventLoop},
+    platform::run_return::EventLoopExtRunReturn,
+    window::Window,
+};
+
+use crate::config::{self, Config, PresentMode, Resolution};
+use crate::overlay::{self, Overlay};
+use crate::pipelines::PipelineCache;
+
+const SETUP_PATH: &str = "setup.toml";
+// How long to wait for the window to take its new size.
+const RESIZE_TIMEOUT: Duration = Duration::from_secs(1);
+
+const COMMON_RESOLUTIONS: [(u32, u32); 5] = [
+    (960, 540),
+    (1280, 720),
+    (1920, 1080),
+    (2560, 1440),
+    (3840, 2160),
+];
+
+#[derive(Default, Serialize, Deserialize)]
+struct LastChoice {
+    adapter: Option<String>,
+    resolution: Option<String>,
+    fullscreen: Option<bool>,
+    vsync: Option<bool>,
+    #[serde(rename = "loop")]
+    looping: Option<bool>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Row {
+    Adapter,
+    Resolution,
+    Display,
+    Vsync,
+    Loop,
+    Start,
+    Quit,
+}
+
+const ROWS: [Row; 7] = [
+    Row::Adapter,
+    Row::Resolution,
+    Row::Display,
+    Row::Vsync,
+    Row::Loop,
+    Row::Start,
+    Row::Quit,
+];
+
+struct Setup {
+    adapters: Vec<String>,
+    resolutions: Vec<Resolution>,
+    adapter: usize,
+    resolution: usize,
+    fullscreen: bool,
+    vsync: bool,
+    looping: bool,
+    row: usize,
+}
+
+impl Setup {
+    fn new(adapters: Vec<String>, mut resolutions: Vec<Resolution>, config: &Config) -> Self {
+        let last: LastChoice = std::fs::read_to_string(SETUP_PATH)
+            .ok()
+            .and_then(|text| toml::from_str(&text).ok())
+            .unwrap_or_default();
+
+        let resolution = last
+            .resolution
+            .and_then(|text| text.parse().ok())
+            .unwrap_or(config.resolution);
+        if !resolutions.contains(&resolution) {
+            resolutions.push(resolution);
+            resolutions.sort_by_key(|r| (r.width, r.height));
+        }
+
+        let configured_adapter = config.adapter.as_ref().and_then(|wanted| {
+            wanted.parse().ok().or_else(|| {
+                let wanted = wanted.to_lowercase();
+                adapters
+                    .iter()
+                    .position(|name| name.to_lowercase().contains(&wanted))
+            })
+        });
+        let adapter = last
+            .adapter
+            .and_then(|name| adapters.iter().position(|a| *a == name))
+            .or(configured_adapter)
+            .filter(|&index| index < adapters.len())
+            .unwrap_or(0);
+
+        let vsync = !matches!(
+            config.present_mode,
+            PresentMode::AutoNoVsync | PresentMode::Mailbox | PresentMode::Immediate
+        );
+
+        Self {
+            resolution: resolutions.iter().position(|r| *r == resolution).unwrap(),
+            adapters,
+            resolutions,
+            adapter,
+            fullscreen: last.fullscreen.unwrap_or(config.fullscreen),
+            vsync: last.vsync.unwrap_or(vsync),
+            looping: last.looping.unwrap_or(config.looping),
+            row: ROWS.iter().position(|row| *row == Row::Start).unwrap(),
+        }
+    }
+
+    fn change(&mut self, step: isize) {
+        let cycle = |index: usize, len: usize| (index as isize + step).rem_euclid(len as isize);
+        match ROWS[self.row] {
+            Row::Adapter if !self.adapters.is_empty() => {
+                self.adapter = cycle(self.adapter, self.adapters.len()) as usize
+            }
+            Row::Resolution => {
+                self.resolution = cycle(self.resolution, self.resolutions.len()) as usize
+            }
+            Row::Display => self.fullscreen = !self.fullscreen,
+            Row::Vsync => self.vsync = !self.vsync,
+            Row::Loop => self.looping = !self.looping,
+            _ => (),
+        }
+    }
+
+    fn lines(&self) -> Vec<(Row, String)> {
+        let on_off = |on: bool| if on { "ON" } else { "OFF" };
+        let adapter = self
+            .adapters
+            .get(self.adapter)
+            .map(String::as_str)
+            .unwrap_or("NONE");
+        ROWS.iter()
+            .map(|&row| {
+                let text = match row {
+                    Row::Adapter => format!("ADAPTER     < {} >", adapter),
+                    Row::Resolution => {
+                        format!("RESOLUTION  < {} >", self.resolutions[self.resolution])
+                    }
+                    Row::Display => format!(
+                        "DISPLAY     < {} >",
+                        if self.fullscreen {
+                            "FULLSCREEN"
+                        } else {
+                            "WINDOWED"
+                        }
+                    ),
+                    Row::Vsync => format!("VSYNC       < {} >", on_off(self.vsync)),
+                    Row::Loop => format!("LOOP DEMO   < {} >", on_off(self.looping)),
+                    Row::Start => "START".to_string(),
+                    Row::Quit => "QUIT".to_string(),
+                };
+                (row, text)
+            })
+            .collect()
+    }
+
+    fn apply(&self, config: &mut Config) {
+        if !self.adapters.is_empty() {
+            config.adapter = Some(self.adapter.to_string());
+        }
+        config.resolution = self.resolutions[self.resolution];
+        config.fullscreen = self.fullscreen;
+        config.present_mode = if self.vsync {
+            PresentMode::AutoVsync
+        } else {
+            PresentMode::AutoNoVsync
+        };
+        config.looping = self.looping;
+    }
+
+    fn save(&self) -> Result<()> {
+        let last = LastChoice {
+            adapter: self.adapters.get(self.adapter).cloned(),
+            resolution: Some(self.resolutions[self.resolution].to_string()),
+            fullscreen: Some(self.fullscreen),
+            vsync: Some(self.vsync),
+            looping: Some(self.looping),
+        };
+        std::fs::write(SETUP_PATH, toml::to_string(&last)?)?;
+        Ok(())
+    }
+}
+
+/// Shows the dialog and updates `config` with the choice. Returns false to quit.
+pub fn run(
+    event_loop: &mut EventLoop<()>,
+    window: &Window,
+    instance: &wgpu::Instance,
+    config: &mut Config,
+) -> Result<bool> {
+    let surface = unsafe { instance.create_surface(window) }?;
+    let adapter = config::select_adapter(instance, &surface, config)?;
+    let (device, queue) = pollster::block_on(adapter.request_device(&Default::default(), None))?;
+
+    let caps = surface.get_capabilities(&adapter);
+    let format = caps
+        .formats
+        .iter()
+        .copied()
+        .find(|f| f.is_srgb())
+        .unwrap_or(caps.formats[0]);
+    let mut surface_config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format,
+        width: window.inner_size().width.max(1),
+        height: window.inner_size().height.max(1),
+        present_mode: wgpu::PresentMode::AutoVsync,
+        alpha_mode: caps.alpha_modes[0],
+        view_formats: vec![],
+    };
+    surface.configure(&device, &surface_config);
+
+    let adapters = instance
+        .enumerate_adapters(config.backend.backends())
+        .map(|adapter| {
+            let info = adapter.get_info();
+            format!("{} ({:?})", info.name, info.backend)
+        })
+        .collect();
+
+    let mut resolutions: Vec<Resolution> = window
+        .current_monitor()
+        .map(|monitor| {
+            monitor
+                .video_modes()
+                .map(|mode| Resolution {
+                    width: mode.size().width,
+                    height: mode.size().height,
+                })
+                .collect()
+        })
+        .unwrap_or_default();
+    if resolutions.is_empty() {
+        resolutions = COMMON_RESOLUTIONS
+            .iter()
+            .map(|&(width, height)| Resolution { width, height })
+            .collect();
+    }
+    resolutions.sort_by_key(|r| (r.width, r.height));
+    resolutions.dedup();
+
+    let mut setup = Setup::new(adapters, resolutions, config);
+    // The device is its own, so are its pipelines.
+    let mut pipelines = PipelineCache::new();
+    let mut overlay = Overlay::new(&device, &queue, &mut pipelines, format);
+    let mut start = false;
+
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::Wait;
+
+        match event {
+            Event::RedrawRequested(_) => {
+                let Result::Ok(output) = surface.get_current_texture() else {
+                    surface.configure(&device, &surface_config);
+                    return;
+                };
+                let view = output.texture.create_view(&Default::default());
+                let mut encoder = device.create_command_encoder(&Default::default());
+                encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("setup clear"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &view,
+                        resolve_target: None,
+                        ops: wgpu::Operations {
+                            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                            store: true,
+                        },
+                    })],
+                    depth_stencil_attachment: None,
+                });
+
+                let (x, y) = (40.0, 40.0);
+                let white = [1.0, 1.0, 1.0, 1.0];
+                let grey = [0.6, 0.6, 0.6, 1.0];
+                overlay.text(x, y, &config.title, white);
+                for (i, (row, text)) in setup.lines().into_iter().enumerate() {
+                    // Leave a gap between the settings and the buttons.
+                    let gap = if i >= ROWS.iter().position(|r| *r == Row::Start).unwrap() {
+                        1.0
+                    } else {
+                        0.0
+                    };
+                    let line_y = y + (i as f32 + 2.0 + gap) * overlay::LINE_HEIGHT;
+                    if i == setup.row {
+                        overlay.rect(
+                            x - 6.0,
+                            line_y - 4.0,
+                            overlay::text_width(&text) + 10.0,
+                            overlay::LINE_HEIGHT,
+                            [0.3, 0.3, 0.8, 0.8],
+                        );
+                    }
+                    let color = if row == Row::Quit { grey } else { white };
+                    overlay.text(x, line_y, &text, color);
+                }
+                overlay.text(
+                    x,
+                    y + (ROWS.len() as f32 + 4.0) * overlay::LINE_HEIGHT,
+                    "UP/DOWN SELECT  LEFT/RIGHT CHANGE  ENTER START  ESC QUIT",
+                    grey,
+                );
+
+                overlay.draw(
+                    &device,
+                    &queue,
+                    &mut pipelines,
+                    &mut encoder,
+                    &view,
+                    (surface_config.width, surface_config.height),
+                );
+                queue.submit(std::iter::once(encoder.finish()));
+                output.present();
+            }
+            Event::WindowEvent { event, .. } => match event {
+                WindowEvent::CloseRequested => *control_flow = ControlFlow::Exit,
+                WindowEvent::Resized(size) if size.width > 0 && size.height > 0 => {
+                    surface_config.width = size.width;
+                    surface_config.height = size.height;
+                    surface.configure(&device, &surface_config);
+                }
+                WindowEvent::KeyboardInput {
+                    input:
+                        KeyboardInput {
+                            state: ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                } => {
+                    match key {
+                        VirtualKeyCode::Up => setup.row = (setup.row + ROWS.len() - 1) % ROWS.len(),
+                        VirtualKeyCode::Down => setup.row = (setup.row + 1) % ROWS.len(),
+                        VirtualKeyCode::Left => setup.change(-1),
+                        VirtualKeyCode::Right => setup.change(1),
+                        VirtualKeyCode::Return | VirtualKeyCode::Space => match ROWS[setup.row] {
+                            Row::Quit => *control_flow = ControlFlow::Exit,
+                            Row::Start => {
+                                start = true;
+                                *control_flow = ControlFlow::Exit;
+                            }
+                            _ => setup.change(1),
+                        },
+                        VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit,
+                        _ => (),
+                    }
+                    window.request_redraw();
+                }
+                _ => (),
+            },
+            _ => (),
+        }
+    });
+
+    if !start {
+        return Ok(false);
+    }
+
+    if let Err(error) = setup.save() {
+        eprintln!("Could not save {}: {:#}", SETUP_PATH, error);
+    }
+    setup.apply(config);
+    resize_window(event_loop, window, config);
+    Ok(true)
+}
+
+/// Applies the chosen size and waits until the window has it, so the demo
+/// creates its render targets at the right size.
+fn resize_window(event_loop: &mut EventLoop<()>, window: &Window, config: &Config) {
+    // Logical like `initialize_window`, so HiDPI scaling applies the same way.
+    let size = winit::dpi::LogicalSize::new(config.resolution.width, config.resolution.height);
+    if config.fullscreen {
+        let monitor = window.current_monitor();
+        let fullscreen = config
+            .fullscreen_mode
+            .fullscreen(monitor, config.resolution);
+        // Already there, unless the video mode changed.
+        if window.fullscreen().as_ref() == Some(&fullscreen) {
+            return;
+        }
+        window.set_fullscreen(Some(fullscreen));
+    } else {
+        window.set_fullscreen(None);
+        window.set_inner_size(size);
+    }
+
+    let deadline = Instant::now() + RESIZE_TIMEOUT;
+    let wanted: winit::dpi::PhysicalSize<u32> = size.to_physical(window.scale_factor());
+    let mut resized = false;
+    event_loop.run_return(|event, _, control_flow| {
+        *control_flow = ControlFlow::WaitUntil(deadline);
+        match event {
+            Event::WindowEvent {
+                event: WindowEvent::Resized(_),
+                ..
+            } => resized = true,
+            Event::MainEventsCleared => {
+                let size = window.inner_size();
+                let done = if config.fullscreen {
+                    resized
+                } else {
+                    size.width == wanted.width && size.height == wanted.height
+                };
+                if done || Instant::now() >= deadline {
+                    *control_flow = ControlFlow::Exit;
+                }
+            }
+            _ => (),
+        }
+    });
+}
@@ -9,11 +9,43 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
-use std::{io::BufReader, time::Duration, time::Instant};
+use std::{time::Duration, time::Instant};
 
+mod analysis;
+mod audio;
+mod camera;
+mod camera_path;
 mod commandbuffer;
+mod config;
+mod controller;
+mod debug_camera;
+mod input;
+mod keymap;
+mod launcher;
+mod mesh;
+mod msaa;
+mod offline;
+mod overlay;
+mod pacing;
+mod parameters;
+mod particles;
+mod pipelines;
+mod post;
 mod resource;
+mod scene;
+mod simulation;
+mod soundtrack;
+mod stats;
+mod synth;
+mod taa;
+mod targets;
+mod texgen;
+mod tonemap;
+#[cfg(feature = "tweak")]
+mod tweak;
 use commandbuffer::{Command, CommandBuffer};
+use simulation::Simulation;
+use texgen::{NoiseKind, Op, TextureGenerator, TextureGraph};
 
 #[repr(C)]
 #[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
@@ -21,152 +53,485 @@ struct Uniforms {
     resolution: [f32; 2],
     time: f32,
     i_pass: i32,
+    // rms, bass, mid, treble
+    audio: [f32; 4],
+    // beat envelope, beat count
+    beat: [f32; 4],
+    // Shadertoy's iMouse
+    mouse: [f32; 4],
+    // normalized position, button mask, scroll
+    cursor: [f32; 4],
+    // effect or debug camera, w of position is 1 while the debug camera is on
+    camera_position: [f32; 4],
+    view: camera::Matrix,
+    projection: camera::Matrix,
+    // fixed-step tick, interpolation alpha between ticks, step length in seconds
+    simulation: [f32; 4],
+    // song row, pattern in the order list, row within the pattern, all 0
+    // unless the music comes from the synthesizer
+    song: [f32; 4],
 }
 
-fn handle_keyboard_input(input: KeyboardInput) -> Option<Command> {
-    if input.state == ElementState::Released {
-        match input.virtual_keycode {
-            Some(VirtualKeyCode::Escape) => Some(Command::Quit),
-            Some(VirtualKeyCode::J) => Some(Command::PlayReverse),
-            Some(VirtualKeyCode::K) => Some(Command::Pause),
-            Some(VirtualKeyCode::L) => Some(Command::PlayForward),
-            Some(VirtualKeyCode::Space) => Some(Command::Play),
-            Some(VirtualKeyCode::Grave) => Some(Command::DebugDraw),
-            /*
-                      Some(VirtualKeyCode::Up) => Some(Command::IncreaseVolume),
-                      Some(VirtualKeyCode::Down) => Some(Command::DecreaseVolume),
-            */
-            _ => None,
+// Use a fixed time step for  logic updates.
+const FIXED_TIME_STEP: Duration = Duration::from_millis(16);
+
+const PARAMETERS_PATH: &str = "params.toml";
+const CAMERA_PATH: &str = "camera.toml";
+const KEYMAP_PATH: &str = "keymap.toml";
+
+fn scene_parameters() -> parameters::ParameterSet {
+    parameters::ParameterSet::new()
+        .float("scale", 3.0, 1.0, 6.0)
+        .float("iteration", 4.0, 1.0, 8.0)
+        .int("steps", 40, 8, 200)
+        .float("sheen", 100.0, 0.0, 400.0)
+        .color("color_a", [0.7, 0.2, 0.3])
+        .color("color_b", [0.2, 0.1, 0.3])
+}
+
+const PARTICLE_CAPACITY: u32 = 4096;
+
+const MUSIC_FADE_IN: Duration = Duration::from_secs(1);
+const MUSIC_FADE_OUT: Duration = Duration::from_secs(3);
+
+/// Deterministic simulation the effect steps on every fixed update, if it has one.
+fn effect_simulation(effect: &str) -> Option<Box<dyn simulation::Simulation>> {
+    match effect {
+        "not_menger" => None,
+        _ => unreachable!("effects are checked by the config"),
+    }
+}
+
+/// Camera path of the effect over `length` seconds, replaced by camera.toml when there is one.
+fn effect_camera_path(effect: &str, length: f32) -> camera_path::CameraPath {
+    match effect {
+        // Sways in front of the fractal, keyed often enough that the spline
+        // follows the sines closely.
+        "not_menger" => {
+            let keys = (0..=(length / 0.25).ceil() as u32)
+                .map(|index| {
+                    let time = index as f32 * 0.25;
+                    let position = [0.1 * time.sin(), 0.12 * (time * 0.7).cos(), 0.0];
+                    camera_path::Key {
+                        time,
+                        position,
+                        target: [position[0], position[1], 1.0],
+                        fov: camera::DEFAULT_FOV.to_degrees(),
+                        roll: 0.0,
+                        handle: None,
+                    }
+                })
+                .collect();
+            camera_path::CameraPath::new(camera_path::Spline::CatmullRom, keys)
         }
-    } else {
-        None
+        _ => unreachable!("effects are checked by the config"),
     }
 }
 
-// Use a fixed time step for  logic updates.
-const FIXED_TIME_STEP: Duration = Duration::from_millis(16);
+/// Post-processing chain of the effect, in the order it runs.
+fn effect_post(effect: &str) -> Vec<post::Effect> {
+    match effect {
+        "not_menger" => vec![post::Effect::Vhs {
+            amount: 0.1,
+            speed: 3.0,
+        }],
+        _ => unreachable!("effects are checked by the config"),
+    }
+}
+
+fn effect_meshes(effect: &str, device: &wgpu::Device) -> Vec<mesh::Mesh> {
+    match effect {
+        "not_menger" => vec![mesh::Mesh::cube(device)],
+        _ => unreachable!("effects are checked by the config"),
+    }
+}
+
+/// Objects of the effect at `time`, using the meshes from `effect_meshes`.
+fn effect_objects<'a>(
+    effect: &str,
+    _time: f32,
+    _meshes: &'a [mesh::Mesh],
+) -> Vec<mesh::Object<'a>> {
+    match effect {
+        "not_menger" => Vec::new(),
+        _ => unreachable!("effects are checked by the config"),
+    }
+}
+
+fn effect_emitters(effect: &str) -> Vec<particles::Emitter> {
+    match effect {
+        "not_menger" => Vec::new(),
+        _ => unreachable!("effects are checked by the config"),
+    }
+}
+
+/// Renders the soundtrack with the synthesizer, with its song position for the shaders.
+fn synth_music() -> (audio::AudioBuffer, Option<synth::SongPosition>) {
+    let song = soundtrack::song().unwrap();
+    let music = synth::render_buffer(&song, 44100).unwrap();
+    (music, Some(synth::SongPosition::new(&song)))
+}
+
+fn effect_source(effect: &str) -> &'static str {
+    match effect {
+        "not_menger" => include_str!("not_menger.wgsl"),
+        _ => unreachable!("effects are checked by the config"),
+    }
+}
+
+/// Compiles the effect again from its file in the source tree, so it can be
+/// edited while the demo runs. Returns the path it was read from.
+///
+/// Only debug builds have it, release builds run away from the source tree.
+#[cfg(debug_assertions)]
+fn reload_effect(
+    device: &Device,
+    pipeline_cache: &mut pipelines::PipelineCache,
+    parameters: &parameters::ParameterSet,
+    effect: &str,
+) -> anyhow::Result<std::path::PathBuf> {
+    use anyhow::Context;
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("src")
+        .join(format!("{}.wgsl", effect));
+    let source = std::fs::read_to_string(&path)
+        .with_context(|| format!("Failed to read {}", path.display()))?;
+    pipeline_cache.reload_shader(device, "effect", &(parameters.wgsl() + &source))?;
+    Ok(path)
+}
 
-fn initialize_window(event_loop: &EventLoop<()>) -> Window {
+/// With vsync frames are expected every refresh of the monitor the window is on.
+fn refresh_interval(
+    monitor: Option<&winit::monitor::MonitorHandle>,
+    vsync: bool,
+) -> Option<Duration> {
+    monitor
+        .and_then(|monitor| monitor.refresh_rate_millihertz())
+        .filter(|_| vsync)
+        .map(|millihertz| Duration::from_secs_f64(1000.0 / millihertz as f64))
+}
+
+fn initialize_window(event_loop: &EventLoop<()>, config: &config::Config) -> Window {
+    let resolution = config.resolution;
     WindowBuilder::new()
-        .with_title("Solar Assembly 2024 Winner Demo")
-        .with_inner_size(winit::dpi::LogicalSize::new(960.0, 540.0))
+        .with_title(&config.title)
+        .with_inner_size(winit::dpi::LogicalSize::new(
+            resolution.width as f64,
+            resolution.height as f64,
+        ))
+        .with_fullscreen(config.fullscreen.then(|| {
+            let monitor = event_loop.primary_monitor();
+            config
+                .fullscreen_mode
+                .fullscreen(monitor, config.resolution)
+        }))
         .build(event_loop)
         .unwrap()
 }
 
-fn main() {
-    env_logger::init(); // Necessary for logging within WGPU
-    let event_loop = EventLoop::new(); // Loop provided by winit for handling window events
-    let window = initialize_window(&event_loop);
+/// Bind groups reading rt_0 and rt_1, so a pass can sample the target it isn't drawing into.
+#[allow(clippy::too_many_arguments)]
+fn create_bind_groups(
+    device: &Device,
+    layout: &BindGroupLayout,
+    targets: &targets::RenderTargets,
+    uniforms: &Buffer,
+    sampler: &Sampler,
+    grain: &TextureView,
+    logo: &TextureView,
+    spectrum: &TextureView,
+    parameters: &Buffer,
+) -> [BindGroup; 2] {
+    let create = |label, rt| {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: uniforms.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(rt),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(rt),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(grain),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(logo),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: wgpu::BindingResource::TextureView(spectrum),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: parameters.as_entire_binding(),
+                },
+            ],
+            layout,
+            label: Some(label),
+        })
+    };
 
-    let mut frame_count = 0;
-    let mut command_buffer = CommandBuffer::new();
-    let mut last_update_time = Instant::now();
+    [
+        create("bind group rt_0", &targets.rt_0),
+        create("bind group rt_1", &targets.rt_1),
+    ]
+}
 
-    let mut _playback_volume: f32 = 1.0;
+fn main() {
+    env_logger::init(); // Necessary for logging within WGPU
+    let mut demo_config = config::Config::load().unwrap_or_else(|error| {
+        eprintln!("{:#}", error);
+        std::process::exit(2);
+    });
 
     let instance = Instance::new(wgpu::InstanceDescriptor {
-        backends: wgpu::Backends::all(),
+        backends: demo_config.backend.backends(),
         dx12_shader_compiler: wgpu::Dx12Compiler::Fxc,
     });
+    if demo_config.list_adapters {
+        config::print_adapters(&instance, &demo_config);
+        return;
+    }
+
+    let mut event_loop = EventLoop::new(); // Loop provided by winit for handling window events
+    let window = initialize_window(&event_loop, &demo_config);
+
+    if demo_config.setup {
+        let start = launcher::run(&mut event_loop, &window, &instance, &mut demo_config)
+            .unwrap_or_else(|error| {
+                eprintln!("Setup failed: {:#}", error);
+                std::process::exit(1);
+            });
+        if !start {
+            return;
+        }
+    }
+
+    let mut frame_count = 0;
+    let mut frame_stats = stats::FrameStats::new();
+    let mut debug_draw = false;
+    let mut command_buffer = CommandBuffer::new();
+    let mut keymap = keymap::Keymap::load_or_default(KEYMAP_PATH);
+    let mut controllers = controller::Controllers::connect();
+    let mut mouse = input::MouseState::new();
+    let mut debug_camera = debug_camera::DebugCamera::new();
+    let mut cursor_visible = true;
+
+    let mut playback_volume = audio::Volume::new(1.0, demo_config.mute);
 
     let surface = unsafe { instance.create_surface(&window) }.unwrap();
 
-    let adapter = pollster::block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
-        power_preference: wgpu::PowerPreference::default(),
-        compatible_surface: Some(&surface),
-        force_fallback_adapter: false,
-    }))
-    .unwrap();
+    let adapter =
+        config::select_adapter(&instance, &surface, &demo_config).unwrap_or_else(|error| {
+            eprintln!("{:#}", error);
+            std::process::exit(1);
+        });
 
     let (device, queue) = pollster::block_on(adapter.request_device(
         &wgpu::DeviceDescriptor {
             label: None,
-            features: wgpu::Features::empty(),
+            // For sample counts other than 4, when the adapter has them.
+            features: adapter.features() & wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES,
             limits: wgpu::Limits::default(),
         },
         None, // Trace path
     ))
     .unwrap();
 
+    let sample_count = msaa::supported_sample_count(&adapter, device.features(), demo_config.msaa);
+    if sample_count != demo_config.msaa {
+        eprintln!(
+            "{}x MSAA is not supported by this adapter, falling back to {}x",
+            demo_config.msaa, sample_count
+        );
+    }
+
     let size = window.inner_size();
 
     let surface_caps = surface.get_capabilities(&adapter);
-    // Shader code in this tutorial assumes an Srgb surface texture. Using a different
-    // one will result all the colors comming out darker. If you want to support non
-    // Srgb surfaces, you'll need to account for that when drawing to the frame.
+    // Prefer an sRGB surface, the tone mapper encodes the output itself on the others.
     let surface_format = surface_caps
         .formats
         .iter()
         .copied()
         .find(|f| f.is_srgb())
         .unwrap_or(surface_caps.formats[0]);
-    let config = wgpu::SurfaceConfiguration {
+    let present_mode = demo_config.present_mode.select(&surface_caps.present_modes);
+    if present_mode != demo_config.present_mode.wgpu() {
+        eprintln!(
+            "{:?} is not supported, using {:?}",
+            demo_config.present_mode.wgpu(),
+            present_mode
+        );
+    }
+    let mut config = wgpu::SurfaceConfiguration {
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         format: surface_format,
         width: size.width,
         height: size.height,
-        present_mode: surface_caps.present_modes[0],
+        present_mode,
         alpha_mode: surface_caps.alpha_modes[0],
         view_formats: vec![],
     };
 
     surface.configure(&device, &config);
 
+    let mut pacer = pacing::FramePacer::new(demo_config.max_fps);
+    // The frame rate cap can only make the refresh interval longer.
+    let vsync = matches!(
+        present_mode,
+        PresentMode::AutoVsync | PresentMode::Fifo | PresentMode::FifoRelaxed
+    );
+    let mut monitor = window.current_monitor();
+    frame_stats.set_expected(
+        pacer
+            .interval()
+            .max(refresh_interval(monitor.as_ref(), vsync)),
+    );
+
     let solar_logo_bytes = include_bytes!("solar_groot.jpg");
     let solar_logo_texture =
         resource::Texture::new(&device, &queue, solar_logo_bytes, Some("Solar Logo")).unwrap();
 
-    let texture_descriptor = wgpu::TextureDescriptor {
-        size: wgpu::Extent3d {
-            width: window.inner_size().width,
-            height: window.inner_size().height,
-            depth_or_array_layers: 1,
-        },
+    let mut pipeline_cache = pipelines::PipelineCache::new();
+
+    let texture_generator = TextureGenerator::new(&device, &mut pipeline_cache);
+    let grain_texture = {
+        let mut graph = TextureGraph::new(256, 256);
+        let fine = graph.add(Op::Noise {
+            kind: NoiseKind::Value,
+            frequency: 64,
+            octaves: 3,
+            persistence: 0.5,
+            seed: 7,
+            low: [0.0, 0.0, 0.0, 1.0],
+            high: [1.0, 1.0, 1.0, 1.0],
+        });
+        let cells = graph.add(Op::Noise {
+            kind: NoiseKind::Cellular,
+            frequency: 8,
+            octaves: 1,
+            persistence: 0.5,
+            seed: 13,
+            low: [0.0, 0.0, 0.0, 1.0],
+            high: [1.0, 1.0, 1.0, 1.0],
+        });
+        let grain = graph.add(Op::Distort {
+            source: fine,
+            offset: cells,
+            strength: 0.02,
+        });
+        texture_generator
+            .generate(
+                &device,
+                &queue,
+                &mut pipeline_cache,
+                &graph,
+                grain,
+                Some("Grain"),
+            )
+            .unwrap()
+    };
+
+    let spectrum_size = wgpu::Extent3d {
+        width: analysis::SPECTRUM_BANDS as u32,
+        height: 1,
+        depth_or_array_layers: 1,
+    };
+    let spectrum_texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Spectrum"),
+        size: spectrum_size,
         mip_level_count: 1,
         sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: config.format, //wgpu::TextureFormat::Bgra8UnormSrgb,
-        usage: wgpu::TextureUsages::TEXTURE_BINDING
-            | wgpu::TextureUsages::RENDER_ATTACHMENT
-            | wgpu::TextureUsages::COPY_DST
-            | wgpu::TextureUsages::COPY_SRC,
-        label: None,
-        view_formats: &[], // Default
-    };
+        format: wgpu::TextureFormat::R32Float,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+    let spectrum_view = spectrum_texture.create_view(&Default::default());
 
-    let texture_view_descriptor: TextureViewDescriptor = wgpu::TextureViewDescriptor {
-        label: None,
-        format: config.format.into(), //Some(wgpu::TextureFormat::Bgra8UnormSrgb),
-        dimension: Some(wgpu::TextureViewDimension::D2),
-        aspect: wgpu::TextureAspect::All,
-        base_mip_level: 0,
-        mip_level_count: None,
-        base_array_layer: 0,
-        array_layer_count: None,
-    };
+    let mut dynamic_scale = targets::DynamicScale::new(
+        demo_config.render_scale,
+        Duration::from_secs_f32(1.0 / demo_config.target_fps),
+    );
+    let mut targets = targets::RenderTargets::new(
+        &device,
+        &config,
+        demo_config.aspect,
+        dynamic_scale.scale,
+        sample_count,
+    );
+    // Set on resize or a new render scale, the targets are recreated before the next frame.
+    let mut targets_dirty = false;
 
-    let rt_0 = device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        ..texture_descriptor
-    });
-    let rt_1 = device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        ..texture_descriptor
-    });
-    let rt_2 = device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        ..texture_descriptor
-    });
-    let rt_3 = device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        ..texture_descriptor
+    // HDR effects, tone mapping, display-referred effects, then the surface.
+    let (display_effects, hdr_effects): (Vec<_>, Vec<_>) = demo_config
+        .post
+        .clone()
+        .unwrap_or_else(|| effect_post(&demo_config.effect))
+        .into_iter()
+        .partition(post::Effect::display_referred);
+    let mut post_stack = |effects| {
+        post::PostStack::new(
+            &device,
+            &queue,
+            &mut pipeline_cache,
+            targets::HDR_FORMAT,
+            effects,
+            &grain_texture.texture,
+        )
+        .unwrap_or_else(|error| {
+            eprintln!("{:#}", error);
+            std::process::exit(1);
+        })
+    };
+    let mut hdr_post = post_stack(hdr_effects);
+    let mut display_post = post_stack(display_effects);
+    hdr_post.resize(&device, &targets, 0);
+    // The logo goes into the other target, tone mapping back into this one.
+    display_post.resize(&device, &targets, hdr_post.output());
+    let mut tone_mapper = tonemap::ToneMapper::new(
+        &device,
+        &mut pipeline_cache,
+        targets::HDR_FORMAT,
+        Some(demo_config.tone_map),
+    );
+    tone_mapper.resize(&device, &targets);
+    let mut present = tonemap::ToneMapper::new(&device, &mut pipeline_cache, config.format, None);
+    present.resize(&device, &targets);
+    let mut depth_resolve = msaa::DepthResolve::new(&device, &mut pipeline_cache);
+    depth_resolve.resize(&device, &queue, &targets);
+    let mut taa = taa::TemporalAa::new(&device, &mut pipeline_cache);
+    taa.resize(&device, &targets);
+    let mut offline = demo_config.offline.clone().map(|directory| {
+        offline::OfflineRender::new(
+            &device,
+            &mut pipeline_cache,
+            directory,
+            demo_config.offline_fps,
+            demo_config.offline_samples,
+            demo_config.start,
+        )
+        .unwrap_or_else(|error| {
+            eprintln!("{:#}", error);
+            std::process::exit(1);
+        })
     });
-
-    let rt_0_view = rt_0.create_view(&Default::default());
-    let rt_1_view = rt_1.create_view(&Default::default());
-    let rt_2_view = rt_2.create_view(&Default::default());
-    let _rt_3_view = rt_3.create_view(&texture_view_descriptor);
+    if let Some(offline) = &mut offline {
+        offline.resize(&device, &targets);
+    }
 
     let render_texture_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
         label: None,
@@ -179,22 +544,42 @@ fn main() {
         ..Default::default()
     });
 
-    let vertex_shader = device.create_shader_module(ShaderModuleDescriptor {
-        label: None,
-        source: wgpu::ShaderSource::Wgsl(include_str!("not_menger.wgsl").into()),
-    });
+    let mut parameters = scene_parameters();
+    if std::path::Path::new(PARAMETERS_PATH).exists() {
+        if let Err(error) = parameters.load(PARAMETERS_PATH) {
+            eprintln!("Using default parameters: {:#}", error);
+        }
+    }
 
-    let fragment_shader = device.create_shader_module(ShaderModuleDescriptor {
-        label: None,
-        source: wgpu::ShaderSource::Wgsl(include_str!("not_menger.wgsl").into()),
+    let parameters_buffer = device.create_buffer_init(&BufferInitDescriptor {
+        label: Some("parameters"),
+        contents: bytemuck::cast_slice(&parameters.uniform_data()),
+        usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
     });
 
+    // The Params struct is generated from the declared parameters.
+    let shader_source = parameters.wgsl() + effect_source(&demo_config.effect);
+
+    pipeline_cache.add_shader(&device, "effect", &shader_source);
+
+    let mut overlay = overlay::Overlay::new(&device, &queue, &mut pipeline_cache, config.format);
+    #[cfg(feature = "tweak")]
+    let mut tweak_gui = tweak::TweakGui::new(&window, &device, config.format);
+
     let mut uniforms = Uniforms {
-        resolution: [size.width as _, size.height as _],
+        resolution: [targets.width as f32, targets.height as f32],
         time: 0.,
         i_pass: 0,
+        audio: [0.0; 4],
+        beat: [0.0; 4],
+        mouse: [0.0; 4],
+        cursor: [0.0; 4],
+        camera_position: [0.0; 4],
+        view: camera::IDENTITY,
+        projection: camera::IDENTITY,
+        simulation: [0.0; 4],
+        song: [0.0; 4],
     };
-    let time = Instant::now();
     let uniforms_buffer = device.create_buffer_init(&BufferInitDescriptor {
         label: None,
         contents: bytemuck::bytes_of(&uniforms),
@@ -260,163 +645,428 @@ fn main() {
                 },
                 count: None,
             },
-        ],
-    });
-
-    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniforms_buffer.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&render_texture_sampler),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: wgpu::BindingResource::TextureView(&rt_1_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: wgpu::BindingResource::TextureView(&rt_1_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 4,
-                resource: wgpu::BindingResource::TextureView(&rt_2_view),
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
             },
-            wgpu::BindGroupEntry {
-                binding: 5,
-                resource: wgpu::BindingResource::TextureView(&solar_logo_texture.view),
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::FRAGMENT,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
             },
         ],
-        layout: &bind_group_layout,
-        label: Some("bind group"),
     });
 
-    let bind_group_rt = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniforms_buffer.as_entire_binding(),
-            },
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::Sampler(&render_texture_sampler),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: wgpu::BindingResource::TextureView(&rt_0_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 3,
-                resource: wgpu::BindingResource::TextureView(&rt_1_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 4,
-                resource: wgpu::BindingResource::TextureView(&rt_2_view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 5,
-                resource: wgpu::BindingResource::TextureView(&solar_logo_texture.view),
-            },
-        ],
-        layout: &bind_group_layout,
-        label: Some("bind group rt"),
-    });
+    let mut bind_groups = create_bind_groups(
+        &device,
+        &bind_group_layout,
+        &targets,
+        &uniforms_buffer,
+        &render_texture_sampler,
+        &grain_texture.view,
+        &solar_logo_texture.view,
+        &spectrum_view,
+        &parameters_buffer,
+    );
 
-    let render_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: None,
-        bind_group_layouts: &[&bind_group_layout],
-        push_constant_ranges: &[],
-    });
+    pipeline_cache.add_layout(&device, "effect", &[&bind_group_layout]);
 
-    let render_pipeline = device.create_render_pipeline(&RenderPipelineDescriptor {
-        label: None,
-        layout: Some(&render_pipeline_layout),
-        vertex: VertexState {
-            module: &vertex_shader,
-            entry_point: "vs_main",
-            buffers: &[],
-        },
-        fragment: Some(FragmentState {
-            module: &fragment_shader,
-            entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: config.format,
-                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
+    // The raymarched scene writes its depth for meshes and particles to test against.
+    let scene_pipeline = pipelines::RenderKey {
+        shader: "effect",
+        layout: "effect",
+        vertex: "vs_main",
+        vertex_buffers: Vec::new(),
+        fragment: "fs_scene",
+        targets: vec![wgpu::ColorTargetState {
+            format: targets::HDR_FORMAT,
+            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+            write_mask: wgpu::ColorWrites::ALL,
+        }],
+        primitive: wgpu::PrimitiveState::default(),
+        depth: Some(pipelines::DepthKey {
+            format: targets::DEPTH_FORMAT,
+            write: true,
+            compare: wgpu::CompareFunction::Always,
         }),
-        primitive: PrimitiveState::default(),
-        depth_stencil: None,
-        multisample: wgpu::MultisampleState {
-            count: 1,
-            mask: !0,
-            alpha_to_coverage_enabled: false,
-        },
-        multiview: None,
+        sample_count,
+    };
+    let render_pipeline = pipelines::RenderKey {
+        fragment: "fs_main",
+        depth: None,
+        sample_count: 1,
+        ..scene_pipeline.clone()
+    };
+
+    let mut mesh_renderer = mesh::MeshRenderer::new(
+        &device,
+        &queue,
+        &mut pipeline_cache,
+        targets::HDR_FORMAT,
+        sample_count,
+    );
+    let scene_meshes = effect_meshes(&demo_config.effect, &device);
+    let gltf_scene = demo_config.scene.as_ref().map(|path| {
+        scene::Scene::load(&device, &queue, &mesh_renderer, path).unwrap_or_else(|error| {
+            eprintln!("{:#}", error);
+            std::process::exit(1);
+        })
     });
 
     // Audio goes here
     let (_stream, stream_handle) = rodio::OutputStream::try_default().unwrap();
-    let file = std::fs::File::open("music.mp3").unwrap();
-    let _music = stream_handle.play_once(BufReader::new(file)).unwrap();
+    let decoded = match &demo_config.music {
+        config::Music::Default => Some(audio::AudioBuffer::decode(config::DEFAULT_MUSIC)),
+        config::Music::File(path) => Some(audio::AudioBuffer::decode(path)),
+        config::Music::Synth => None,
+    };
+    let (music, song_position) = match decoded {
+        Some(Result::Ok(music)) => (music, None),
+        None => synth_music(),
+        // No soundtrack on disk, generate one instead.
+        Some(Err(error))
+            if demo_config.music == config::Music::Default
+                && error
+                    .downcast_ref::<std::io::Error>()
+                    .is_some_and(|error| error.kind() == std::io::ErrorKind::NotFound) =>
+        {
+            synth_music()
+        }
+        Some(Err(error)) => {
+            eprintln!("{:#}", error);
+            std::process::exit(2);
+        }
+    };
+    if Duration::from_secs_f32(demo_config.start) > music.duration() {
+        eprintln!(
+            "Start time {:.2}s is past the end of the music at {:.2}s",
+            demo_config.start,
+            music.duration().as_secs_f32()
+        );
+        std::process::exit(1);
+    }
+    let mut camera_path = effect_camera_path(&demo_config.effect, music.duration().as_secs_f32());
+    if std::path::Path::new(CAMERA_PATH).exists() {
+        match camera_path::CameraPath::load(CAMERA_PATH) {
+            Result::Ok(path) => camera_path = path,
+            Err(error) => eprintln!("Using the effect's camera path: {:#}", error),
+        }
+    }
+
+    let (source, playback) = audio::play(
+        music.clone(),
+        Duration::from_secs_f32(demo_config.start),
+        demo_config.looping,
+    );
+    let mut playback_clock = audio::PlaybackClock::new(playback.clone());
+    let music_sink = rodio::Sink::try_new(&stream_handle).unwrap();
+    playback_volume.fade_in(MUSIC_FADE_IN);
+    music_sink.set_volume(playback_volume.gain(Instant::now()));
+    music_sink.append(source);
+    if offline.is_some() {
+        // Frames take as long as they take, the music would run ahead.
+        music_sink.pause();
+    }
+    let mut analyzer = analysis::Analyzer::new();
+
+    // Ticks before the start time run up front, so starting later reaches the same state.
+    let mut scene_simulation = effect_simulation(&demo_config.effect);
+    let mut particles = particles::ParticleSystem::new(
+        &device,
+        &mut pipeline_cache,
+        targets::HDR_FORMAT,
+        sample_count,
+        PARTICLE_CAPACITY,
+        effect_emitters(&demo_config.effect),
+    );
+    let mut fixed_step = simulation::FixedStep::new(FIXED_TIME_STEP, 0);
+    let start = offline
+        .as_ref()
+        .map_or_else(|| playback.seconds(), |offline| offline.time());
+    for tick in fixed_step.advance_to(Duration::from_secs_f32(start), u64::MAX) {
+        if let Some(scene_simulation) = &mut scene_simulation {
+            scene_simulation.step(tick, FIXED_TIME_STEP.as_secs_f32());
+        }
+        particles.step(tick, FIXED_TIME_STEP.as_secs_f32());
+    }
 
     // Opens the window and starts processing events
     event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
+        #[cfg(feature = "tweak")]
+        if let Event::WindowEvent { event, .. } = &event {
+            if tweak_gui.on_event(event) {
+                return;
+            }
+        }
+
+        // Monitors can have different refresh rates.
+        if matches!(
+            event,
+            Event::WindowEvent {
+                event: WindowEvent::Moved(_) | WindowEvent::ScaleFactorChanged { .. },
+                ..
+            }
+        ) {
+            let current = window.current_monitor();
+            if current != monitor {
+                frame_stats.set_expected(
+                    pacer
+                        .interval()
+                        .max(refresh_interval(current.as_ref(), vsync)),
+                );
+                monitor = current;
+            }
+        }
 
         match event {
             Event::RedrawRequested(_) => {
                 let now = Instant::now();
-                let _time_delta = now.duration_since(last_update_time);
+                frame_stats.frame(now);
+
+                if demo_config.dynamic_resolution && offline.is_none() {
+                    if let Some(frame_time) = frame_stats.last_frame_time() {
+                        targets_dirty |= dynamic_scale.update(now, frame_time);
+                    }
+                }
+                if targets_dirty {
+                    targets_dirty = false;
+                    targets = targets::RenderTargets::new(
+                        &device,
+                        &config,
+                        demo_config.aspect,
+                        dynamic_scale.scale,
+                        sample_count,
+                    );
+                    bind_groups = create_bind_groups(
+                        &device,
+                        &bind_group_layout,
+                        &targets,
+                        &uniforms_buffer,
+                        &render_texture_sampler,
+                        &grain_texture.view,
+                        &solar_logo_texture.view,
+                        &spectrum_view,
+                        &parameters_buffer,
+                    );
+                    hdr_post.resize(&device, &targets, 0);
+                    display_post.resize(&device, &targets, hdr_post.output());
+                    tone_mapper.resize(&device, &targets);
+                    present.resize(&device, &targets);
+                    depth_resolve.resize(&device, &queue, &targets);
+                    taa.resize(&device, &targets);
+                    if let Some(offline) = &mut offline {
+                        offline.resize(&device, &targets);
+                    }
+                }
+
+                // The offline render steps by whole frames, otherwise the music sets the time.
+                uniforms.time = match &offline {
+                    Some(offline) => offline.time(),
+                    None => playback_clock.seconds(Instant::now()),
+                };
+                let audio_frame = (uniforms.time * music.sample_rate as f32) as usize;
+                if let Some(song_position) = &song_position {
+                    let (pattern, line) = song_position.pattern_row(uniforms.time);
+                    uniforms.song = [
+                        song_position.row(uniforms.time) as f32,
+                        pattern as f32,
+                        line as f32,
+                        0.0,
+                    ];
+                }
+
+                let features = analyzer.update(&music, audio_frame);
+                uniforms.audio = [features.rms, features.bass, features.mid, features.treble];
+                uniforms.beat = [features.beat, features.beats as f32, 0.0, 0.0];
 
-                uniforms.time = time.elapsed().as_secs_f32();
+                // iMouse is in render target pixels, like the resolution of the scene pass.
+                let scale = targets.width as f32 / targets.viewport.width;
+                uniforms.mouse = mouse.shadertoy(&targets.viewport).map(|v| v * scale);
+                uniforms.cursor = mouse.cursor(&targets.viewport);
+                mouse.end_frame();
+
+                let alpha = fixed_step.alpha();
+                let pose = gltf_scene.as_ref().map(|scene| scene.pose(uniforms.time));
+                let scene_camera = gltf_scene
+                    .as_ref()
+                    .zip(pose.as_deref())
+                    .and_then(|(scene, pose)| scene.camera(pose));
+                let path_camera = camera_path.camera(uniforms.time);
+                let mut camera = if debug_camera.enabled {
+                    let (forward, right, up) = debug_camera.basis();
+                    camera::Camera {
+                        position: debug_camera.interpolated_position(alpha),
+                        forward,
+                        right,
+                        up,
+                        fov: path_camera.fov,
+                        jitter: [0.0; 2],
+                    }
+                } else if let Some(scene_camera) = scene_camera {
+                    scene_camera
+                } else {
+                    path_camera
+                };
+                let [x, y, z] = camera.position;
+                uniforms.camera_position = [x, y, z, debug_camera.enabled as i32 as f32];
+                uniforms.view = camera.view();
+                uniforms.simulation = [
+                    fixed_step.tick as f32,
+                    alpha,
+                    FIXED_TIME_STEP.as_secs_f32(),
+                    0.0,
+                ];
                 queue.write_buffer(&uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
+                queue.write_buffer(
+                    &parameters_buffer,
+                    0,
+                    bytemuck::cast_slice(&parameters.uniform_data()),
+                );
+                queue.write_texture(
+                    spectrum_texture.as_image_copy(),
+                    bytemuck::cast_slice(&features.spectrum),
+                    wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(4 * analysis::SPECTRUM_BANDS as u32),
+                        rows_per_image: None,
+                    },
+                    spectrum_size,
+                );
 
-                let output = surface.get_current_texture().unwrap();
+                let output = match surface.get_current_texture() {
+                    Ok(output) => output,
+                    Err(SurfaceError::Lost | SurfaceError::Outdated) => {
+                        surface.configure(&device, &config);
+                        return;
+                    }
+                    // The frame didn't become available in time, try again on the next one.
+                    Err(SurfaceError::Timeout) => return,
+                    Err(SurfaceError::OutOfMemory) => {
+                        eprintln!("Out of memory getting the next frame");
+                        *control_flow = quit();
+                        return;
+                    }
+                };
                 let view = output
                     .texture
                     .create_view(&wgpu::TextureViewDescriptor::default());
-                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("Render Encode 1r"),
-                });
 
-                {
-                    let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
-                        label: None,
-                        color_attachments: &[Some(RenderPassColorAttachment {
-                            view: &rt_0_view,
-                            resolve_target: None,
-                            ops: Operations {
-                                load: LoadOp::Clear(Color::BLUE),
-                                store: true,
-                            },
-                        })],
-                        depth_stencil_attachment: None,
-                    });
-                    render_pass.set_pipeline(&render_pipeline);
-                    render_pass.set_bind_group(0, &bind_group, &[]);
+                let mut objects = effect_objects(&demo_config.effect, uniforms.time, &scene_meshes);
+                if let (Some(scene), Some(pose)) = (&gltf_scene, &pose) {
+                    objects.extend(scene.objects(pose));
+                }
+
+                // The offline render averages several jittered samples per frame,
+                // in real time temporal anti-aliasing spreads them over frames.
+                let samples = offline.as_ref().map_or(1, |offline| offline.samples);
+                for sample in 0..samples {
+                    camera.jitter = match &offline {
+                        Some(_) => taa::halton_jitter(sample, &targets),
+                        None if demo_config.taa => taa.jitter(&targets),
+                        None => [0.0; 2],
+                    };
+                    uniforms.projection =
+                        camera.projection(targets.width as f32 / targets.height as f32);
+
+                    let mut encoder =
+                        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Render Encode 1r"),
+                        });
 
-                    /*for i in 0..1*/
                     {
-                        uniforms.i_pass = 0; // i
-                        queue.write_buffer(&uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
-                        render_pass.draw(0..3, 0..1);
+                        let mut render_pass = encoder.begin_render_pass(&RenderPassDescriptor {
+                            label: None,
+                            color_attachments: &[Some(
+                                targets.color_attachment(LoadOp::Clear(Color::BLUE)),
+                            )],
+                            depth_stencil_attachment: Some(
+                                targets.depth_attachment(LoadOp::Clear(0.0)),
+                            ),
+                        });
+                        render_pass.set_pipeline(pipeline_cache.render(&device, &scene_pipeline));
+                        render_pass.set_bind_group(0, &bind_groups[1], &[]);
+
+                        /*for i in 0..1*/
+                        {
+                            uniforms.i_pass = 0; // i
+                            uniforms.resolution = [targets.width as f32, targets.height as f32];
+                            queue.write_buffer(&uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
+                            render_pass.draw(0..3, 0..1);
+                        }
                     }
+
+                    mesh_renderer.draw(
+                        &device,
+                        &queue,
+                        &mut pipeline_cache,
+                        &mut encoder,
+                        &targets,
+                        &camera,
+                        &objects,
+                    );
+                    queue.submit(std::iter::once(encoder.finish()));
+                    particles.render(&device, &queue, &mut pipeline_cache, &targets, &camera);
+
+                    let mut encoder =
+                        device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                            label: Some("Temporal anti-aliasing"),
+                        });
+                    depth_resolve.resolve(&device, &mut pipeline_cache, &mut encoder, &targets);
+                    match &offline {
+                        Some(_) => taa.accumulate(
+                            &device,
+                            &queue,
+                            &mut pipeline_cache,
+                            &mut encoder,
+                            &targets,
+                            sample,
+                        ),
+                        None if demo_config.taa => taa.resolve(
+                            &device,
+                            &queue,
+                            &mut pipeline_cache,
+                            &mut encoder,
+                            &targets,
+                            &camera,
+                        ),
+                        None => {}
+                    }
+                    queue.submit(std::iter::once(encoder.finish()));
                 }
 
-                queue.submit(std::iter::once(encoder.finish()));
                 let mut encoder2 = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
                     label: Some("Render Encoder 2"),
                 });
+                let post_output = hdr_post.render(
+                    &device,
+                    &queue,
+                    &mut pipeline_cache,
+                    &mut encoder2,
+                    &targets,
+                    uniforms.time,
+                );
 
+                // The logo and border go on top of the chain, into the other target.
+                let composite_output = 1 - post_output;
                 {
                     let mut render_pass = encoder2.begin_render_pass(&RenderPassDescriptor {
                         label: None,
                         color_attachments: &[Some(RenderPassColorAttachment {
-                            view: &view,
+                            view: match composite_output {
+                                0 => &targets.rt_0,
+                                _ => &targets.rt_1,
+                            },
                             resolve_target: None,
                             ops: Operations {
                                 load: LoadOp::Clear(Color::BLACK),
@@ -425,22 +1075,197 @@ fn main() {
                         })],
                         depth_stencil_attachment: None,
                     });
-                    render_pass.set_pipeline(&render_pipeline);
-                    render_pass.set_bind_group(0, &bind_group_rt, &[]);
+                    render_pass.set_pipeline(pipeline_cache.render(&device, &render_pipeline));
+                    // tex_0 is the end of the post-processing chain.
+                    render_pass.set_bind_group(0, &bind_groups[post_output], &[]);
 
                     /*for i in 1..2*/
                     {
                         uniforms.i_pass = 1; // i
+                        uniforms.resolution = [targets.width as f32, targets.height as f32];
                         queue.write_buffer(&uniforms_buffer, 0, bytemuck::bytes_of(&uniforms));
                         render_pass.draw(0..3, 0..1);
                     }
                 }
+                tone_mapper.draw(
+                    &device,
+                    &queue,
+                    &mut pipeline_cache,
+                    &mut encoder2,
+                    match post_output {
+                        0 => &targets.rt_0,
+                        _ => &targets.rt_1,
+                    },
+                    targets::Viewport::fit(targets.width, targets.height, None),
+                    composite_output,
+                    demo_config.exposure,
+                );
+                let display_output = display_post.render(
+                    &device,
+                    &queue,
+                    &mut pipeline_cache,
+                    &mut encoder2,
+                    &targets,
+                    uniforms.time,
+                );
+                // Letterboxed into the design aspect ratio, the clear leaves the bars black.
+                present.draw(
+                    &device,
+                    &queue,
+                    &mut pipeline_cache,
+                    &mut encoder2,
+                    &view,
+                    targets.viewport,
+                    display_output,
+                    0.0,
+                );
+
+                if debug_draw {
+                    let white = [1.0, 1.0, 1.0, 1.0];
+                    let frame_time = frame_stats.average_frame_time().as_secs_f32() * 1000.0;
+                    let lines = [
+                        format!("TIME {:.2}  FRAME {}", uniforms.time, frame_count),
+                        format!(
+                            "FPS {:.1}  {:.2} MS  MISSED {}",
+                            frame_stats.fps(),
+                            frame_time,
+                            frame_stats.missed()
+                        ),
+                        format!("EFFECT {}", demo_config.effect),
+                        format!(
+                            "TONE MAP {:?}  EXPOSURE {:+.1}",
+                            demo_config.tone_map, demo_config.exposure
+                        )
+                        .to_uppercase(),
+                        format!(
+                            "RENDER {}X{}  {:.0}%  MSAA {}X",
+                            targets.width,
+                            targets.height,
+                            dynamic_scale.scale * 100.0,
+                            targets.sample_count
+                        ),
+                        format!(
+                            "VOLUME {:.1}{}",
+                            playback_volume.volume(),
+                            if playback_volume.is_muted() {
+                                " MUTED"
+                            } else {
+                                ""
+                            }
+                        ),
+                        format!(
+                            "RMS {:.2}  BASS {:.2}  MID {:.2}  TREBLE {:.2}",
+                            features.rms, features.bass, features.mid, features.treble
+                        ),
+                        format!("BEAT {:.2}  COUNT {}", features.beat, features.beats),
+                        format!("LEARN {}", keymap.learning().unwrap_or("OFF")),
+                        format!(
+                            "MOUSE {:.0} {:.0}  SCROLL {:.1}",
+                            mouse.position[0], mouse.position[1], mouse.scroll
+                        ),
+                    ]
+                    .into_iter()
+                    .chain(parameters.iter().map(|parameter| match parameter.kind {
+                        parameters::ParameterKind::Color => format!(
+                            "{} {:.2} {:.2} {:.2}",
+                            parameter.name,
+                            parameter.value[0],
+                            parameter.value[1],
+                            parameter.value[2]
+                        ),
+                        _ => format!("{} {}", parameter.name, parameter.value[0]),
+                    }))
+                    .collect::<Vec<_>>();
+
+                    let margin = 16.0;
+                    overlay.rect(
+                        margin - 4.0,
+                        margin - 4.0,
+                        380.0,
+                        lines.len() as f32 * overlay::LINE_HEIGHT + 4.0,
+                        [0.0, 0.0, 0.0, 0.6],
+                    );
+                    for (i, line) in lines.iter().enumerate() {
+                        let y = margin + i as f32 * overlay::LINE_HEIGHT;
+                        overlay.text(margin, y, line, white);
+                    }
+
+                    // Frame times, full height is two 60 Hz frames.
+                    let frame_times: Vec<f32> = frame_stats
+                        .frame_times()
+                        .map(|frame_time| frame_time.as_secs_f32() * 1000.0)
+                        .collect();
+                    let graph_y = margin + lines.len() as f32 * overlay::LINE_HEIGHT + 8.0;
+                    overlay.graph(
+                        margin - 4.0,
+                        graph_y,
+                        380.0,
+                        60.0,
+                        &frame_times,
+                        33.3,
+                        [0.3, 1.0, 0.4, 0.9],
+                    );
+                    overlay.rect(
+                        margin - 4.0,
+                        graph_y + 30.0,
+                        380.0,
+                        1.0,
+                        [1.0, 1.0, 1.0, 0.5],
+                    );
+
+                    overlay.draw(
+                        &device,
+                        &queue,
+                        &mut pipeline_cache,
+                        &mut encoder2,
+                        &view,
+                        (config.width, config.height),
+                    );
+                }
+
+                #[cfg(feature = "tweak")]
+                tweak_gui.draw(
+                    &window,
+                    &device,
+                    &queue,
+                    &mut encoder2,
+                    &view,
+                    &mut parameters,
+                    PARAMETERS_PATH,
+                );
 
                 queue.submit(std::iter::once(encoder2.finish()));
+                if let Some(offline) = &mut offline {
+                    let captured =
+                        offline.capture(&device, &queue, &mut pipeline_cache, display_output);
+                    if let Err(error) = captured {
+                        eprintln!("{:#}", error);
+                        *control_flow = quit();
+                    }
+                }
                 output.present();
             }
             // New
             Event::MainEventsCleared => {
+                for event in controllers.poll() {
+                    for action in keymap.handle_control(&event) {
+                        match action {
+                            keymap::ControlAction::Command(command) => {
+                                command_buffer.add_command(command)
+                            }
+                            keymap::ControlAction::SetParameter { name, value } => {
+                                parameters.set_normalized(&name, value)
+                            }
+                            keymap::ControlAction::Learned { name, control } => {
+                                println!("Bound {} to {}", control, name);
+                                if let Err(error) = keymap.save(KEYMAP_PATH) {
+                                    eprintln!("Could not save key bindings: {:#}", error);
+                                }
+                            }
+                        }
+                    }
+                }
+
                 while let Some(command) = command_buffer.next_command() {
                     match command {
                         Command::Quit => *control_flow = quit(),
@@ -448,9 +1273,7 @@ fn main() {
                             println!("Play");
                             println!("framecount: {}", frame_count);
                         }
-                        Command::DebugDraw => {
-                            println!("DebugDraw")
-                        }
+                        Command::DebugDraw => debug_draw = !debug_draw,
                         Command::Pause => {
                             println!("Pause!")
                         }
@@ -459,60 +1282,212 @@ fn main() {
                         }
                         Command::PlayReverse => {
                             println!("PlayReverse!")
-                        } //_ => ()
+                        }
+                        Command::IncreaseVolume => playback_volume.increase(),
+                        Command::DecreaseVolume => playback_volume.decrease(),
+                        Command::ToggleMute => playback_volume.toggle_mute(),
+                        Command::ToggleDebugCamera => debug_camera.toggle(),
+                        Command::AddCameraKey if debug_camera.enabled => {
+                            let time = playback.seconds();
+                            let (forward, _, _) = debug_camera.basis();
+                            let position = debug_camera.position;
+                            camera_path.insert(camera_path::Key {
+                                time,
+                                position,
+                                target: [0, 1, 2].map(|axis| position[axis] + forward[axis]),
+                                fov: camera_path.camera(time).fov.to_degrees(),
+                                roll: 0.0,
+                                handle: None,
+                            });
+                            match camera_path.save(CAMERA_PATH) {
+                                Result::Ok(()) => println!("Camera key at {:.2}s", time),
+                                Err(error) => {
+                                    eprintln!("Could not save the camera path: {:#}", error)
+                                }
+                            }
+                        }
+                        Command::AddCameraKey => println!("Turn on the debug camera to add keys"),
+                        #[cfg(debug_assertions)]
+                        Command::ReloadShaders => {
+                            match reload_effect(
+                                &device,
+                                &mut pipeline_cache,
+                                &parameters,
+                                &demo_config.effect,
+                            ) {
+                                Result::Ok(path) => println!("Reloaded {}", path.display()),
+                                Err(error) => eprintln!("{:#}", error),
+                            }
+                        }
+                        #[cfg(not(debug_assertions))]
+                        Command::ReloadShaders => {
+                            println!("Reloading shaders needs a debug build")
+                        }
+                        Command::ToggleFullscreen => {
+                            let fullscreen =
+                                match window.fullscreen() {
+                                    Some(_) => None,
+                                    None => Some(demo_config.fullscreen_mode.fullscreen(
+                                        window.current_monitor(),
+                                        demo_config.resolution,
+                                    )),
+                                };
+                            window.set_fullscreen(fullscreen);
+                        }
+                        Command::LearnControl => {
+                            // Each press moves on to the next parameter, then stops learning.
+                            let next = match keymap.learning() {
+                                None => parameters.iter().next(),
+                                Some(current) => parameters
+                                    .iter()
+                                    .skip_while(|parameter| parameter.name != current)
+                                    .nth(1),
+                            }
+                            .map(|parameter| parameter.name.to_string());
+                            keymap.learn(next);
+                            match keymap.learning() {
+                                Some(name) => println!("Move a control to bind it to {}", name),
+                                None => println!("Learn mode off"),
+                            }
+                        }
+                        #[cfg(feature = "tweak")]
+                        Command::ToggleTweaks => tweak_gui.toggle(),
+                        #[cfg(not(feature = "tweak"))]
+                        Command::ToggleTweaks => println!("Built without the tweak feature"),
                     }
                 }
 
-                let now = Instant::now();
-                let time_delta = now.duration_since(last_update_time);
+                let show_cursor = !demo_config.hide_cursor || debug_draw || debug_camera.enabled;
+                #[cfg(feature = "tweak")]
+                let show_cursor = show_cursor || tweak_gui.is_visible();
+                if show_cursor != cursor_visible {
+                    window.set_cursor_visible(show_cursor);
+                    cursor_visible = show_cursor;
+                }
+
+                // Fade the music out towards the end of the demo.
+                let time = offline
+                    .as_ref()
+                    .map_or_else(|| playback.seconds(), |offline| offline.time());
+                let remaining = music
+                    .duration()
+                    .saturating_sub(Duration::from_secs_f32(time));
+                if !demo_config.looping || offline.is_some() {
+                    if remaining <= MUSIC_FADE_OUT && !playback_volume.is_fading_out() {
+                        playback_volume.fade_out(remaining);
+                    }
+                    if remaining.is_zero() {
+                        command_buffer.add_command(Command::Quit);
+                    }
+                }
+                music_sink.set_volume(playback_volume.gain(Instant::now()));
 
-                if time_delta >= FIXED_TIME_STEP {
-                    // Update logic here.
-                    last_update_time = now;
+                let now = Instant::now();
+                let dt = FIXED_TIME_STEP.as_secs_f32();
+                // The simulation follows the music, the offline render never leaves steps for later.
+                let (time, max_steps) = match &offline {
+                    Some(offline) => (offline.time(), u64::MAX),
+                    None => (playback_clock.seconds(now), simulation::MAX_STEPS_PER_FRAME),
+                };
+                let time = Duration::from_secs_f32(time);
+                // The music looped, the simulation starts over with it.
+                if fixed_step.is_past(time) {
+                    fixed_step = simulation::FixedStep::new(FIXED_TIME_STEP, 0);
+                    scene_simulation = effect_simulation(&demo_config.effect);
+                    particles.reset();
+                }
+                let ticks = fixed_step.advance_to(time, max_steps);
+                for tick in ticks {
+                    debug_camera.update(dt);
+                    if let Some(scene_simulation) = &mut scene_simulation {
+                        scene_simulation.step(tick, dt);
+                    }
+                    particles.step(tick, dt);
 
                     frame_count += 1;
                 }
 
-                window.request_redraw();
+                // The offline render doesn't wait for anything.
+                if offline.is_some() || pacer.frame_due(now) {
+                    window.request_redraw();
+                }
+                // Sleep until the next frame or update is due when the frame rate is capped.
+                if *control_flow != ControlFlow::Exit {
+                    *control_flow = match pacer.next_frame() {
+                        Some(next_frame) if offline.is_none() => ControlFlow::WaitUntil(
+                            next_frame.min(now + fixed_step.until_next_step()),
+                        ),
+                        _ => ControlFlow::Poll,
+                    };
+                }
             }
 
             Event::WindowEvent {
                 event: WindowEvent::CloseRequested,
                 window_id,
             } if window_id == window.id() => command_buffer.add_command(Command::Quit),
+            // Minimized windows report a zero size.
+            Event::WindowEvent {
+                event: WindowEvent::Resized(size),
+                ..
+            }
+            | Event::WindowEvent {
+                event:
+                    WindowEvent::ScaleFactorChanged {
+                        new_inner_size: &mut size,
+                        ..
+                    },
+                ..
+            } if size.width > 0 && size.height > 0 => {
+                config.width = size.width;
+                config.height = size.height;
+                surface.configure(&device, &config);
+                targets_dirty = true;
+            }
+            Event::WindowEvent {
+                event: WindowEvent::ModifiersChanged(modifiers),
+                ..
+            } => keymap.set_modifiers(modifiers),
+            Event::WindowEvent {
+                event: WindowEvent::Focused(false),
+                ..
+            } => keymap.focus_lost(),
             Event::WindowEvent {
                 event: WindowEvent::KeyboardInput { input, .. },
                 ..
             } => {
-                if let Some(command) = handle_keyboard_input(input) {
+                for command in keymap.handle(input) {
                     command_buffer.add_command(command);
                 }
+                if debug_camera.enabled {
+                    debug_camera.key(input);
+                }
             }
-            // handle mouse input here
             Event::WindowEvent {
                 event: WindowEvent::CursorMoved { position, .. },
                 ..
             } => {
-                println!("Mouse moved to ({:?})", position);
+                let previous = mouse.position;
+                mouse.cursor_moved(position);
+                if debug_camera.enabled && mouse.buttons & input::RIGHT_BUTTON != 0 {
+                    debug_camera.look([
+                        mouse.position[0] - previous[0],
+                        mouse.position[1] - previous[1],
+                    ]);
+                }
             }
-            // handle mouse button input here
             Event::WindowEvent {
                 event: WindowEvent::MouseInput { state, button, .. },
                 ..
-            } => {
-                println!(
-                    "Mouse button {:?} is {:?} at {:?}",
-                    button,
-                    state,
-                    Instant::now()
-                );
-            }
-            // handle mouse wheel input here
+            } => mouse.button(button, state),
             Event::WindowEvent {
                 event: WindowEvent::MouseWheel { delta, .. },
                 ..
             } => {
-                println!("Mouse wheel moved {:?} at {:?}", delta, Instant::now());
+                let lines = mouse.wheel(delta);
+                if debug_camera.enabled {
+                    debug_camera.scroll(lines);
+                }
             }
 
             _ => (),
@@ -0,0 +1,83 @@
+struct Frame {
+    view_projection: mat4x4<f32>,
+    camera_position: vec4<f32>,
+    // Towards the light.
+    light_direction: vec4<f32>,
+    light_color: vec4<f32>,
+    ambient: vec4<f32>,
+};
+
+struct Object {
+    model: mat4x4<f32>,
+    // Inverse transpose of the model's rotation and scale.
+    normal: mat4x4<f32>,
+    base_color: vec4<f32>,
+    // metallic, roughness
+    material: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> frame: Frame;
+@group(1) @binding(0) var<uniform> object: Object;
+@group(2) @binding(0) var base_color_texture: texture_2d<f32>;
+@group(2) @binding(1) var base_color_sampler: sampler;
+
+const pi = 3.1415926539;
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) world: vec3<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) uv: vec2<f32>,
+};
+
+@vertex
+fn vs_main(in: VertexInput) -> VertexOutput {
+    var out: VertexOutput;
+    let world = object.model * vec4<f32>(in.position, 1.0);
+    out.position = frame.view_projection * world;
+    out.world = world.xyz;
+    out.normal = (object.normal * vec4<f32>(in.normal, 0.0)).xyz;
+    out.uv = in.uv;
+    return out;
+}
+
+// Cook-Torrance with GGX, Smith-Schlick and Fresnel-Schlick.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let n = normalize(in.normal);
+    let v = normalize(frame.camera_position.xyz - in.world);
+    let l = normalize(frame.light_direction.xyz);
+    let h = normalize(v + l);
+
+    let metallic = object.material.x;
+    let roughness = max(object.material.y, 0.04);
+    let base_color = object.base_color * textureSample(base_color_texture, base_color_sampler, in.uv);
+    let albedo = base_color.rgb;
+
+    let n_dot_l = max(dot(n, l), 0.0);
+    let n_dot_v = max(dot(n, v), 0.0001);
+    let n_dot_h = max(dot(n, h), 0.0);
+
+    let a = roughness * roughness;
+    let a2 = a * a;
+    let d = n_dot_h * n_dot_h * (a2 - 1.0) + 1.0;
+    let distribution = a2 / (pi * d * d);
+
+    let k = (roughness + 1.0) * (roughness + 1.0) / 8.0;
+    let geometry = n_dot_v / (n_dot_v * (1.0 - k) + k) * n_dot_l / (n_dot_l * (1.0 - k) + k);
+
+    let f0 = mix(vec3<f32>(0.04), albedo, metallic);
+    let fresnel = f0 + (1.0 - f0) * pow(1.0 - max(dot(h, v), 0.0), 5.0);
+
+    let specular = distribution * geometry * fresnel / (4.0 * n_dot_v * max(n_dot_l, 0.0001));
+    let diffuse = (1.0 - fresnel) * (1.0 - metallic) * albedo / pi;
+
+    let color = (diffuse + specular) * frame.light_color.rgb * n_dot_l + frame.ambient.rgb * albedo;
+    return vec4<f32>(color, base_color.a);
+}
@@ -0,0 +1,423 @@
+// Polygonal objects drawn into the scene target after the raymarched pass,
+// depth tested against the depth it wrote so the two intersect.
+//
+// Shading is Cook-Torrance with a single directional light and a flat
+// ambient term. Objects are rebuilt every frame, each gets its own slot of
+// one uniform buffer, which grows when a frame has more of them.
+
+use wgpu::util::DeviceExt;
+
+use crate::camera::{self, Camera, Matrix};
+use crate::pipelines::{DepthKey, PipelineCache, RenderKey};
+use crate::resource;
+use crate::targets::{RenderTargets, DEPTH_FORMAT};
+
+// Slots of the object buffer to start with.
+const INITIAL_OBJECTS: usize = 64;
+const LIGHT_DIRECTION: [f32; 3] = [0.4, 0.8, -0.45];
+const LIGHT_COLOR: [f32; 3] = [3.0, 2.8, 2.6];
+const AMBIENT: [f32; 3] = [0.05, 0.04, 0.06];
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+    0 => Float32x3,
+    1 => Float32x3,
+    2 => Float32x2,
+];
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    pub uv: [f32; 2],
+}
+
+pub struct Mesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    index_count: u32,
+}
+
+impl Mesh {
+    pub fn new(device: &wgpu::Device, label: &str, vertices: &[Vertex], indices: &[u32]) -> Self {
+        Self {
+            vertex_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(vertices),
+                usage: wgpu::BufferUsages::VERTEX,
+            }),
+            index_buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some(label),
+                contents: bytemuck::cast_slice(indices),
+                usage: wgpu::BufferUsages::INDEX,
+            }),
+            index_count: indices.len() as u32,
+        }
+    }
+
+    /// From -1 to 1 on every axis, with a face normal per side.
+    pub fn cube(device: &wgpu::Device) -> Self {
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        for axis in 0..3 {
+            for sign in [1.0, -1.0] {
+                let mut normal = [0.0; 3];
+                normal[axis] = sign;
+                // Two axes along the face, ordered so the winding faces out.
+                let (u, v) = if sign > 0.0 {
+                    ((axis + 1) % 3, (axis + 2) % 3)
+                } else {
+                    ((axis + 2) % 3, (axis + 1) % 3)
+                };
+
+                let first = vertices.len() as u32;
+                for (corner_u, corner_v) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+                    let mut position = normal;
+                    position[u] = corner_u;
+                    position[v] = corner_v;
+                    vertices.push(Vertex {
+                        position,
+                        normal,
+                        uv: [(corner_u + 1.0) * 0.5, (1.0 - corner_v) * 0.5],
+                    });
+                }
+                indices.extend([0, 1, 2, 0, 2, 3].map(|index| first + index));
+            }
+        }
+        Self::new(device, "cube", &vertices, &indices)
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Material {
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+}
+
+pub struct Object<'a> {
+    pub mesh: &'a Mesh,
+    pub transform: Matrix,
+    pub material: Material,
+    /// Base color texture from `MeshRenderer::texture_bind_group`, white without one.
+    pub texture: Option<&'a wgpu::BindGroup>,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Frame {
+    view_projection: Matrix,
+    camera_position: [f32; 4],
+    light_direction: [f32; 4],
+    light_color: [f32; 4],
+    ambient: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct ObjectUniforms {
+    model: Matrix,
+    normal: Matrix,
+    base_color: [f32; 4],
+    material: [f32; 4],
+}
+
+pub struct MeshRenderer {
+    pipeline: RenderKey,
+    frame_buffer: wgpu::Buffer,
+    frame_bind_group: wgpu::BindGroup,
+    object_stride: u64,
+    object_layout: wgpu::BindGroupLayout,
+    object_capacity: usize,
+    object_buffer: wgpu::Buffer,
+    object_bind_group: wgpu::BindGroup,
+    texture_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+    white: wgpu::BindGroup,
+}
+
+impl MeshRenderer {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        let frame_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("mesh frame"),
+            size: std::mem::size_of::<Frame>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let object_stride =
+            (std::mem::size_of::<ObjectUniforms>() as u64).next_multiple_of(alignment);
+        let layout = |label, dynamic| {
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                label: Some(label),
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX_FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: dynamic,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+            })
+        };
+        let frame_layout = layout("mesh frame bind group layout", false);
+        let object_layout = layout("mesh object bind group layout", true);
+
+        let frame_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("mesh frame bind group"),
+            layout: &frame_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: frame_buffer.as_entire_binding(),
+            }],
+        });
+        let (object_buffer, object_bind_group) =
+            create_object_buffer(device, &object_layout, object_stride, INITIAL_OBJECTS);
+
+        let texture_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mesh texture bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mesh texture sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let white_texture = device.create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("white"),
+                size: wgpu::Extent3d {
+                    width: 1,
+                    height: 1,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8UnormSrgb,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING,
+                view_formats: &[],
+            },
+            &[255; 4],
+        );
+        let white = create_texture_bind_group(
+            device,
+            &texture_layout,
+            &white_texture.create_view(&Default::default()),
+            &sampler,
+        );
+
+        pipelines.add_shader(device, "mesh", include_str!("mesh.wgsl"));
+        pipelines.add_layout(
+            device,
+            "mesh",
+            &[&frame_layout, &object_layout, &texture_layout],
+        );
+        let pipeline = RenderKey {
+            shader: "mesh",
+            layout: "mesh",
+            vertex: "vs_main",
+            vertex_buffers: vec![wgpu::VertexBufferLayout {
+                array_stride: std::mem::size_of::<Vertex>() as u64,
+                step_mode: wgpu::VertexStepMode::Vertex,
+                attributes: &VERTEX_ATTRIBUTES,
+            }],
+            fragment: "fs_main",
+            targets: vec![wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+            primitive: wgpu::PrimitiveState {
+                // Scenes are left handed, x right, y up and z forward, which turns
+                // counter-clockwise faces clockwise on screen.
+                front_face: wgpu::FrontFace::Cw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth: Some(DepthKey {
+                format: DEPTH_FORMAT,
+                write: true,
+                compare: wgpu::CompareFunction::Greater,
+            }),
+            sample_count,
+        };
+
+        Self {
+            pipeline,
+            frame_buffer,
+            frame_bind_group,
+            object_stride,
+            object_layout,
+            object_capacity: INITIAL_OBJECTS,
+            object_buffer,
+            object_bind_group,
+            texture_layout,
+            sampler,
+            white,
+        }
+    }
+
+    pub fn texture_bind_group(
+        &self,
+        device: &wgpu::Device,
+        texture: &resource::Texture,
+    ) -> wgpu::BindGroup {
+        create_texture_bind_group(device, &self.texture_layout, &texture.view, &self.sampler)
+    }
+
+    /// Draws `objects` on top of rt_0, testing against and writing to the depth.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargets,
+        camera: &Camera,
+        objects: &[Object],
+    ) {
+        if objects.is_empty() {
+            return;
+        }
+        if objects.len() > self.object_capacity {
+            self.object_capacity = objects.len().next_power_of_two();
+            (self.object_buffer, self.object_bind_group) = create_object_buffer(
+                device,
+                &self.object_layout,
+                self.object_stride,
+                self.object_capacity,
+            );
+        }
+
+        let aspect = targets.width as f32 / targets.height as f32;
+        let [x, y, z] = camera.position;
+        let [lx, ly, lz] = LIGHT_DIRECTION;
+        let [r, g, b] = LIGHT_COLOR;
+        let [ar, ag, ab] = AMBIENT;
+        let frame = Frame {
+            view_projection: camera::multiply(camera.projection(aspect), camera.view()),
+            camera_position: [x, y, z, 1.0],
+            light_direction: [lx, ly, lz, 0.0],
+            light_color: [r, g, b, 0.0],
+            ambient: [ar, ag, ab, 0.0],
+        };
+        queue.write_buffer(&self.frame_buffer, 0, bytemuck::bytes_of(&frame));
+
+        let mut data = vec![0u8; self.object_stride as usize * objects.len()];
+        for (slot, object) in objects.iter().enumerate() {
+            let uniforms = ObjectUniforms {
+                model: object.transform,
+                normal: camera::normal_matrix(object.transform),
+                base_color: object.material.base_color,
+                material: [
+                    object.material.metallic,
+                    object.material.roughness,
+                    0.0,
+                    0.0,
+                ],
+            };
+            let offset = slot * self.object_stride as usize;
+            data[offset..offset + std::mem::size_of::<ObjectUniforms>()]
+                .copy_from_slice(bytemuck::bytes_of(&uniforms));
+        }
+        queue.write_buffer(&self.object_buffer, 0, &data);
+
+        let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("meshes"),
+            color_attachments: &[Some(targets.color_attachment(wgpu::LoadOp::Load))],
+            depth_stencil_attachment: Some(targets.depth_attachment(wgpu::LoadOp::Load)),
+        });
+        pass.set_pipeline(pipelines.render(device, &self.pipeline));
+        pass.set_bind_group(0, &self.frame_bind_group, &[]);
+        for (slot, object) in objects.iter().enumerate() {
+            let offset = (slot as u64 * self.object_stride) as u32;
+            pass.set_bind_group(1, &self.object_bind_group, &[offset]);
+            pass.set_bind_group(2, object.texture.unwrap_or(&self.white), &[]);
+            pass.set_vertex_buffer(0, object.mesh.vertex_buffer.slice(..));
+            pass.set_index_buffer(
+                object.mesh.index_buffer.slice(..),
+                wgpu::IndexFormat::Uint32,
+            );
+            pass.draw_indexed(0..object.mesh.index_count, 0, 0..1);
+        }
+    }
+}
+
+fn create_object_buffer(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    stride: u64,
+    capacity: usize,
+) -> (wgpu::Buffer, wgpu::BindGroup) {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("mesh objects"),
+        size: stride * capacity as u64,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+    let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("mesh object bind group"),
+        layout,
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                buffer: &buffer,
+                offset: 0,
+                size: wgpu::BufferSize::new(std::mem::size_of::<ObjectUniforms>() as u64),
+            }),
+        }],
+    });
+    (buffer, bind_group)
+}
+
+fn create_texture_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    view: &wgpu::TextureView,
+    sampler: &wgpu::Sampler,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: Some("mesh texture bind group"),
+        layout,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: wgpu::BindingResource::Sampler(sampler),
+            },
+        ],
+    })
+}
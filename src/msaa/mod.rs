@@ -0,0 +1,171 @@
+// Multisampling for the geometry passes. The raymarched scene, meshes and
+// particles draw into multisampled color and depth, every pass resolves the
+// color into rt_0 and a pass of its own resolves the depth for the temporal
+// anti-aliasing, keeping the nearest sample.
+//
+// The raymarcher still shades once per pixel, only mesh and particle edges
+// get smoother.
+
+use crate::pipelines::{DepthKey, PipelineCache, RenderKey};
+use crate::targets::{RenderTargets, DEPTH_FORMAT, HDR_FORMAT};
+
+/// The highest count up to `requested` that the adapter can render and
+/// resolve in the scene formats, 1 when none of them.
+pub fn supported_sample_count(
+    adapter: &wgpu::Adapter,
+    features: wgpu::Features,
+    requested: u32,
+) -> u32 {
+    let supported = |count: u32| {
+        // Without adapter specific format features only 4 is guaranteed.
+        if !features.contains(wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES) {
+            return count == 4;
+        }
+        let color = adapter.get_texture_format_features(HDR_FORMAT).flags;
+        let depth = adapter.get_texture_format_features(DEPTH_FORMAT).flags;
+        color.sample_count_supported(count)
+            && color.contains(wgpu::TextureFormatFeatureFlags::MULTISAMPLE_RESOLVE)
+            && depth.sample_count_supported(count)
+    };
+    [16, 8, 4, 2]
+        .into_iter()
+        .filter(|&count| count <= requested)
+        .find(|&count| supported(count))
+        .unwrap_or(1)
+}
+
+pub fn multisample_state(sample_count: u32) -> wgpu::MultisampleState {
+    wgpu::MultisampleState {
+        count: sample_count,
+        mask: !0,
+        alpha_to_coverage_enabled: false,
+    }
+}
+
+/// Writes the nearest sample of the multisampled depth into `targets.depth`.
+pub struct DepthResolve {
+    layout: wgpu::BindGroupLayout,
+    pipeline: RenderKey,
+    sample_count_buffer: wgpu::Buffer,
+    bind_group: Option<wgpu::BindGroup>,
+}
+
+impl DepthResolve {
+    pub fn new(device: &wgpu::Device, pipelines: &mut PipelineCache) -> Self {
+        pipelines.add_shader(
+            device,
+            "msaa",
+            concat!(
+                include_str!("../fullscreen.wgsl"),
+                include_str!("msaa.wgsl")
+            ),
+        );
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("depth resolve"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: true,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        pipelines.add_layout(device, "msaa", &[&layout]);
+
+        Self {
+            layout,
+            // Only the depth is written.
+            pipeline: RenderKey {
+                shader: "msaa",
+                layout: "msaa",
+                vertex: "vs_main",
+                vertex_buffers: Vec::new(),
+                fragment: "fs_resolve_depth",
+                targets: Vec::new(),
+                primitive: wgpu::PrimitiveState::default(),
+                depth: Some(DepthKey {
+                    format: DEPTH_FORMAT,
+                    write: true,
+                    compare: wgpu::CompareFunction::Always,
+                }),
+                sample_count: 1,
+            },
+            // Padded to the minimum uniform size of GLES.
+            sample_count_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("depth resolve sample count"),
+                size: 16,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            bind_group: None,
+        }
+    }
+
+    /// Reads the multisampled depth of new render targets, if they have one.
+    pub fn resize(&mut self, device: &wgpu::Device, queue: &wgpu::Queue, targets: &RenderTargets) {
+        queue.write_buffer(
+            &self.sample_count_buffer,
+            0,
+            bytemuck::bytes_of(&[targets.sample_count, 0, 0, 0]),
+        );
+        self.bind_group = targets.msaa_depth.as_ref().map(|view| {
+            device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("depth resolve"),
+                layout: &self.layout,
+                entries: &[
+                    wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: wgpu::BindingResource::TextureView(view),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 1,
+                        resource: self.sample_count_buffer.as_entire_binding(),
+                    },
+                ],
+            })
+        });
+    }
+
+    /// Does nothing without multisampling, the geometry passes wrote `targets.depth` directly.
+    pub fn resolve(
+        &self,
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargets,
+    ) {
+        let Some(bind_group) = &self.bind_group else {
+            return;
+        };
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("depth resolve"),
+            color_attachments: &[],
+            depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+                view: &targets.depth,
+                depth_ops: Some(wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(0.0),
+                    store: true,
+                }),
+                stencil_ops: None,
+            }),
+        });
+        render_pass.set_pipeline(pipelines.render(device, &self.pipeline));
+        render_pass.set_bind_group(0, bind_group, &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
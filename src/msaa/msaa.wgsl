@@ -0,0 +1,14 @@
+@group(0) @binding(0) var depth: texture_multisampled_2d<f32>;
+// textureNumSamples isn't available on GLES.
+@group(0) @binding(1) var<uniform> sample_count: u32;
+
+// Depth is reversed, the nearest sample is the largest.
+@fragment
+fn fs_resolve_depth(in: VertexOutput) -> @builtin(frag_depth) f32 {
+    let pixel = vec2<i32>(in.position.xy);
+    var nearest = 0.0;
+    for (var sample = 0; sample < i32(sample_count); sample++) {
+        nearest = max(nearest, textureLoad(depth, pixel, sample).r);
+    }
+    return nearest;
+}
@@ -24,6 +24,22 @@ struct Uniforms {
     resolution : vec2<f32>,
     time : f32,
     i_pass : i32,
+    // rms, bass, mid, treble
+    audio : vec4<f32>,
+    // beat envelope, beat count
+    beat : vec4<f32>,
+    // Shadertoy's iMouse
+    mouse : vec4<f32>,
+    // normalized position, button mask, scroll
+    cursor : vec4<f32>,
+    // effect or debug camera, w of position is 1 while the debug camera is on
+    camera_position : vec4<f32>,
+    view : mat4x4<f32>,
+    projection : mat4x4<f32>,
+    // fixed-step tick, interpolation alpha between ticks, step length in seconds
+    simulation : vec4<f32>,
+    // song row, pattern in the order list, row within the pattern
+    song : vec4<f32>,
 };
 
 const epsilon = 0.0001;
@@ -35,6 +51,9 @@ const pi = 3.1415926539;
 @group(0) @binding(3) var tex_1: texture_2d<f32>;
 @group(0) @binding(4) var tex_2: texture_2d<f32>;
 @group(0) @binding(5) var tex_3: texture_2d<f32>;
+@group(0) @binding(6) var spectrum: texture_2d<f32>;
+// Params is generated from the parameters declared in main.rs.
+@group(0) @binding(7) var<uniform> params: Params;
 
 
 fn rotate2D(plane: vec2<f32>, angle: f32) -> vec2<f32> {
@@ -69,8 +88,8 @@ fn not_menger(pos: vec3<f32>) -> f32 {
     q = rotate3D(q, vec3<f32>(0.0, 1.0, 0.0), u.time * 0.7);
     q = rotate3D(q, vec3<f32>(1.0, 0.0, 0.0), u.time * 0.5);
 
-    let scale = 3.0;
-    let iteration = 4.0;
+    let scale = params.scale.x;
+    let iteration = params.iteration.x;
     let offset = 0.0;
 
     var p = abs(fract(q / scale) * scale - scale * 0.5);    
@@ -94,7 +113,7 @@ fn raymarch(origin: vec3<f32>, direction: vec3<f32>) -> vec2<f32> {
     var t = 0.0;
     var near_miss = 0.0;
 
-    for(var i: i32 = 0; i < 40; i++) {
+    for(var i: i32 = 0; i < i32(params.steps.x); i++) {
     	let pos = vec3<f32>(origin + t * direction);
         var dist = not_menger(pos);
 
@@ -139,89 +158,98 @@ fn solar_logo(pos: vec2<f32>) -> f32 {
     
 }
 
-fn rand(n: f32) -> f32 {return fract(sin(n) * 43758.5453123);}
-fn my_mod(x: f32, y: f32) -> f32 {return x - y * floor(x / y);}
-fn vhs(tex: texture_2d<f32>, samp: sampler, coords: vec2<f32>, amount: f32, spd: f32, t: f32) -> vec4<f32> { 
-    var scale = amount * 0.7314;
-    var uv = coords;
-    var inner = t * 33.14 * cos(spd * 6.28 + 0.4 * rand(scale));
-    var speed = floor(my_mod(inner, 128.0));
-    
-    var ln = 1.628*rand(speed * 0.5678) * 1.0;
-    var width = 1.33*rand(speed+t) * 0.25 * 1.0;    
-    var offset = 0.0;
-
-    uv.x += -0.00628 + 0.009*fract(ln);
-    uv.y -= -0.00428 + 0.008*fract(ln*ln);
-
-    var color: vec3<f32> = textureSample(tex, samp, uv).rgb;
-    var abberated = 0.00314*length(uv)+0.31415*abs(offset);
-    
-    color.r = textureSample(tex, samp, uv + abberated).r;  
-    color.g = textureSample(tex, samp, uv).g;
-    color.b = textureSample(tex, samp, uv - abberated).b;  
-
-    return vec4<f32>(color,length(color));
-}
 /*
 fn overlay_texcoords(coords: vec4) -> vec2 {
 
 }
 */
+struct SceneOutput {
+    @location(0) color: vec4<f32>,
+    @builtin(frag_depth) depth: f32,
+};
+
+// camera::NEAR, depth is near / z like the mesh projection.
+const near = 0.05;
+
+fn border(tex_coords: vec2<f32>) -> bool {
+    return tex_coords.y < 0.01 || tex_coords.y > 0.99 || tex_coords.x < 0.01 || tex_coords.x > 0.99;
+}
+
+// Raymarched scene into rt_0, with depth so meshes and particles intersect it.
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+fn fs_scene(in: VertexOutput) -> SceneOutput {
     let uv = (in.tex_coords.xy * u.resolution.xy * 2.0 - u.resolution.xy) / u.resolution.x;
     var color = vec3<f32>(0.0);
-    if (u.i_pass == 0) {
-        let pulse = 5.0 * -u.time;//5.0 * sin(0.5 * u.time);
-        var campos = vec3<f32>(0.1 * sin(u.time), 0.12 * cos(u.time * 0.7), 0.0);
-        var camdir = vec3<f32>(0.0, 0.0, 1.0);
-        let ratio = u.resolution.y / u.resolution.x;
-        let u = vec3<f32>(1.0, 0.0, 0.0);
-        var v = vec3<f32>(0.0, 1.0, 0.0);
-
-        var ro = campos;
-        var rd = normalize(camdir + uv.x * u + uv.y * v);
-
-        var dist = raymarch(ro, rd);
-
-        if (dist.x > 0.0) {
-            var p = ro + dist.x * rd;
-            var N = get_normal(p);
-            var L = -rd;
-            
-            var c0 = vec3<f32>(0.7,0.2,0.3) * N.y;
-            color = c0;
-            
-            var c1 = vec3<f32>(0.2,0.1,0.3) * -N.z;
-            color += c1;
-            color = -color + dist.y;
-            color *= color;
-            
-            var sheen = vec3<f32>(dist.y * 10.0) * dist.y * dist.y * 100.0;
-            color *= sheen;
-            color = mix(sheen * 0.8, color, 0.7 + 0.3 * pow(2.0, sin(dist.x * 0.5 + pulse)));
-        }
-    
-        var logo = solar_logo(uv * 1.0);
-        if (logo < 0.0) {
-            color = max(color, vec3<f32>(0.5));
-        }
+    // Half a wave per beat, eased by the beat envelope.
+    let pulse = -pi * (u.beat.y + 1.0 - u.beat.x);
+    // Undo the projection for a camera space ray, the view rotation is
+    // orthonormal so its transpose takes it back to world space.
+    let ndc = in.tex_coords.xy * 2.0 - 1.0;
+    let jitter = u.projection[2].xy;
+    let view_ray = vec3<f32>(
+        (ndc.x - jitter.x) / u.projection[0].x,
+        (ndc.y - jitter.y) / u.projection[1].y,
+        1.0,
+    );
+    let to_world = transpose(mat3x3<f32>(u.view[0].xyz, u.view[1].xyz, u.view[2].xyz));
+    let camdir = to_world[2];
+
+    var ro = u.camera_position.xyz;
+    var rd = normalize(to_world * view_ray);
+
+    var dist = raymarch(ro, rd);
+    // Nothing hit is infinitely far away.
+    var depth = 0.0;
+
+    if (dist.x > 0.0) {
+        var p = ro + dist.x * rd;
+        depth = near / (dist.x * dot(rd, camdir));
+        var N = get_normal(p);
+        var L = -rd;
+        
+        var c0 = params.color_a.rgb * N.y;
+        color = c0;
+        
+        var c1 = params.color_b.rgb * -N.z;
+        color += c1;
+        color = -color + dist.y;
+        color *= color;
+        
+        var sheen = vec3<f32>(dist.y * 10.0) * dist.y * dist.y * params.sheen.x;
+        color *= sheen;
+        color = mix(sheen * 0.8, color, 0.7 + 0.3 * pow(2.0, sin(dist.x * 0.5 + pulse)));
+    }
+
+    var logo = solar_logo(uv * 1.0);
+    if (logo < 0.0) {
+        let band = i32(clamp(atan2(uv.y, uv.x) / pi * 0.5 + 0.5, 0.0, 1.0) * f32(textureDimensions(spectrum).x - 1u));
+        let level = textureLoad(spectrum, vec2<i32>(band, 0), 0).r;
+        color = max(color, vec3<f32>(0.4 + 0.4 * level));
+        // In front of everything in the scene.
+        depth = 1.0;
+    }
 
+    if (border(in.tex_coords)) {
+        color = vec3<f32>(0.0);
+        depth = 1.0;
     }
 
+    return SceneOutput(vec4<f32>(color, 1.), depth);
+}
 
-    else if (u.i_pass == 1) {
-        let tex_color: vec4<f32> = vhs(tex_0, samp, vec2<f32>(1.0, -1.0) * in.tex_coords.xy, 0.1, 3.0, u.time);
+// Logo and border on top of the post-processing chain, still in HDR.
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    // The end of the post-processing chain.
+    let tex_color = textureSample(tex_0, samp, vec2<f32>(1.0, -1.0) * in.tex_coords.xy);
 
-        let logo: vec4<f32> = textureSample(tex_3, samp, vec2<f32>(1.0, -0.75) * in.tex_coords.xy - vec2<f32>(0.0, 0.15));
+    let logo: vec4<f32> = textureSample(tex_3, samp, vec2<f32>(1.0, -0.75) * in.tex_coords.xy - vec2<f32>(0.0, 0.15));
 
-        color = tex_color.rgb + logo.rgb;
-    }
+    var color = tex_color.rgb + logo.rgb;
 
-    if (in.tex_coords.y < 0.01 || in.tex_coords.y > 0.99 || in.tex_coords.x < 0.01 || in.tex_coords.x > 0.99) {
+    if (border(in.tex_coords)) {
         color = vec3<f32>(0.0);
     }
 
     return vec4<f32>(color, 1.);
-}
\ No newline at end of file
+}
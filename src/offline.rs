@@ -0,0 +1,142 @@
+// Offline rendering to numbered PNGs, for encoding a video afterwards. Time
+// steps by whole frames instead of following the music, however long a frame
+// takes, and every frame averages a number of jittered samples so edges and
+// fine detail come out clean.
+
+use anyhow::*;
+use std::path::PathBuf;
+
+use crate::pipelines::PipelineCache;
+use crate::targets::{RenderTargets, Viewport};
+use crate::tonemap::ToneMapper;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+pub struct OfflineRender {
+    directory: PathBuf,
+    fps: f32,
+    start: f32,
+    pub samples: u32,
+    // Frames written so far.
+    frame: u64,
+    tone_mapper: ToneMapper,
+    texture: Option<wgpu::Texture>,
+}
+
+impl OfflineRender {
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        directory: PathBuf,
+        fps: f32,
+        samples: u32,
+        start: f32,
+    ) -> Result<Self> {
+        std::fs::create_dir_all(&directory)
+            .with_context(|| format!("Failed to create {}", directory.display()))?;
+        Ok(Self {
+            directory,
+            fps,
+            start,
+            samples,
+            frame: 0,
+            tone_mapper: ToneMapper::new(device, pipelines, FORMAT, None),
+            texture: None,
+        })
+    }
+
+    /// Demo time of the next frame.
+    pub fn time(&self) -> f32 {
+        self.start + self.frame as f32 / self.fps
+    }
+
+    /// Frames are as large as the viewport, without the black bars.
+    pub fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        self.tone_mapper.resize(device, targets);
+        self.texture = Some(device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offline frame"),
+            size: wgpu::Extent3d {
+                width: targets.viewport.width as u32,
+                height: targets.viewport.height as u32,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: FORMAT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        }));
+    }
+
+    /// Writes rt_0 or rt_1 by `source`, tone mapped already, as the next frame.
+    pub fn capture(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        source: usize,
+    ) -> Result<()> {
+        let texture = self
+            .texture
+            .as_ref()
+            .context("Offline render without targets")?;
+        let (width, height) = (texture.width(), texture.height());
+        let viewport = Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: width as f32,
+            height: height as f32,
+        };
+        let row = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offline frame"),
+            size: (row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("offline frame"),
+        });
+        self.tone_mapper.draw(
+            device,
+            queue,
+            pipelines,
+            &mut encoder,
+            &texture.create_view(&Default::default()),
+            viewport,
+            source,
+            0.0,
+        );
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(row),
+                    rows_per_image: None,
+                },
+            },
+            texture.size(),
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let slice = buffer.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |_| {});
+        device.poll(wgpu::Maintain::Wait);
+        let pixels: Vec<u8> = slice
+            .get_mapped_range()
+            .chunks(row as usize)
+            .flat_map(|line| &line[..width as usize * 4])
+            .copied()
+            .collect();
+
+        let path = self.directory.join(format!("frame_{:05}.png", self.frame));
+        image::save_buffer(&path, &pixels, width, height, image::ColorType::Rgba8)
+            .with_context(|| format!("Failed to write {}", path.display()))?;
+        self.frame += 1;
+        Ok(())
+    }
+}
@@ -0,0 +1,283 @@
+// Debug overlay: text and rectangles drawn on top of the final image.
+//
+// Everything is an instanced quad, glyphs sample the built-in font atlas and
+// solid quads skip it. Quads are collected during the frame and drawn and
+// cleared by `draw`.
+
+mod font;
+
+use wgpu::util::DeviceExt;
+
+use crate::pipelines::{PipelineCache, RenderKey};
+use font::{GLYPHS, GLYPH_HEIGHT, GLYPH_WIDTH};
+
+const SOLID: u32 = u32::MAX;
+// Screen pixels per font pixel.
+const SCALE: f32 = 2.0;
+
+pub const LINE_HEIGHT: f32 = (GLYPH_HEIGHT + 2) as f32 * SCALE;
+const ADVANCE: f32 = (GLYPH_WIDTH + 1) as f32 * SCALE;
+const QUAD_ATTRIBUTES: [wgpu::VertexAttribute; 3] = wgpu::vertex_attr_array![
+    0 => Float32x4,
+    1 => Float32x4,
+    2 => Uint32,
+];
+
+/// Width of `text` drawn with `Overlay::text`.
+pub fn text_width(text: &str) -> f32 {
+    text.chars().count() as f32 * ADVANCE
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Quad {
+    rect: [f32; 4],
+    color: [f32; 4],
+    glyph: u32,
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Screen {
+    size: [f32; 2],
+    _pad: [f32; 2],
+}
+
+pub struct Overlay {
+    pipeline: RenderKey,
+    bind_group: wgpu::BindGroup,
+    screen_buffer: wgpu::Buffer,
+    quads: Vec<Quad>,
+}
+
+impl Overlay {
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        format: wgpu::TextureFormat,
+    ) -> Self {
+        // All glyphs side by side in a single row.
+        let atlas_width = GLYPHS.len() as u32 * GLYPH_WIDTH;
+        let mut atlas = vec![0u8; (atlas_width * GLYPH_HEIGHT) as usize];
+        for (index, (_, rows)) in GLYPHS.iter().enumerate() {
+            for (y, row) in rows.iter().enumerate() {
+                for x in 0..GLYPH_WIDTH {
+                    if row & (1 << (GLYPH_WIDTH - 1 - x)) != 0 {
+                        let texel = y as u32 * atlas_width + index as u32 * GLYPH_WIDTH + x;
+                        atlas[texel as usize] = 255;
+                    }
+                }
+            }
+        }
+
+        let atlas_size = wgpu::Extent3d {
+            width: atlas_width,
+            height: GLYPH_HEIGHT,
+            depth_or_array_layers: 1,
+        };
+        let font_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("overlay font"),
+            size: atlas_size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::R8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            font_texture.as_image_copy(),
+            &atlas,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(atlas_width),
+                rows_per_image: None,
+            },
+            atlas_size,
+        );
+        let font_view = font_texture.create_view(&Default::default());
+
+        let screen_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("overlay screen"),
+            size: std::mem::size_of::<Screen>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("overlay bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: false },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("overlay bind group"),
+            layout: &bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: screen_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&font_view),
+                },
+            ],
+        });
+
+        pipelines.add_shader(device, "overlay", include_str!("overlay.wgsl"));
+        pipelines.add_layout(device, "overlay", &[&bind_group_layout]);
+
+        Self {
+            pipeline: RenderKey {
+                shader: "overlay",
+                layout: "overlay",
+                vertex: "vs_main",
+                vertex_buffers: vec![wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Quad>() as u64,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &QUAD_ATTRIBUTES,
+                }],
+                fragment: "fs_main",
+                targets: vec![wgpu::ColorTargetState {
+                    format,
+                    blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                    write_mask: wgpu::ColorWrites::ALL,
+                }],
+                primitive: wgpu::PrimitiveState {
+                    topology: wgpu::PrimitiveTopology::TriangleStrip,
+                    ..Default::default()
+                },
+                depth: None,
+                sample_count: 1,
+            },
+            bind_group,
+            screen_buffer,
+            quads: Vec::new(),
+        }
+    }
+
+    pub fn rect(&mut self, x: f32, y: f32, width: f32, height: f32, color: [f32; 4]) {
+        self.quads.push(Quad {
+            rect: [x, y, width, height],
+            color,
+            glyph: SOLID,
+        });
+    }
+
+    /// Draws a single line of text with its top left corner at `x`, `y`.
+    pub fn text(&mut self, x: f32, y: f32, text: &str, color: [f32; 4]) {
+        for (i, c) in text.chars().enumerate() {
+            if c == ' ' {
+                continue;
+            }
+            self.quads.push(Quad {
+                rect: [
+                    x + i as f32 * ADVANCE,
+                    y,
+                    GLYPH_WIDTH as f32 * SCALE,
+                    GLYPH_HEIGHT as f32 * SCALE,
+                ],
+                color,
+                glyph: font::glyph_index(c),
+            });
+        }
+    }
+
+    /// Bar graph of `values` on a dark background, bars reach the top at `max`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn graph(
+        &mut self,
+        x: f32,
+        y: f32,
+        width: f32,
+        height: f32,
+        values: &[f32],
+        max: f32,
+        color: [f32; 4],
+    ) {
+        self.rect(x, y, width, height, [0.0, 0.0, 0.0, 0.6]);
+        if values.is_empty() {
+            return;
+        }
+
+        let bar_width = width / values.len() as f32;
+        for (i, value) in values.iter().enumerate() {
+            let bar_height = (value / max).clamp(0.0, 1.0) * height;
+            self.rect(
+                x + i as f32 * bar_width,
+                y + height - bar_height,
+                bar_width.max(1.0),
+                bar_height,
+                color,
+            );
+        }
+    }
+
+    /// Draws and clears the collected quads on top of `view`.
+    pub fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        size: (u32, u32),
+    ) {
+        if self.quads.is_empty() {
+            return;
+        }
+
+        let screen = Screen {
+            size: [size.0 as f32, size.1 as f32],
+            _pad: [0.0; 2],
+        };
+        queue.write_buffer(&self.screen_buffer, 0, bytemuck::bytes_of(&screen));
+
+        let instances = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("overlay quads"),
+            contents: bytemuck::cast_slice(&self.quads),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("overlay"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Load,
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_pipeline(pipelines.render(device, &self.pipeline));
+        render_pass.set_bind_group(0, &self.bind_group, &[]);
+        render_pass.set_vertex_buffer(0, instances.slice(..));
+        render_pass.draw(0..4, 0..self.quads.len() as u32);
+
+        self.quads.clear();
+    }
+}
@@ -0,0 +1,52 @@
+struct Screen {
+    size: vec2<f32>,
+    _pad: vec2<f32>,
+};
+
+@group(0) @binding(0) var<uniform> screen: Screen;
+@group(0) @binding(1) var font: texture_2d<f32>;
+
+const SOLID = 0xffffffffu;
+const CELL = vec2<f32>(5.0, 7.0);
+
+struct QuadInput {
+    // x, y, width, height in pixels from the top left
+    @location(0) rect: vec4<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) glyph: u32,
+};
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) local: vec2<f32>,
+    @location(1) color: vec4<f32>,
+    @location(2) @interpolate(flat) glyph: u32,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32, quad: QuadInput) -> VertexOutput {
+    var out: VertexOutput;
+
+    // Triangle strip corners.
+    let corner = vec2<f32>(f32(index & 1u), f32((index >> 1u) & 1u));
+    let pixel = quad.rect.xy + corner * quad.rect.zw;
+
+    out.position = vec4<f32>(pixel / screen.size * vec2<f32>(2.0, -2.0) + vec2<f32>(-1.0, 1.0), 0.0, 1.0);
+    out.local = corner;
+    out.color = quad.color;
+    out.glyph = quad.glyph;
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    if (in.glyph == SOLID) {
+        return in.color;
+    }
+
+    let cell = min(vec2<i32>(in.local * CELL), vec2<i32>(CELL) - 1);
+    let texel = vec2<i32>(i32(in.glyph) * i32(CELL.x) + cell.x, cell.y);
+    let coverage = textureLoad(font, texel, 0).r;
+
+    return vec4<f32>(in.color.rgb, in.color.a * coverage);
+}
@@ -0,0 +1,45 @@
+// Frame rate cap. Rendering is scheduled on its own clock, so the fixed-step
+// updates keep running at FIXED_TIME_STEP whatever the frame rate.
+
+use std::time::{Duration, Instant};
+
+pub struct FramePacer {
+    interval: Option<Duration>,
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new(max_fps: Option<f32>) -> Self {
+        Self {
+            interval: max_fps.map(|fps| Duration::from_secs_f32(1.0 / fps)),
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Time between frames with a cap, `None` renders as fast as presenting allows.
+    pub fn interval(&self) -> Option<Duration> {
+        self.interval
+    }
+
+    /// True when a frame is due, which schedules the one after it.
+    pub fn frame_due(&mut self, now: Instant) -> bool {
+        let Some(interval) = self.interval else {
+            return true;
+        };
+        if now < self.next_frame {
+            return false;
+        }
+
+        self.next_frame += interval;
+        // After a stall start over from now rather than rendering the missed frames back to back.
+        if self.next_frame < now {
+            self.next_frame = now + interval;
+        }
+        true
+    }
+
+    /// When the next frame is due with a cap.
+    pub fn next_frame(&self) -> Option<Instant> {
+        self.interval.map(|_| self.next_frame)
+    }
+}
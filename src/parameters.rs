@@ -0,0 +1,196 @@
+// Tweakable shader parameters.
+//
+// Parameters are declared once in Rust. The WGSL `Params` struct is generated
+// from the declarations and prepended to the shader source, with one vec4 per
+// parameter: floats and ints live in `.x`, colors in `.rgb`.
+
+// Editing and saving are only used by the tweak panel.
+#![cfg_attr(not(feature = "tweak"), allow(dead_code))]
+
+use anyhow::*;
+use std::path::Path;
+
+#[derive(Clone, Copy, Debug)]
+pub enum ParameterKind {
+    Float { min: f32, max: f32 },
+    Int { min: i32, max: i32 },
+    Color,
+}
+
+pub struct Parameter {
+    pub name: &'static str,
+    pub kind: ParameterKind,
+    pub value: [f32; 4],
+    pub default: [f32; 4],
+}
+
+pub struct ParameterSet {
+    parameters: Vec<Parameter>,
+}
+
+impl ParameterSet {
+    pub fn new() -> Self {
+        Self {
+            parameters: Vec::new(),
+        }
+    }
+
+    fn declare(mut self, name: &'static str, kind: ParameterKind, value: [f32; 4]) -> Self {
+        self.parameters.push(Parameter {
+            name,
+            kind,
+            value,
+            default: value,
+        });
+        self
+    }
+
+    pub fn float(self, name: &'static str, value: f32, min: f32, max: f32) -> Self {
+        self.declare(
+            name,
+            ParameterKind::Float { min, max },
+            [value, 0.0, 0.0, 0.0],
+        )
+    }
+
+    pub fn int(self, name: &'static str, value: i32, min: i32, max: i32) -> Self {
+        self.declare(
+            name,
+            ParameterKind::Int { min, max },
+            [value as f32, 0.0, 0.0, 0.0],
+        )
+    }
+
+    pub fn color(self, name: &'static str, rgb: [f32; 3]) -> Self {
+        self.declare(name, ParameterKind::Color, [rgb[0], rgb[1], rgb[2], 1.0])
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Parameter> {
+        self.parameters.iter()
+    }
+
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Parameter> {
+        self.parameters.iter_mut()
+    }
+
+    /// Sets `name` from 0 to 1 across its range. Colors go from black to their default.
+    pub fn set_normalized(&mut self, name: &str, amount: f32) {
+        let Some(parameter) = self.parameters.iter_mut().find(|p| p.name == name) else {
+            return;
+        };
+        let amount = amount.clamp(0.0, 1.0);
+        match parameter.kind {
+            ParameterKind::Float { min, max } => parameter.value[0] = min + (max - min) * amount,
+            ParameterKind::Int { min, max } => {
+                parameter.value[0] = (min as f32 + (max - min) as f32 * amount).round()
+            }
+            ParameterKind::Color => {
+                for channel in 0..3 {
+                    parameter.value[channel] = parameter.default[channel] * amount;
+                }
+            }
+        }
+    }
+
+    pub fn reset(&mut self) {
+        for parameter in &mut self.parameters {
+            parameter.value = parameter.default;
+        }
+    }
+
+    /// Contents of the uniform buffer, matches the struct from `wgsl`.
+    pub fn uniform_data(&self) -> Vec<[f32; 4]> {
+        let mut data: Vec<[f32; 4]> = self.parameters.iter().map(|p| p.value).collect();
+        if data.is_empty() {
+            data.push([0.0; 4]);
+        }
+        data
+    }
+
+    pub fn wgsl(&self) -> String {
+        let mut source = String::from("struct Params {\n");
+        for parameter in &self.parameters {
+            source += &format!("    {}: vec4<f32>,\n", parameter.name);
+        }
+        if self.parameters.is_empty() {
+            source += "    _unused: vec4<f32>,\n";
+        }
+        source += "};\n";
+        source
+    }
+
+    /// Applies values saved by `save`, clamped to each parameter's range.
+    /// Parameters missing from the file keep their value.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let table: toml::Table =
+            toml::from_str(&text).with_context(|| format!("parsing {}", path.display()))?;
+
+        for parameter in &mut self.parameters {
+            let Some(value) = table.get(parameter.name) else {
+                continue;
+            };
+            let invalid = || {
+                anyhow!(
+                    "invalid value for '{}' in {}",
+                    parameter.name,
+                    path.display()
+                )
+            };
+
+            // Integers are fine where floats are expected.
+            let number = |value: &toml::Value| {
+                value
+                    .as_float()
+                    .or_else(|| value.as_integer().map(|i| i as f64))
+                    .ok_or_else(invalid)
+            };
+            match parameter.kind {
+                ParameterKind::Float { min, max } => {
+                    parameter.value[0] = (number(value)? as f32).clamp(min, max);
+                }
+                ParameterKind::Int { min, max } => {
+                    let value = value.as_integer().ok_or_else(invalid)?;
+                    parameter.value[0] = value.clamp(min as i64, max as i64) as f32;
+                }
+                ParameterKind::Color => {
+                    let rgb = value
+                        .as_array()
+                        .filter(|rgb| rgb.len() == 3)
+                        .ok_or_else(invalid)?;
+                    // The tweak panel edits colors from 0 to 1.
+                    for (channel, component) in rgb.iter().enumerate() {
+                        parameter.value[channel] = (number(component)? as f32).clamp(0.0, 1.0);
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let mut table = toml::Table::new();
+        for parameter in &self.parameters {
+            let value = match parameter.kind {
+                ParameterKind::Float { .. } => toml::Value::Float(to_f64(parameter.value[0])),
+                ParameterKind::Int { .. } => toml::Value::Integer(parameter.value[0] as i64),
+                ParameterKind::Color => toml::Value::Array(
+                    parameter.value[..3]
+                        .iter()
+                        .map(|&c| toml::Value::Float(to_f64(c)))
+                        .collect(),
+                ),
+            };
+            table.insert(parameter.name.to_string(), value);
+        }
+
+        std::fs::write(path, toml::to_string(&table)?)?;
+        Ok(())
+    }
+}
+
+// Goes through the shortest decimal representation so 0.7 is saved as 0.7.
+fn to_f64(value: f32) -> f64 {
+    value.to_string().parse().unwrap()
+}
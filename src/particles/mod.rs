@@ -0,0 +1,505 @@
+// GPU particles: emitters spawn into a storage buffer that a compute shader
+// integrates with gravity, drag and curl noise, then the live particles are
+// sorted far to near and drawn as alpha blended sprites into a render target,
+// depth tested against the scene.
+//
+// Simulation runs on the fixed update ticks. Spawns go round a ring of slots
+// and randomness is seeded by the tick and slot, so a run replays the same.
+
+use wgpu::util::DeviceExt;
+
+use crate::camera::Camera;
+use crate::pipelines::{ComputeKey, DepthKey, PipelineCache, RenderKey};
+use crate::simulation::Simulation;
+use crate::targets::{RenderTargets, DEPTH_FORMAT};
+
+const MAX_EMITTERS: usize = 4;
+const WORKGROUP_SIZE: u32 = 64;
+// Pending ticks simulated per submission, each needs its own uniform slot.
+const TICKS_PER_SUBMIT: usize = 64;
+
+pub struct Emitter {
+    pub position: [f32; 3],
+    /// Particles spawn anywhere within this distance of the position.
+    pub radius: f32,
+    pub velocity: [f32; 3],
+    /// Random velocity added to each particle, up to this length.
+    pub spread: f32,
+    pub gravity: [f32; 3],
+    /// Fraction of the velocity lost per second, roughly.
+    pub drag: f32,
+    pub curl_strength: f32,
+    /// Frequency of the curl noise, higher is more turbulent.
+    pub curl_scale: f32,
+    pub color: [f32; 4],
+    /// Sprite radius in world units.
+    pub size: f32,
+    /// Seconds, picked at random between the two.
+    pub lifetime: [f32; 2],
+    /// Particles per second.
+    pub rate: f32,
+}
+
+impl Emitter {
+    fn gpu(&self) -> GpuEmitter {
+        let [x, y, z] = self.position;
+        let [vx, vy, vz] = self.velocity;
+        let [gx, gy, gz] = self.gravity;
+        GpuEmitter {
+            position: [x, y, z, self.radius],
+            velocity: [vx, vy, vz, self.spread],
+            gravity: [gx, gy, gz, self.drag],
+            color: self.color,
+            shape: [self.curl_strength, self.curl_scale, self.size, self.rate],
+            lifetime: [self.lifetime[0], self.lifetime[1], 0.0, 0.0],
+        }
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuEmitter {
+    position: [f32; 4],
+    velocity: [f32; 4],
+    gravity: [f32; 4],
+    color: [f32; 4],
+    shape: [f32; 4],
+    lifetime: [f32; 4],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Particle {
+    position: [f32; 4],
+    velocity: [f32; 4],
+    emitter: u32,
+    _pad: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Step {
+    tick: u32,
+    dt: f32,
+    cursor: u32,
+    total: u32,
+    spawns: [u32; MAX_EMITTERS],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GpuView {
+    position: [f32; 4],
+    forward: [f32; 4],
+    right: [f32; 4],
+    up: [f32; 4],
+    // x and y scale of the projection
+    focal: [f32; 2],
+    // Sub-pixel offset in NDC
+    jitter: [f32; 2],
+    count: u32,
+    _pad: [u32; 3],
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct SortKey {
+    depth: f32,
+    index: u32,
+}
+
+pub struct ParticleSystem {
+    emitters: Vec<Emitter>,
+    capacity: u32,
+    // Next slot to spawn into.
+    cursor: u32,
+    pending: Vec<(u64, f32)>,
+    // Kill every particle before the next steps run.
+    clear: bool,
+    particle_buffer: wgpu::Buffer,
+    step_stride: u64,
+    step_buffer: wgpu::Buffer,
+    sort_steps: Vec<u64>,
+    view_buffer: wgpu::Buffer,
+    compute_bind_group: wgpu::BindGroup,
+    render_bind_group: wgpu::BindGroup,
+    simulate_pipeline: ComputeKey,
+    sort_keys_pipeline: ComputeKey,
+    sort_pipeline: ComputeKey,
+    render_pipeline: RenderKey,
+}
+
+impl ParticleSystem {
+    /// `capacity` must be a power of two for the sort.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        capacity: u32,
+        emitters: Vec<Emitter>,
+    ) -> Self {
+        assert!(
+            capacity.is_power_of_two(),
+            "particle capacity must be a power of two"
+        );
+        assert!(
+            emitters.len() <= MAX_EMITTERS,
+            "at most {} emitters",
+            MAX_EMITTERS
+        );
+
+        let particle_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particles"),
+            size: capacity as u64 * std::mem::size_of::<Particle>() as u64,
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+        let key_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particle sort keys"),
+            size: capacity as u64 * std::mem::size_of::<SortKey>() as u64,
+            usage: wgpu::BufferUsages::STORAGE,
+            mapped_at_creation: false,
+        });
+
+        let mut gpu_emitters = [GpuEmitter::default(); MAX_EMITTERS];
+        for (gpu, emitter) in gpu_emitters.iter_mut().zip(&emitters) {
+            *gpu = emitter.gpu();
+        }
+        let emitter_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("particle emitters"),
+            contents: bytemuck::cast_slice(&gpu_emitters),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        // Every tick of a submission and every sort step gets its own slot, picked by
+        // dynamic offset.
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let step_stride = (std::mem::size_of::<Step>() as u64).next_multiple_of(alignment);
+        let step_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particle steps"),
+            size: step_stride * TICKS_PER_SUBMIT as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let sort_stride = (std::mem::size_of::<[u32; 2]>() as u64).next_multiple_of(alignment);
+        let mut sort_data = Vec::new();
+        let mut k = 2;
+        while k <= capacity {
+            let mut j = k / 2;
+            while j > 0 {
+                let mut slot = vec![0u8; sort_stride as usize];
+                slot[..8].copy_from_slice(bytemuck::bytes_of(&[j, k]));
+                sort_data.extend(slot);
+                j /= 2;
+            }
+            k *= 2;
+        }
+        let sort_steps = (0..sort_data.len() as u64 / sort_stride)
+            .map(|step| step * sort_stride)
+            .collect();
+        let sort_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("particle sort steps"),
+            contents: &sort_data,
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let view_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("particle view"),
+            size: std::mem::size_of::<GpuView>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let entry = |binding, visibility, ty| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility,
+            ty,
+            count: None,
+        };
+        let uniform = |dynamic: bool, size: usize| wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Uniform,
+            has_dynamic_offset: dynamic,
+            min_binding_size: wgpu::BufferSize::new(size as u64),
+        };
+        let storage = |read_only| wgpu::BindingType::Buffer {
+            ty: wgpu::BufferBindingType::Storage { read_only },
+            has_dynamic_offset: false,
+            min_binding_size: None,
+        };
+        let compute = wgpu::ShaderStages::COMPUTE;
+        let vertex = wgpu::ShaderStages::VERTEX;
+
+        let compute_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("particle compute bind group layout"),
+            entries: &[
+                entry(0, compute, storage(false)),
+                entry(1, compute, uniform(false, 0)),
+                entry(2, compute, uniform(true, std::mem::size_of::<Step>())),
+                entry(3, compute, storage(false)),
+                entry(4, compute, uniform(false, 0)),
+                entry(5, compute, uniform(true, 8)),
+            ],
+        });
+        let render_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("particle render bind group layout"),
+            entries: &[
+                entry(1, vertex, uniform(false, 0)),
+                entry(4, vertex, uniform(false, 0)),
+                entry(6, vertex, storage(true)),
+                entry(7, vertex, storage(true)),
+            ],
+        });
+
+        let compute_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("particle compute bind group"),
+            layout: &compute_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: emitter_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: sized(&step_buffer, std::mem::size_of::<Step>()),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: key_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: sized(&sort_buffer, 8),
+                },
+            ],
+        });
+        let render_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("particle render bind group"),
+            layout: &render_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: emitter_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: view_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: particle_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: key_buffer.as_entire_binding(),
+                },
+            ],
+        });
+
+        pipelines.add_shader(device, "particles", include_str!("particles.wgsl"));
+        pipelines.add_layout(device, "particles compute", &[&compute_layout]);
+        pipelines.add_layout(device, "particles", &[&render_layout]);
+        let compute_pipeline = |entry_point| ComputeKey {
+            shader: "particles",
+            layout: "particles compute",
+            entry_point,
+        };
+        let render_pipeline = RenderKey {
+            shader: "particles",
+            layout: "particles",
+            vertex: "vs_main",
+            vertex_buffers: Vec::new(),
+            fragment: "fs_main",
+            targets: vec![wgpu::ColorTargetState {
+                format,
+                blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleStrip,
+                ..Default::default()
+            },
+            depth: Some(DepthKey {
+                format: DEPTH_FORMAT,
+                write: false,
+                compare: wgpu::CompareFunction::Greater,
+            }),
+            sample_count,
+        };
+
+        Self {
+            emitters,
+            capacity,
+            cursor: 0,
+            pending: Vec::new(),
+            clear: false,
+            particle_buffer,
+            step_stride,
+            step_buffer,
+            sort_steps,
+            view_buffer,
+            compute_bind_group,
+            render_bind_group,
+            simulate_pipeline: compute_pipeline("simulate"),
+            sort_keys_pipeline: compute_pipeline("sort_keys"),
+            sort_pipeline: compute_pipeline("sort"),
+            render_pipeline,
+        }
+    }
+
+    /// Spawn counts per emitter for `tick`, from the whole particles emitted up to it.
+    fn spawns(&self, tick: u64, dt: f32) -> [u32; MAX_EMITTERS] {
+        let mut spawns = [0; MAX_EMITTERS];
+        for (spawn, emitter) in spawns.iter_mut().zip(&self.emitters) {
+            let emitted = |tick: u64| (tick as f64 * dt as f64 * emitter.rate as f64).floor();
+            *spawn = ((emitted(tick + 1) - emitted(tick)) as u32).min(self.capacity);
+        }
+        spawns
+    }
+
+    /// Starts over without any particles, for when the simulation goes back to tick 0.
+    pub fn reset(&mut self) {
+        self.cursor = 0;
+        self.pending.clear();
+        self.clear = true;
+    }
+
+    /// Runs the pending simulation steps, then sorts and draws the particles into rt_0.
+    pub fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        targets: &RenderTargets,
+        camera: &Camera,
+    ) {
+        if self.emitters.is_empty() {
+            return;
+        }
+
+        let workgroups = self.capacity.div_ceil(WORKGROUP_SIZE);
+        if std::mem::take(&mut self.clear) {
+            // All zeros is dead, the age has reached the lifetime.
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("particle reset"),
+            });
+            encoder.clear_buffer(&self.particle_buffer, 0, None);
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+        let pending = std::mem::take(&mut self.pending);
+        for chunk in pending.chunks(TICKS_PER_SUBMIT) {
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("particle simulation"),
+            });
+            let mut data = vec![0u8; self.step_stride as usize * chunk.len()];
+            for (slot, &(tick, dt)) in chunk.iter().enumerate() {
+                let spawns = self.spawns(tick, dt);
+                let total = spawns.iter().sum::<u32>().min(self.capacity);
+                let step = Step {
+                    tick: tick as u32,
+                    dt,
+                    cursor: self.cursor,
+                    total,
+                    spawns,
+                };
+                self.cursor = (self.cursor + total) % self.capacity;
+
+                let offset = slot * self.step_stride as usize;
+                data[offset..offset + std::mem::size_of::<Step>()]
+                    .copy_from_slice(bytemuck::bytes_of(&step));
+            }
+            queue.write_buffer(&self.step_buffer, 0, &data);
+
+            {
+                let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                    label: Some("particle simulation"),
+                });
+                pass.set_pipeline(pipelines.compute(device, &self.simulate_pipeline));
+                for slot in 0..chunk.len() as u64 {
+                    let offset = (slot * self.step_stride) as u32;
+                    pass.set_bind_group(0, &self.compute_bind_group, &[offset, 0]);
+                    pass.dispatch_workgroups(workgroups, 1, 1);
+                }
+            }
+            queue.submit(std::iter::once(encoder.finish()));
+        }
+
+        let projection = camera.projection(targets.width as f32 / targets.height as f32);
+        let view = GpuView {
+            position: extend(camera.position),
+            forward: extend(camera.forward),
+            right: extend(camera.right),
+            up: extend(camera.up),
+            focal: [projection[0][0], projection[1][1]],
+            jitter: camera.jitter,
+            count: self.capacity,
+            _pad: [0; 3],
+        };
+        queue.write_buffer(&self.view_buffer, 0, bytemuck::bytes_of(&view));
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("particles"),
+        });
+        // The cache hands out one pipeline per pass.
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("particle sort keys"),
+            });
+            pass.set_pipeline(pipelines.compute(device, &self.sort_keys_pipeline));
+            pass.set_bind_group(0, &self.compute_bind_group, &[0, 0]);
+            pass.dispatch_workgroups(workgroups, 1, 1);
+        }
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("particle sort"),
+            });
+            pass.set_pipeline(pipelines.compute(device, &self.sort_pipeline));
+            for &offset in &self.sort_steps {
+                pass.set_bind_group(0, &self.compute_bind_group, &[0, offset as u32]);
+                pass.dispatch_workgroups(workgroups, 1, 1);
+            }
+        }
+        {
+            let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("particles"),
+                color_attachments: &[Some(targets.color_attachment(wgpu::LoadOp::Load))],
+                depth_stencil_attachment: Some(targets.depth_attachment(wgpu::LoadOp::Load)),
+            });
+            pass.set_pipeline(pipelines.render(device, &self.render_pipeline));
+            pass.set_bind_group(0, &self.render_bind_group, &[]);
+            pass.draw(0..4, 0..self.capacity);
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+    }
+}
+
+impl Simulation for ParticleSystem {
+    // The GPU work is recorded here and submitted with the next frame.
+    fn step(&mut self, tick: u64, dt: f32) {
+        if !self.emitters.is_empty() {
+            self.pending.push((tick, dt));
+        }
+    }
+}
+
+// The first `size` bytes, the window a dynamic offset moves through the buffer.
+fn sized(buffer: &wgpu::Buffer, size: usize) -> wgpu::BindingResource<'_> {
+    wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+        buffer,
+        offset: 0,
+        size: wgpu::BufferSize::new(size as u64),
+    })
+}
+
+fn extend([x, y, z]: [f32; 3]) -> [f32; 4] {
+    [x, y, z, 0.0]
+}
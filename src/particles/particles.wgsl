@@ -0,0 +1,244 @@
+struct Particle {
+    // xyz, age in seconds
+    position: vec4<f32>,
+    // xyz, lifetime in seconds, dead once the age reaches it
+    velocity: vec4<f32>,
+    emitter: u32,
+    _pad: vec3<u32>,
+};
+
+struct Emitter {
+    // xyz, spawn radius
+    position: vec4<f32>,
+    // xyz, random spread
+    velocity: vec4<f32>,
+    // xyz, drag
+    gravity: vec4<f32>,
+    color: vec4<f32>,
+    // curl strength, curl scale, size, rate
+    shape: vec4<f32>,
+    // min, max
+    lifetime: vec4<f32>,
+};
+
+struct Step {
+    tick: u32,
+    dt: f32,
+    // First slot to spawn into and the number of spawns per emitter.
+    cursor: u32,
+    total: u32,
+    spawns: vec4<u32>,
+};
+
+struct View {
+    position: vec4<f32>,
+    forward: vec4<f32>,
+    right: vec4<f32>,
+    up: vec4<f32>,
+    // x and y scale of the projection
+    focal: vec2<f32>,
+    // Sub-pixel offset in NDC
+    jitter: vec2<f32>,
+    count: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+};
+
+struct SortKey {
+    depth: f32,
+    index: u32,
+};
+
+struct SortStep {
+    j: u32,
+    k: u32,
+};
+
+const MAX_EMITTERS = 4u;
+// camera::NEAR
+const NEAR = 0.05;
+
+@group(0) @binding(0) var<storage, read_write> particles: array<Particle>;
+@group(0) @binding(1) var<uniform> emitters: array<Emitter, MAX_EMITTERS>;
+@group(0) @binding(2) var<uniform> step: Step;
+@group(0) @binding(3) var<storage, read_write> keys: array<SortKey>;
+@group(0) @binding(4) var<uniform> view: View;
+@group(0) @binding(5) var<uniform> sort_step: SortStep;
+
+fn hash(value: u32) -> u32 {
+    // PCG
+    let state = value * 747796405u + 2891336453u;
+    let word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+fn random(seed: ptr<function, u32>) -> f32 {
+    *seed = hash(*seed);
+    return f32(*seed) / 4294967295.0;
+}
+
+fn random_in_sphere(seed: ptr<function, u32>) -> vec3<f32> {
+    let z = random(seed) * 2.0 - 1.0;
+    let angle = random(seed) * 6.2831853;
+    let radius = sqrt(1.0 - z * z);
+    return vec3<f32>(radius * cos(angle), radius * sin(angle), z) * pow(random(seed), 1.0 / 3.0);
+}
+
+fn value_noise(p: vec3<f32>) -> f32 {
+    let cell = floor(p);
+    let f = p - cell;
+    let s = f * f * (3.0 - 2.0 * f);
+    let c = vec3<i32>(cell);
+    var corners: array<f32, 8>;
+    for (var i = 0; i < 8; i++) {
+        let corner = c + vec3<i32>(i & 1, (i >> 1u) & 1, (i >> 2u) & 1);
+        let bits = bitcast<vec3<u32>>(corner);
+        corners[i] = f32(hash(bits.x ^ hash(bits.y ^ hash(bits.z)))) / 4294967295.0;
+    }
+    let x0 = mix(mix(corners[0], corners[1], s.x), mix(corners[2], corners[3], s.x), s.y);
+    let x1 = mix(mix(corners[4], corners[5], s.x), mix(corners[6], corners[7], s.x), s.y);
+    return mix(x0, x1, s.z);
+}
+
+fn potential(p: vec3<f32>) -> vec3<f32> {
+    return vec3<f32>(
+        value_noise(p),
+        value_noise(p + vec3<f32>(31.4, 15.9, 26.5)),
+        value_noise(p + vec3<f32>(-27.1, 82.8, -18.2)),
+    );
+}
+
+// Divergence free flow, the curl of a noise potential by central differences.
+fn curl_noise(p: vec3<f32>) -> vec3<f32> {
+    let e = 0.1;
+    let dx = potential(p + vec3<f32>(e, 0.0, 0.0)) - potential(p - vec3<f32>(e, 0.0, 0.0));
+    let dy = potential(p + vec3<f32>(0.0, e, 0.0)) - potential(p - vec3<f32>(0.0, e, 0.0));
+    let dz = potential(p + vec3<f32>(0.0, 0.0, e)) - potential(p - vec3<f32>(0.0, 0.0, e));
+    return vec3<f32>(dy.z - dz.y, dz.x - dx.z, dx.y - dy.x) / (2.0 * e);
+}
+
+@compute @workgroup_size(64)
+fn simulate(@builtin(global_invocation_id) id: vec3<u32>) {
+    let count = arrayLength(&particles);
+    let index = id.x;
+    if (index >= count) {
+        return;
+    }
+
+    var particle = particles[index];
+
+    // Spawns fill the ring of slots from the cursor on, overwriting the oldest particles.
+    let offset = (index + count - step.cursor) % count;
+    if (offset < step.total) {
+        var emitter_index = 0u;
+        var first = 0u;
+        for (var e = 0u; e < MAX_EMITTERS; e++) {
+            if (offset < first + step.spawns[e]) {
+                emitter_index = e;
+                break;
+            }
+            first += step.spawns[e];
+        }
+        let emitter = emitters[emitter_index];
+
+        var seed = hash(step.tick ^ hash(index));
+        particle.position = vec4<f32>(emitter.position.xyz + random_in_sphere(&seed) * emitter.position.w, 0.0);
+        let lifetime = mix(emitter.lifetime.x, emitter.lifetime.y, random(&seed));
+        particle.velocity = vec4<f32>(emitter.velocity.xyz + random_in_sphere(&seed) * emitter.velocity.w, lifetime);
+        particle.emitter = emitter_index;
+        particles[index] = particle;
+        return;
+    }
+
+    if (particle.position.w >= particle.velocity.w) {
+        return;
+    }
+
+    let emitter = emitters[particle.emitter];
+    let time = f32(step.tick) * step.dt;
+    let flow = curl_noise(particle.position.xyz * emitter.shape.y + vec3<f32>(0.0, 0.0, time * 0.1));
+    var velocity = particle.velocity.xyz + (emitter.gravity.xyz + flow * emitter.shape.x) * step.dt;
+    velocity *= exp(-emitter.gravity.w * step.dt);
+
+    particle.position = vec4<f32>(particle.position.xyz + velocity * step.dt, particle.position.w + step.dt);
+    particle.velocity = vec4<f32>(velocity, particle.velocity.w);
+    particles[index] = particle;
+}
+
+// Distance along the view direction, dead particles and those behind the camera sort last.
+@compute @workgroup_size(64)
+fn sort_keys(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    if (index >= view.count) {
+        return;
+    }
+
+    let particle = particles[index];
+    var depth = dot(particle.position.xyz - view.position.xyz, view.forward.xyz);
+    if (particle.position.w >= particle.velocity.w || depth < NEAR) {
+        depth = -1.0;
+    }
+    keys[index] = SortKey(depth, index);
+}
+
+// One step of a bitonic sort, far to near so alpha blending composites correctly.
+@compute @workgroup_size(64)
+fn sort(@builtin(global_invocation_id) id: vec3<u32>) {
+    let index = id.x;
+    let other = index ^ sort_step.j;
+    if (other <= index || other >= view.count) {
+        return;
+    }
+
+    let a = keys[index];
+    let b = keys[other];
+    let descending = (index & sort_step.k) == 0u;
+    if ((descending && a.depth < b.depth) || (!descending && a.depth > b.depth)) {
+        keys[index] = b;
+        keys[other] = a;
+    }
+}
+
+@group(0) @binding(6) var<storage, read> sprites: array<Particle>;
+@group(0) @binding(7) var<storage, read> order: array<SortKey>;
+
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) local: vec2<f32>,
+    @location(1) color: vec4<f32>,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) vertex: u32, @builtin(instance_index) instance: u32) -> VertexOutput {
+    var out: VertexOutput;
+
+    let key = order[instance];
+    let particle = sprites[key.index];
+    if (key.depth < NEAR) {
+        // Outside the clip volume.
+        out.position = vec4<f32>(2.0, 2.0, 2.0, 1.0);
+        return out;
+    }
+
+    let emitter = emitters[particle.emitter];
+    let relative = particle.position.xyz - view.position.xyz;
+    // Same projection as Camera::projection, with the corners offset in camera space.
+    let center = vec2<f32>(dot(relative, view.right.xyz), dot(relative, view.up.xyz));
+
+    let corner = vec2<f32>(f32(vertex & 1u), f32((vertex >> 1u) & 1u)) * 2.0 - 1.0;
+    let ndc = (center + corner * emitter.shape.z) * view.focal / key.depth + view.jitter;
+    out.position = vec4<f32>(ndc, NEAR / key.depth, 1.0);
+    out.local = corner;
+
+    let life = particle.position.w / particle.velocity.w;
+    let fade = smoothstep(0.0, 0.1, life) * (1.0 - smoothstep(0.7, 1.0, life));
+    out.color = vec4<f32>(emitter.color.rgb, emitter.color.a * fade);
+    return out;
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let falloff = 1.0 - smoothstep(0.3, 1.0, length(in.local));
+    return vec4<f32>(in.color.rgb, in.color.a * falloff);
+}
@@ -0,0 +1,225 @@
+// Render and compute pipelines created on first use and kept for as long as
+// their shader doesn't change. Shaders and pipeline layouts are registered by
+// name, a pipeline is looked up by a key of everything that tells it apart,
+// so passes asking for the same combination share one.
+//
+// Reloading a shader builds every cached pipeline made from it again from the
+// new source, and only takes the new shader when all of them are valid.
+
+use std::collections::HashMap;
+
+/// Everything a render pipeline is told apart by.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderKey {
+    pub shader: &'static str,
+    pub layout: &'static str,
+    pub vertex: &'static str,
+    pub vertex_buffers: Vec<wgpu::VertexBufferLayout<'static>>,
+    pub fragment: &'static str,
+    pub targets: Vec<wgpu::ColorTargetState>,
+    pub primitive: wgpu::PrimitiveState,
+    pub depth: Option<DepthKey>,
+    pub sample_count: u32,
+}
+
+impl RenderKey {
+    /// A pass over the whole of one target, drawn with the `vs_main` of
+    /// fullscreen.wgsl and the layout of the same name as the shader.
+    pub fn fullscreen(
+        shader: &'static str,
+        fragment: &'static str,
+        format: wgpu::TextureFormat,
+        blend: Option<wgpu::BlendState>,
+    ) -> Self {
+        Self {
+            shader,
+            layout: shader,
+            vertex: "vs_main",
+            vertex_buffers: Vec::new(),
+            fragment,
+            targets: vec![wgpu::ColorTargetState {
+                format,
+                blend,
+                write_mask: wgpu::ColorWrites::ALL,
+            }],
+            primitive: wgpu::PrimitiveState::default(),
+            depth: None,
+            sample_count: 1,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct DepthKey {
+    pub format: wgpu::TextureFormat,
+    pub write: bool,
+    pub compare: wgpu::CompareFunction,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct ComputeKey {
+    pub shader: &'static str,
+    pub layout: &'static str,
+    pub entry_point: &'static str,
+}
+
+#[derive(Default)]
+pub struct PipelineCache {
+    shaders: HashMap<&'static str, wgpu::ShaderModule>,
+    layouts: HashMap<&'static str, wgpu::PipelineLayout>,
+    render_pipelines: HashMap<RenderKey, wgpu::RenderPipeline>,
+    compute_pipelines: HashMap<ComputeKey, wgpu::ComputePipeline>,
+}
+
+impl PipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Compiles `source` as shader `name`, replacing the one of that name if
+    /// there was one.
+    pub fn add_shader(&mut self, device: &wgpu::Device, name: &'static str, source: &str) {
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        self.replace_shader(name, module);
+    }
+
+    /// Like `add_shader`, but keeps the old shader and pipelines when the new
+    /// source doesn't compile or doesn't fit a pipeline made from the old one,
+    /// for editing shaders while the demo runs.
+    #[cfg(debug_assertions)]
+    pub fn reload_shader(
+        &mut self,
+        device: &wgpu::Device,
+        name: &'static str,
+        source: &str,
+    ) -> anyhow::Result<()> {
+        device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(name),
+            source: wgpu::ShaderSource::Wgsl(source.into()),
+        });
+        let render_pipelines: Vec<_> = self
+            .render_pipelines
+            .keys()
+            .filter(|key| key.shader == name)
+            .map(|key| {
+                let pipeline = create_render_pipeline(device, &module, &self.layouts, key);
+                (key.clone(), pipeline)
+            })
+            .collect();
+        let compute_pipelines: Vec<_> = self
+            .compute_pipelines
+            .keys()
+            .filter(|key| key.shader == name)
+            .map(|key| {
+                let pipeline = create_compute_pipeline(device, &module, &self.layouts, key);
+                (key.clone(), pipeline)
+            })
+            .collect();
+        if let Some(error) = pollster::block_on(device.pop_error_scope()) {
+            // The error itself says little more than that validation failed.
+            let description = match error {
+                wgpu::Error::Validation { description, .. } => description,
+                error => error.to_string(),
+            };
+            anyhow::bail!("Failed to reload shader {}: {}", name, description);
+        }
+        self.shaders.insert(name, module);
+        self.render_pipelines.extend(render_pipelines);
+        self.compute_pipelines.extend(compute_pipelines);
+        Ok(())
+    }
+
+    pub fn add_layout(
+        &mut self,
+        device: &wgpu::Device,
+        name: &'static str,
+        bind_group_layouts: &[&wgpu::BindGroupLayout],
+    ) {
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some(name),
+            bind_group_layouts,
+            push_constant_ranges: &[],
+        });
+        self.layouts.insert(name, layout);
+        // Pipelines keep the layout they were made with.
+        self.render_pipelines.retain(|key, _| key.layout != name);
+        self.compute_pipelines.retain(|key, _| key.layout != name);
+    }
+
+    /// Panics if the shader or layout of `key` wasn't added.
+    pub fn render(&mut self, device: &wgpu::Device, key: &RenderKey) -> &wgpu::RenderPipeline {
+        if !self.render_pipelines.contains_key(key) {
+            let pipeline =
+                create_render_pipeline(device, &self.shaders[key.shader], &self.layouts, key);
+            self.render_pipelines.insert(key.clone(), pipeline);
+        }
+        &self.render_pipelines[key]
+    }
+
+    /// Panics if the shader or layout of `key` wasn't added.
+    pub fn compute(&mut self, device: &wgpu::Device, key: &ComputeKey) -> &wgpu::ComputePipeline {
+        if !self.compute_pipelines.contains_key(key) {
+            let pipeline =
+                create_compute_pipeline(device, &self.shaders[key.shader], &self.layouts, key);
+            self.compute_pipelines.insert(key.clone(), pipeline);
+        }
+        &self.compute_pipelines[key]
+    }
+
+    fn replace_shader(&mut self, name: &'static str, module: wgpu::ShaderModule) {
+        self.shaders.insert(name, module);
+        self.render_pipelines.retain(|key, _| key.shader != name);
+        self.compute_pipelines.retain(|key, _| key.shader != name);
+    }
+}
+
+fn create_render_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    layouts: &HashMap<&'static str, wgpu::PipelineLayout>,
+    key: &RenderKey,
+) -> wgpu::RenderPipeline {
+    let targets: Vec<_> = key.targets.iter().cloned().map(Some).collect();
+    device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+        label: Some(key.fragment),
+        layout: Some(&layouts[key.layout]),
+        vertex: wgpu::VertexState {
+            module: shader,
+            entry_point: key.vertex,
+            buffers: &key.vertex_buffers,
+        },
+        fragment: Some(wgpu::FragmentState {
+            module: shader,
+            entry_point: key.fragment,
+            targets: &targets,
+        }),
+        primitive: key.primitive,
+        depth_stencil: key.depth.map(|depth| wgpu::DepthStencilState {
+            format: depth.format,
+            depth_write_enabled: depth.write,
+            depth_compare: depth.compare,
+            stencil: Default::default(),
+            bias: Default::default(),
+        }),
+        multisample: crate::msaa::multisample_state(key.sample_count),
+        multiview: None,
+    })
+}
+
+fn create_compute_pipeline(
+    device: &wgpu::Device,
+    shader: &wgpu::ShaderModule,
+    layouts: &HashMap<&'static str, wgpu::PipelineLayout>,
+    key: &ComputeKey,
+) -> wgpu::ComputePipeline {
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: Some(key.entry_point),
+        layout: Some(&layouts[key.layout]),
+        module: shader,
+        entry_point: key.entry_point,
+    })
+}
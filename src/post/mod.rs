@@ -0,0 +1,538 @@
+// Post-processing between the scene passes and the final pass. Every effect
+// is its own fullscreen pass ping-ponging between rt_0 and rt_1, effects list
+// their chain in main.rs in the order it runs. The chain is split in two
+// stacks: most effects work on the HDR scene before tone mapping, color grades
+// and FXAA expect display-referred colors from 0 to 1 and run after it.
+//
+// Bloom takes a few more passes: it keeps what is above the threshold at half
+// size, blurs it down a chain of smaller levels and back up, then adds it
+// onto the scene.
+
+use anyhow::*;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use crate::pipelines::{PipelineCache, RenderKey};
+use crate::targets::RenderTargets;
+
+const MAX_BLOOM_LEVELS: u32 = 6;
+
+const ADDITIVE: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent::REPLACE,
+};
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "effect", rename_all = "kebab-case", deny_unknown_fields)]
+pub enum Effect {
+    /// Adds a blurred copy of everything brighter than `threshold`, `radius`
+    /// spreads the blur in texels of each level.
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        radius: f32,
+    },
+    /// Pulls red and blue apart towards the edges, `strength` is the offset
+    /// at the border as a fraction of the size.
+    ChromaticAberration {
+        strength: f32,
+    },
+    /// Tape wobble with the channels bleeding apart.
+    Vhs {
+        amount: f32,
+        speed: f32,
+    },
+    /// Noise added on top, a new pattern `fps` times a second.
+    FilmGrain {
+        intensity: f32,
+        fps: f32,
+    },
+    /// Darkens the corners, `smoothness` is how far towards the center it reaches.
+    Vignette {
+        intensity: f32,
+        smoothness: f32,
+    },
+    /// Looks colors up in `lut`, an image of the blue slices of a cube side
+    /// by side, as wide as the cube is high times its height. `strength`
+    /// blends it with the original. Above 1 the colors pass through ungraded.
+    ColorGrade {
+        lut: PathBuf,
+        strength: f32,
+    },
+    Fxaa,
+}
+
+impl Effect {
+    /// Runs after tone mapping, on colors from 0 to 1.
+    pub fn display_referred(&self) -> bool {
+        matches!(self, Effect::ColorGrade { .. } | Effect::Fxaa)
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Settings {
+    texel: [f32; 2],
+    time: f32,
+    _pad: f32,
+    params: [f32; 4],
+}
+
+#[derive(Clone, Copy)]
+enum Target {
+    /// rt_0 or rt_1
+    Scene(usize),
+    Bloom(usize),
+}
+
+struct Pass {
+    entry_point: &'static str,
+    // Added onto the target instead of replacing it.
+    additive: bool,
+    target: Target,
+    bind_group: wgpu::BindGroup,
+    texel: [f32; 2],
+    params: [f32; 4],
+}
+
+pub struct PostStack {
+    effects: Vec<Effect>,
+    format: wgpu::TextureFormat,
+    // For the color grades, by effect index.
+    luts: HashMap<usize, wgpu::TextureView>,
+    identity_lut: wgpu::TextureView,
+    noise: wgpu::TextureView,
+    layout: wgpu::BindGroupLayout,
+    clamp_sampler: wgpu::Sampler,
+    repeat_sampler: wgpu::Sampler,
+    settings_buffer: wgpu::Buffer,
+    settings_stride: u64,
+    bloom_levels: Vec<wgpu::TextureView>,
+    passes: Vec<Pass>,
+    // Which of rt_0 and rt_1 ends up with the result.
+    output: usize,
+}
+
+impl PostStack {
+    /// `noise` is tiled for the film grain.
+    pub fn new(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        format: wgpu::TextureFormat,
+        effects: Vec<Effect>,
+        noise: &wgpu::Texture,
+    ) -> Result<Self> {
+        pipelines.add_shader(
+            device,
+            "post",
+            concat!(
+                include_str!("../fullscreen.wgsl"),
+                include_str!("post.wgsl")
+            ),
+        );
+
+        let texture_entry = |binding, view_dimension| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let sampler_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+            count: None,
+        };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("post"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: true,
+                        min_binding_size: wgpu::BufferSize::new(
+                            std::mem::size_of::<Settings>() as u64
+                        ),
+                    },
+                    count: None,
+                },
+                sampler_entry(1),
+                sampler_entry(2),
+                texture_entry(3, wgpu::TextureViewDimension::D2),
+                texture_entry(4, wgpu::TextureViewDimension::D2),
+                texture_entry(5, wgpu::TextureViewDimension::D3),
+            ],
+        });
+        pipelines.add_layout(device, "post", &[&layout]);
+
+        let mut luts = HashMap::new();
+        for (index, effect) in effects.iter().enumerate() {
+            if let Effect::ColorGrade { lut, .. } = effect {
+                let bytes = std::fs::read(lut)
+                    .with_context(|| format!("Failed to read LUT {}", lut.display()))?;
+                let image = image::load_from_memory(&bytes)
+                    .with_context(|| format!("Failed to decode LUT {}", lut.display()))?
+                    .to_rgba8();
+                let size = image.height();
+                ensure!(
+                    size > 1 && image.width() == size * size,
+                    "LUT {} should be {} wide for its height of {}",
+                    lut.display(),
+                    size * size,
+                    size
+                );
+                // The slices side by side are rows of one slice after another.
+                let mut texels = Vec::with_capacity(image.as_raw().len());
+                for slice in 0..size {
+                    for y in 0..size {
+                        let row = (y * image.width() + slice * size) as usize * 4;
+                        texels.extend_from_slice(&image.as_raw()[row..row + size as usize * 4]);
+                    }
+                }
+                luts.insert(index, create_lut(device, queue, size, &texels));
+            }
+        }
+        let identity: Vec<u8> = (0..8u32)
+            .flat_map(|index| [index & 1, (index >> 1) & 1, index >> 2, 1].map(|c| c as u8 * 255))
+            .collect();
+
+        let clamp_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post clamp"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let repeat_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("post repeat"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let settings_stride = (std::mem::size_of::<Settings>() as u64).next_multiple_of(alignment);
+
+        Ok(Self {
+            effects,
+            format,
+            luts,
+            identity_lut: create_lut(device, queue, 2, &identity),
+            noise: noise.create_view(&Default::default()),
+            layout,
+            clamp_sampler,
+            repeat_sampler,
+            settings_buffer: create_settings_buffer(device, settings_stride),
+            settings_stride,
+            bloom_levels: Vec::new(),
+            passes: Vec::new(),
+            output: 0,
+        })
+    }
+
+    /// Rebuilds the passes for new render targets, starting from rt_0 or rt_1 by `input`.
+    pub fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets, input: usize) {
+        let scene_texel = [1.0 / targets.width as f32, 1.0 / targets.height as f32];
+
+        // Half size, down to a few pixels at the smallest level.
+        let (width, height) = ((targets.width / 2).max(1), (targets.height / 2).max(1));
+        let level_count = (width.min(height).max(8).ilog2() - 2).min(MAX_BLOOM_LEVELS);
+        let bloom_texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("bloom"),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::RENDER_ATTACHMENT,
+            view_formats: &[],
+        });
+        self.bloom_levels = (0..level_count)
+            .map(|level| {
+                bloom_texture.create_view(&wgpu::TextureViewDescriptor {
+                    base_mip_level: level,
+                    mip_level_count: Some(1),
+                    ..Default::default()
+                })
+            })
+            .collect();
+        let level_texel = |level: usize| {
+            [
+                1.0 / (width >> level).max(1) as f32,
+                1.0 / (height >> level).max(1) as f32,
+            ]
+        };
+
+        // Sized before the bind groups refer to it.
+        let pass_count: usize = self
+            .effects
+            .iter()
+            .map(|effect| match effect {
+                Effect::Bloom { .. } => 2 * level_count as usize,
+                _ => 1,
+            })
+            .sum();
+        let size = self.settings_stride * pass_count.max(1) as u64;
+        if self.settings_buffer.size() < size {
+            self.settings_buffer = create_settings_buffer(device, size);
+        }
+
+        let scene = [&targets.rt_0, &targets.rt_1];
+        let levels = &self.bloom_levels;
+        let mut passes = Vec::new();
+        let mut current = input;
+        for (index, effect) in self.effects.iter().enumerate() {
+            let next = 1 - current;
+            let pass = |entry_point, target, source, extra: Option<&wgpu::TextureView>| Pass {
+                entry_point,
+                additive: false,
+                target,
+                bind_group: self.bind_group(
+                    device,
+                    source,
+                    extra.unwrap_or(&self.noise),
+                    self.luts.get(&index).unwrap_or(&self.identity_lut),
+                ),
+                texel: scene_texel,
+                params: [0.0; 4],
+            };
+
+            let (entry_point, params) = match *effect {
+                Effect::Bloom {
+                    threshold,
+                    intensity,
+                    radius,
+                } => {
+                    passes.push(Pass {
+                        params: [threshold, 0.0, 0.0, 0.0],
+                        ..pass("fs_bloom_prefilter", Target::Bloom(0), scene[current], None)
+                    });
+                    for level in 1..levels.len() {
+                        passes.push(Pass {
+                            texel: level_texel(level - 1),
+                            ..pass(
+                                "fs_bloom_downsample",
+                                Target::Bloom(level),
+                                &levels[level - 1],
+                                None,
+                            )
+                        });
+                    }
+                    for level in (0..levels.len() - 1).rev() {
+                        passes.push(Pass {
+                            additive: true,
+                            texel: level_texel(level + 1),
+                            params: [radius, 0.0, 0.0, 0.0],
+                            ..pass(
+                                "fs_bloom_upsample",
+                                Target::Bloom(level),
+                                &levels[level + 1],
+                                None,
+                            )
+                        });
+                    }
+                    passes.push(Pass {
+                        params: [intensity, 0.0, 0.0, 0.0],
+                        ..pass(
+                            "fs_bloom_composite",
+                            Target::Scene(next),
+                            scene[current],
+                            Some(&levels[0]),
+                        )
+                    });
+                    current = next;
+                    continue;
+                }
+                Effect::ChromaticAberration { strength } => {
+                    ("fs_chromatic_aberration", [strength, 0.0, 0.0, 0.0])
+                }
+                Effect::Vhs { amount, speed } => ("fs_vhs", [amount, speed, 0.0, 0.0]),
+                Effect::FilmGrain { intensity, fps } => {
+                    ("fs_film_grain", [intensity, fps, 0.0, 0.0])
+                }
+                Effect::Vignette {
+                    intensity,
+                    smoothness,
+                } => ("fs_vignette", [intensity, smoothness, 0.0, 0.0]),
+                Effect::ColorGrade { strength, .. } => {
+                    ("fs_color_grade", [strength, 0.0, 0.0, 0.0])
+                }
+                Effect::Fxaa => ("fs_fxaa", [0.0; 4]),
+            };
+            passes.push(Pass {
+                params,
+                ..pass(entry_point, Target::Scene(next), scene[current], None)
+            });
+            current = next;
+        }
+
+        self.passes = passes;
+        self.output = current;
+    }
+
+    /// Which of rt_0 and rt_1 holds the result.
+    pub fn output(&self) -> usize {
+        self.output
+    }
+
+    /// Runs the chain, returns which of rt_0 and rt_1 holds the result.
+    pub fn render(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargets,
+        time: f32,
+    ) -> usize {
+        let mut data = vec![0u8; self.settings_stride as usize * self.passes.len()];
+        for (slot, pass) in self.passes.iter().enumerate() {
+            let settings = Settings {
+                texel: pass.texel,
+                time,
+                _pad: 0.0,
+                params: pass.params,
+            };
+            let offset = slot * self.settings_stride as usize;
+            data[offset..offset + std::mem::size_of::<Settings>()]
+                .copy_from_slice(bytemuck::bytes_of(&settings));
+        }
+        queue.write_buffer(&self.settings_buffer, 0, &data);
+
+        for (slot, pass) in self.passes.iter().enumerate() {
+            let view = match pass.target {
+                Target::Scene(0) => &targets.rt_0,
+                Target::Scene(_) => &targets.rt_1,
+                Target::Bloom(level) => &self.bloom_levels[level],
+            };
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some(pass.entry_point),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: if pass.additive {
+                            wgpu::LoadOp::Load
+                        } else {
+                            wgpu::LoadOp::Clear(wgpu::Color::BLACK)
+                        },
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipelines.render(
+                device,
+                &RenderKey::fullscreen(
+                    "post",
+                    pass.entry_point,
+                    self.format,
+                    pass.additive.then_some(ADDITIVE),
+                ),
+            ));
+            let offset = (slot as u64 * self.settings_stride) as u32;
+            render_pass.set_bind_group(0, &pass.bind_group, &[offset]);
+            render_pass.draw(0..3, 0..1);
+        }
+        self.output
+    }
+
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        extra: &wgpu::TextureView,
+        lut: &wgpu::TextureView,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("post"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &self.settings_buffer,
+                        offset: 0,
+                        size: wgpu::BufferSize::new(std::mem::size_of::<Settings>() as u64),
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.clamp_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Sampler(&self.repeat_sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(extra),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: wgpu::BindingResource::TextureView(lut),
+                },
+            ],
+        })
+    }
+}
+
+fn create_settings_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("post settings"),
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// `texels` are RGBA, red along x, green along y and blue along z.
+fn create_lut(
+    device: &wgpu::Device,
+    queue: &wgpu::Queue,
+    size: u32,
+    texels: &[u8],
+) -> wgpu::TextureView {
+    use wgpu::util::DeviceExt;
+    device
+        .create_texture_with_data(
+            queue,
+            &wgpu::TextureDescriptor {
+                label: Some("lut"),
+                size: wgpu::Extent3d {
+                    width: size,
+                    height: size,
+                    depth_or_array_layers: size,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D3,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            },
+            texels,
+        )
+        .create_view(&Default::default())
+}
@@ -0,0 +1,170 @@
+struct Settings {
+    // One over the size of the source in pixels.
+    texel: vec2<f32>,
+    time: f32,
+    _pad: f32,
+    // Per effect, see post::Effect.
+    params: vec4<f32>,
+};
+
+@group(0) @binding(0) var<uniform> settings: Settings;
+@group(0) @binding(1) var clamp_sampler: sampler;
+@group(0) @binding(2) var repeat_sampler: sampler;
+@group(0) @binding(3) var source: texture_2d<f32>;
+// Bloom for the composite, noise for the grain.
+@group(0) @binding(4) var extra: texture_2d<f32>;
+@group(0) @binding(5) var lut: texture_3d<f32>;
+
+fn fetch(uv: vec2<f32>) -> vec4<f32> {
+    return textureSampleLevel(source, clamp_sampler, uv, 0.0);
+}
+
+fn luma(color: vec3<f32>) -> f32 {
+    return dot(color, vec3<f32>(0.299, 0.587, 0.114));
+}
+
+fn rand(n: f32) -> f32 {
+    return fract(sin(n) * 43758.5453123);
+}
+
+// Four bilinear taps averaging a 4x4 block, keeping what is above the
+// threshold with a soft knee.
+@fragment
+fn fs_bloom_prefilter(in: VertexOutput) -> @location(0) vec4<f32> {
+    let o = settings.texel;
+    let color = (fetch(in.uv + vec2<f32>(-o.x, -o.y)) + fetch(in.uv + vec2<f32>(o.x, -o.y))
+        + fetch(in.uv + vec2<f32>(-o.x, o.y)) + fetch(in.uv + o)).rgb * 0.25;
+
+    let threshold = settings.params.x;
+    let knee = threshold * 0.5;
+    let brightness = max(color.r, max(color.g, color.b));
+    var soft = clamp(brightness - threshold + knee, 0.0, 2.0 * knee);
+    soft = soft * soft / (4.0 * knee + 0.0001);
+    let contribution = max(soft, brightness - threshold) / max(brightness, 0.0001);
+    return vec4<f32>(color * contribution, 1.0);
+}
+
+// Dual filter, the center and four diagonal taps.
+@fragment
+fn fs_bloom_downsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let o = settings.texel;
+    var color = fetch(in.uv).rgb * 4.0;
+    color += fetch(in.uv + vec2<f32>(-o.x, -o.y)).rgb;
+    color += fetch(in.uv + vec2<f32>(o.x, -o.y)).rgb;
+    color += fetch(in.uv + vec2<f32>(-o.x, o.y)).rgb;
+    color += fetch(in.uv + o).rgb;
+    return vec4<f32>(color / 8.0, 1.0);
+}
+
+// 3x3 tent, added onto the next larger level.
+@fragment
+fn fs_bloom_upsample(in: VertexOutput) -> @location(0) vec4<f32> {
+    let o = settings.texel * settings.params.x;
+    var color = fetch(in.uv).rgb * 4.0;
+    color += (fetch(in.uv + vec2<f32>(-o.x, 0.0)) + fetch(in.uv + vec2<f32>(o.x, 0.0))
+        + fetch(in.uv + vec2<f32>(0.0, -o.y)) + fetch(in.uv + vec2<f32>(0.0, o.y))).rgb * 2.0;
+    color += (fetch(in.uv + vec2<f32>(-o.x, -o.y)) + fetch(in.uv + vec2<f32>(o.x, -o.y))
+        + fetch(in.uv + vec2<f32>(-o.x, o.y)) + fetch(in.uv + o)).rgb;
+    return vec4<f32>(color / 16.0, 1.0);
+}
+
+@fragment
+fn fs_bloom_composite(in: VertexOutput) -> @location(0) vec4<f32> {
+    let bloom = textureSampleLevel(extra, clamp_sampler, in.uv, 0.0).rgb;
+    return vec4<f32>(fetch(in.uv).rgb + bloom * settings.params.x, 1.0);
+}
+
+@fragment
+fn fs_chromatic_aberration(in: VertexOutput) -> @location(0) vec4<f32> {
+    let offset = (in.uv - 0.5) * settings.params.x;
+    return vec4<f32>(fetch(in.uv - offset).r, fetch(in.uv).g, fetch(in.uv + offset).b, 1.0);
+}
+
+// Tape wobble jumping between random offsets, with the channels bleeding apart.
+@fragment
+fn fs_vhs(in: VertexOutput) -> @location(0) vec4<f32> {
+    let scale = settings.params.x * 0.7314;
+    let inner = settings.time * 33.14 * cos(settings.params.y * 6.28 + 0.4 * rand(scale));
+    let jump = floor(inner - 128.0 * floor(inner / 128.0));
+    let line = 1.628 * rand(jump * 0.5678);
+
+    var uv = in.uv;
+    uv.x += -0.00628 + 0.009 * fract(line);
+    uv.y -= -0.00428 + 0.008 * fract(line * line);
+    let aberration = 0.00314 * length(uv);
+    return vec4<f32>(fetch(uv + aberration).r, fetch(uv).g, fetch(uv - aberration).b, 1.0);
+}
+
+@fragment
+fn fs_film_grain(in: VertexOutput) -> @location(0) vec4<f32> {
+    let frame = floor(settings.time * settings.params.y);
+    let offset = vec2<f32>(rand(frame), rand(frame + 0.5));
+    // One noise texel per pixel.
+    let tiles = vec2<f32>(textureDimensions(extra)) * settings.texel;
+    let grain = textureSampleLevel(extra, repeat_sampler, in.uv / tiles + offset, 0.0).r;
+    return vec4<f32>(fetch(in.uv).rgb + (grain - 0.5) * settings.params.x, 1.0);
+}
+
+@fragment
+fn fs_vignette(in: VertexOutput) -> @location(0) vec4<f32> {
+    // 0 in the center, 1 in the corners.
+    let edge = length(in.uv * 2.0 - 1.0) * 0.7071;
+    let shade = 1.0 - settings.params.x * smoothstep(1.0 - settings.params.y, 1.0, edge);
+    return vec4<f32>(fetch(in.uv).rgb * shade, 1.0);
+}
+
+fn to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+fn to_linear(srgb: vec3<f32>) -> vec3<f32> {
+    let low = srgb / 12.92;
+    let high = pow((srgb + 0.055) / 1.055, vec3<f32>(2.4));
+    return select(high, low, srgb <= vec3<f32>(0.04045));
+}
+
+// LUTs are authored on sRGB encoded colors.
+@fragment
+fn fs_color_grade(in: VertexOutput) -> @location(0) vec4<f32> {
+    let hdr = fetch(in.uv).rgb;
+    let color = clamp(hdr, vec3<f32>(0.0), vec3<f32>(1.0));
+    let size = f32(textureDimensions(lut).x);
+    // Texel centers of the first and last entries.
+    let coords = to_srgb(color) * (size - 1.0) / size + 0.5 / size;
+    let graded = to_linear(textureSampleLevel(lut, clamp_sampler, coords, 0.0).rgb);
+    // What the LUT can't hold is added back on top.
+    return vec4<f32>(mix(color, graded, settings.params.x) + hdr - color, 1.0);
+}
+
+// FXAA 3.11 console version, blurring along the edge direction found from luma.
+@fragment
+fn fs_fxaa(in: VertexOutput) -> @location(0) vec4<f32> {
+    let o = settings.texel;
+    let north_west = luma(fetch(in.uv + vec2<f32>(-o.x, -o.y)).rgb);
+    let north_east = luma(fetch(in.uv + vec2<f32>(o.x, -o.y)).rgb);
+    let south_west = luma(fetch(in.uv + vec2<f32>(-o.x, o.y)).rgb);
+    let south_east = luma(fetch(in.uv + o).rgb);
+    let middle = fetch(in.uv);
+    let center = luma(middle.rgb);
+
+    let luma_min = min(center, min(min(north_west, north_east), min(south_west, south_east)));
+    let luma_max = max(center, max(max(north_west, north_east), max(south_west, south_east)));
+
+    var direction = vec2<f32>(
+        (south_west + south_east) - (north_west + north_east),
+        (north_west + south_west) - (north_east + south_east),
+    );
+    let reduce = max((north_west + north_east + south_west + south_east) * 0.25 / 8.0, 1.0 / 128.0);
+    let scale = 1.0 / (min(abs(direction.x), abs(direction.y)) + reduce);
+    direction = clamp(direction * scale, vec2<f32>(-8.0), vec2<f32>(8.0)) * o;
+
+    let inner = 0.5 * (fetch(in.uv - direction / 6.0).rgb + fetch(in.uv + direction / 6.0).rgb);
+    let outer = inner * 0.5 + 0.25 * (fetch(in.uv - direction * 0.5).rgb + fetch(in.uv + direction * 0.5).rgb);
+    let outer_luma = luma(outer);
+    if (outer_luma < luma_min || outer_luma > luma_max) {
+        return vec4<f32>(inner, 1.0);
+    }
+    return vec4<f32>(outer, 1.0);
+}
@@ -3,8 +3,10 @@ use image::GenericImageView;
 use std::num::NonZeroU32;
 
 pub struct Texture {
+    #[allow(dead_code)]
     pub texture: wgpu::Texture,
     pub view: wgpu::TextureView,
+    #[allow(dead_code)]
     pub sampler: wgpu::Sampler,
 }
 
@@ -51,6 +53,11 @@ impl Texture {
             size,
         );
 
+        Ok(Self::from_texture(device, texture))
+    }
+
+    /// Wraps a texture that was filled on the GPU, e.g. by the texture generator.
+    pub fn from_texture(device: &wgpu::Device, texture: wgpu::Texture) -> Self {
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
@@ -62,10 +69,10 @@ impl Texture {
             ..Default::default()
         });
 
-        Ok(Self {
+        Self {
             texture,
             view,
             sampler,
-        })
+        }
     }
 }
@@ -0,0 +1,609 @@
+// glTF 2.0 scenes exported from Blender: meshes, metallic/roughness
+// materials with base color textures, the node hierarchy, cameras and
+// animations of node transforms.
+//
+// glTF is right handed with cameras looking down -z, the demo is left handed
+// and looks down +z, so z is mirrored on load. Triangles are flipped with it
+// to keep their faces pointing out.
+
+use anyhow::*;
+use base64::Engine;
+use gltf::animation::util::ReadOutputs;
+use std::path::Path;
+
+use crate::camera::{self, Camera, Matrix};
+use crate::mesh::{self, MeshRenderer};
+use crate::resource;
+
+struct Primitive {
+    mesh: mesh::Mesh,
+    material: Option<usize>,
+}
+
+struct Material {
+    material: mesh::Material,
+    texture: Option<usize>,
+}
+
+struct Node {
+    translation: [f32; 3],
+    // x, y, z, w
+    rotation: [f32; 4],
+    scale: [f32; 3],
+    mesh: Option<usize>,
+    /// Vertical field of view in radians.
+    camera: Option<f32>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Property {
+    Translation,
+    Rotation,
+    Scale,
+}
+
+#[derive(Clone, Copy)]
+enum Interpolation {
+    Step,
+    Linear,
+    CubicSpline,
+}
+
+struct Channel {
+    node: usize,
+    property: Property,
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    // Three per key for cubic splines, in tangent, value and out tangent.
+    values: Vec<[f32; 4]>,
+}
+
+pub struct Scene {
+    meshes: Vec<Vec<Primitive>>,
+    materials: Vec<Material>,
+    texture_bind_groups: Vec<wgpu::BindGroup>,
+    nodes: Vec<Node>,
+    // Node indices with parents before their children.
+    order: Vec<(usize, Option<usize>)>,
+    channels: Vec<Channel>,
+}
+
+impl Scene {
+    pub fn load(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        renderer: &MeshRenderer,
+        path: &Path,
+    ) -> Result<Self> {
+        let gltf = gltf::Gltf::open(path)
+            .with_context(|| format!("Failed to read glTF {}", path.display()))?;
+        let directory = path.parent().unwrap_or(Path::new("."));
+
+        let mut buffers = Vec::new();
+        for buffer in gltf.buffers() {
+            let data = match buffer.source() {
+                gltf::buffer::Source::Bin => gltf
+                    .blob
+                    .clone()
+                    .context("glTF refers to a binary chunk it doesn't have")?,
+                gltf::buffer::Source::Uri(uri) => load_uri(directory, uri)?,
+            };
+            buffers.push(data);
+        }
+
+        let mut textures = Vec::new();
+        for texture in gltf.textures() {
+            let image = texture.source();
+            let bytes = match image.source() {
+                gltf::image::Source::View { view, .. } => {
+                    let buffer = &buffers[view.buffer().index()];
+                    buffer[view.offset()..view.offset() + view.length()].to_vec()
+                }
+                gltf::image::Source::Uri { uri, .. } => load_uri(directory, uri)?,
+            };
+            let texture = resource::Texture::new(device, queue, &bytes, image.name())
+                .with_context(|| format!("Failed to decode image {}", image.index()))?;
+            textures.push(texture);
+        }
+        let texture_bind_groups = textures
+            .iter()
+            .map(|texture| renderer.texture_bind_group(device, texture))
+            .collect();
+
+        let materials = gltf
+            .materials()
+            .map(|material| {
+                let pbr = material.pbr_metallic_roughness();
+                Material {
+                    material: mesh::Material {
+                        base_color: pbr.base_color_factor(),
+                        metallic: pbr.metallic_factor(),
+                        roughness: pbr.roughness_factor(),
+                    },
+                    texture: pbr.base_color_texture().map(|info| info.texture().index()),
+                }
+            })
+            .collect();
+
+        let mut meshes = Vec::new();
+        for gltf_mesh in gltf.meshes() {
+            let mut primitives = Vec::new();
+            for primitive in gltf_mesh.primitives() {
+                if primitive.mode() != gltf::mesh::Mode::Triangles {
+                    continue;
+                }
+                let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
+                let Some(positions) = reader.read_positions() else {
+                    continue;
+                };
+                let positions: Vec<[f32; 3]> = positions.map(mirror).collect();
+                let normals: Vec<[f32; 3]> = match reader.read_normals() {
+                    Some(normals) => normals.map(mirror).collect(),
+                    None => vec![[0.0, 1.0, 0.0]; positions.len()],
+                };
+                let uvs: Vec<[f32; 2]> = match reader.read_tex_coords(0) {
+                    Some(uvs) => uvs.into_f32().collect(),
+                    None => vec![[0.0; 2]; positions.len()],
+                };
+                let mut indices: Vec<u32> = match reader.read_indices() {
+                    Some(indices) => indices.into_u32().collect(),
+                    None => (0..positions.len() as u32).collect(),
+                };
+                for triangle in indices.chunks_exact_mut(3) {
+                    triangle.swap(1, 2);
+                }
+
+                let vertices: Vec<mesh::Vertex> = positions
+                    .iter()
+                    .zip(&normals)
+                    .zip(&uvs)
+                    .map(|((&position, &normal), &uv)| mesh::Vertex {
+                        position,
+                        normal,
+                        uv,
+                    })
+                    .collect();
+                primitives.push(Primitive {
+                    mesh: mesh::Mesh::new(
+                        device,
+                        gltf_mesh.name().unwrap_or("gltf mesh"),
+                        &vertices,
+                        &indices,
+                    ),
+                    material: primitive.material().index(),
+                });
+            }
+            meshes.push(primitives);
+        }
+
+        let nodes = gltf
+            .nodes()
+            .map(|node| {
+                let (translation, rotation, scale) = node.transform().decomposed();
+                Node {
+                    translation: mirror(translation),
+                    rotation: mirror_rotation(rotation),
+                    scale,
+                    mesh: node.mesh().map(|mesh| mesh.index()),
+                    camera: node.camera().and_then(|camera| match camera.projection() {
+                        gltf::camera::Projection::Perspective(perspective) => {
+                            Some(perspective.yfov())
+                        }
+                        gltf::camera::Projection::Orthographic(_) => None,
+                    }),
+                }
+            })
+            .collect();
+
+        let scene = gltf
+            .default_scene()
+            .or_else(|| gltf.scenes().next())
+            .context("glTF has no scenes")?;
+        let mut order = Vec::new();
+        let mut stack: Vec<_> = scene.nodes().map(|node| (node, None)).collect();
+        while let Some((node, parent)) = stack.pop() {
+            order.push((node.index(), parent));
+            stack.extend(node.children().map(|child| (child, Some(node.index()))));
+        }
+
+        let mut channels = Vec::new();
+        for animation in gltf.animations() {
+            for channel in animation.channels() {
+                let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+                let (Some(times), Some(outputs)) = (reader.read_inputs(), reader.read_outputs())
+                else {
+                    continue;
+                };
+                let (property, values): (_, Vec<[f32; 4]>) = match outputs {
+                    ReadOutputs::Translations(values) => (
+                        Property::Translation,
+                        values.map(|value| extend(mirror(value))).collect(),
+                    ),
+                    ReadOutputs::Rotations(values) => (
+                        Property::Rotation,
+                        values.into_f32().map(mirror_rotation).collect(),
+                    ),
+                    ReadOutputs::Scales(values) => (Property::Scale, values.map(extend).collect()),
+                    ReadOutputs::MorphTargetWeights(_) => continue,
+                };
+                let interpolation = match channel.sampler().interpolation() {
+                    gltf::animation::Interpolation::Step => Interpolation::Step,
+                    gltf::animation::Interpolation::Linear => Interpolation::Linear,
+                    gltf::animation::Interpolation::CubicSpline => Interpolation::CubicSpline,
+                };
+                let times: Vec<f32> = times.collect();
+                if times.is_empty() {
+                    continue;
+                }
+                channels.push(Channel {
+                    node: channel.target().node().index(),
+                    property,
+                    interpolation,
+                    times,
+                    values,
+                });
+            }
+        }
+
+        Ok(Self {
+            meshes,
+            materials,
+            texture_bind_groups,
+            nodes,
+            order,
+            channels,
+        })
+    }
+
+    /// World transform of every node at `time` seconds, animations hold their last key.
+    pub fn pose(&self, time: f32) -> Vec<Matrix> {
+        let mut locals: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| (extend(node.translation), node.rotation, extend(node.scale)))
+            .collect();
+        for channel in &self.channels {
+            let value = channel.sample(time);
+            let local = &mut locals[channel.node];
+            match channel.property {
+                Property::Translation => local.0 = value,
+                Property::Rotation => local.1 = value,
+                Property::Scale => local.2 = value,
+            }
+        }
+
+        let mut world = vec![camera::IDENTITY; self.nodes.len()];
+        for &(node, parent) in &self.order {
+            let (translation, rotation, scale) = locals[node];
+            let local = [
+                camera::translation([translation[0], translation[1], translation[2]]),
+                rotation_matrix(rotation),
+                scale_matrix(scale),
+            ]
+            .into_iter()
+            .reduce(camera::multiply)
+            .unwrap();
+            world[node] = match parent {
+                Some(parent) => camera::multiply(world[parent], local),
+                None => local,
+            };
+        }
+        world
+    }
+
+    /// Every mesh primitive placed by `pose`.
+    pub fn objects(&self, pose: &[Matrix]) -> Vec<mesh::Object<'_>> {
+        let mut objects = Vec::new();
+        for &(node, _) in &self.order {
+            let Some(mesh) = self.nodes[node].mesh else {
+                continue;
+            };
+            for primitive in &self.meshes[mesh] {
+                let material = primitive.material.map(|index| &self.materials[index]);
+                objects.push(mesh::Object {
+                    mesh: &primitive.mesh,
+                    transform: pose[node],
+                    material: material.map_or(DEFAULT_MATERIAL, |material| material.material),
+                    texture: material
+                        .and_then(|material| material.texture)
+                        .map(|texture| &self.texture_bind_groups[texture]),
+                });
+            }
+        }
+        objects
+    }
+
+    /// The first camera in the scene placed by `pose`.
+    pub fn camera(&self, pose: &[Matrix]) -> Option<Camera> {
+        let (node, fov) = self
+            .order
+            .iter()
+            .find_map(|&(node, _)| Some((node, self.nodes[node].camera?)))?;
+        let matrix = pose[node];
+        let axis =
+            |column: usize| normalize([matrix[column][0], matrix[column][1], matrix[column][2]]);
+        Some(Camera {
+            position: [matrix[3][0], matrix[3][1], matrix[3][2]],
+            forward: axis(2),
+            right: axis(0),
+            up: axis(1),
+            fov,
+            jitter: [0.0; 2],
+        })
+    }
+}
+
+// Untextured primitives without a material, per the glTF spec.
+const DEFAULT_MATERIAL: mesh::Material = mesh::Material {
+    base_color: [1.0; 4],
+    metallic: 1.0,
+    roughness: 1.0,
+};
+
+impl Channel {
+    fn sample(&self, time: f32) -> [f32; 4] {
+        let value = |key: usize| match self.interpolation {
+            Interpolation::CubicSpline => self.values[key * 3 + 1],
+            _ => self.values[key],
+        };
+        let last = self.times.len() - 1;
+        if time <= self.times[0] {
+            return value(0);
+        }
+        if time >= self.times[last] {
+            return value(last);
+        }
+
+        let next = self.times.partition_point(|&key_time| key_time <= time);
+        let previous = next - 1;
+        let span = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / span;
+
+        let result = match self.interpolation {
+            Interpolation::Step => value(previous),
+            Interpolation::Linear if self.property == Property::Rotation => {
+                return slerp(value(previous), value(next), t);
+            }
+            Interpolation::Linear => lerp(value(previous), value(next), t),
+            Interpolation::CubicSpline => {
+                // Hermite with the tangents scaled to the key interval.
+                let out_tangent = self.values[previous * 3 + 2];
+                let in_tangent = self.values[next * 3];
+                let (t2, t3) = (t * t, t * t * t);
+                let weights = [
+                    2.0 * t3 - 3.0 * t2 + 1.0,
+                    (t3 - 2.0 * t2 + t) * span,
+                    -2.0 * t3 + 3.0 * t2,
+                    (t3 - t2) * span,
+                ];
+                let points = [value(previous), out_tangent, value(next), in_tangent];
+                let mut result = [0.0; 4];
+                for (weight, point) in weights.iter().zip(points) {
+                    for (result, component) in result.iter_mut().zip(point) {
+                        *result += weight * component;
+                    }
+                }
+                result
+            }
+        };
+        if self.property == Property::Rotation {
+            normalize_quaternion(result)
+        } else {
+            result
+        }
+    }
+}
+
+/// Reads a buffer or image next to the glTF file, or embedded as a base64 data URI.
+fn load_uri(directory: &Path, uri: &str) -> Result<Vec<u8>> {
+    if let Some(data) = uri.strip_prefix("data:") {
+        let (_, encoded) = data
+            .split_once(";base64,")
+            .context("only base64 data URIs are supported")?;
+        return base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .context("invalid base64 data URI");
+    }
+    let path = directory.join(percent_decode(uri)?);
+    std::fs::read(&path).with_context(|| format!("Failed to read {}", path.display()))
+}
+
+/// Relative URIs escape spaces, `#` and non-ASCII bytes of UTF-8 as `%XX`.
+fn percent_decode(uri: &str) -> Result<String> {
+    let mut bytes = Vec::with_capacity(uri.len());
+    let mut rest = uri.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = tail
+                .get(..2)
+                .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+                .and_then(|hex| std::str::from_utf8(hex).ok())
+                .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+                .with_context(|| format!("invalid escape in URI {}", uri))?;
+            bytes.push(hex);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).with_context(|| format!("URI {} is not UTF-8", uri))
+}
+
+fn mirror([x, y, z]: [f32; 3]) -> [f32; 3] {
+    [x, y, -z]
+}
+
+fn mirror_rotation([x, y, z, w]: [f32; 4]) -> [f32; 4] {
+    [-x, -y, z, w]
+}
+
+fn extend([x, y, z]: [f32; 3]) -> [f32; 4] {
+    [x, y, z, 0.0]
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    [0, 1, 2, 3].map(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+fn slerp(a: [f32; 4], mut b: [f32; 4], t: f32) -> [f32; 4] {
+    let mut cos = (0..4).map(|i| a[i] * b[i]).sum::<f32>();
+    // The shorter way round.
+    if cos < 0.0 {
+        b = b.map(|component| -component);
+        cos = -cos;
+    }
+    if cos > 0.9995 {
+        return normalize_quaternion(lerp(a, b, t));
+    }
+    let angle = cos.acos();
+    let sin = angle.sin();
+    let (wa, wb) = (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin);
+    [0, 1, 2, 3].map(|i| a[i] * wa + b[i] * wb)
+}
+
+fn normalize_quaternion(q: [f32; 4]) -> [f32; 4] {
+    let length = q
+        .iter()
+        .map(|component| component * component)
+        .sum::<f32>()
+        .sqrt();
+    q.map(|component| component / length)
+}
+
+fn normalize(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    v.map(|component| component / length)
+}
+
+fn rotation_matrix([x, y, z, w]: [f32; 4]) -> Matrix {
+    [
+        [
+            1.0 - 2.0 * (y * y + z * z),
+            2.0 * (x * y + z * w),
+            2.0 * (x * z - y * w),
+            0.0,
+        ],
+        [
+            2.0 * (x * y - z * w),
+            1.0 - 2.0 * (x * x + z * z),
+            2.0 * (y * z + x * w),
+            0.0,
+        ],
+        [
+            2.0 * (x * z + y * w),
+            2.0 * (y * z - x * w),
+            1.0 - 2.0 * (x * x + y * y),
+            0.0,
+        ],
+        [0.0, 0.0, 0.0, 1.0],
+    ]
+}
+
+fn scale_matrix([x, y, z, _]: [f32; 4]) -> Matrix {
+    let mut matrix = camera::IDENTITY;
+    matrix[0][0] = x;
+    matrix[1][1] = y;
+    matrix[2][2] = z;
+    matrix
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(property: Property, interpolation: Interpolation, values: Vec<[f32; 4]>) -> Channel {
+        let keys = match interpolation {
+            Interpolation::CubicSpline => values.len() / 3,
+            _ => values.len(),
+        };
+        Channel {
+            node: 0,
+            property,
+            interpolation,
+            times: (0..keys).map(|key| key as f32 + 1.0).collect(),
+            values,
+        }
+    }
+
+    fn assert_close(a: [f32; 4], b: [f32; 4]) {
+        assert!(
+            a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn holds_the_end_keys() {
+        let channel = channel(
+            Property::Translation,
+            Interpolation::Linear,
+            vec![[1.0, 0.0, 0.0, 0.0], [3.0, 0.0, 0.0, 0.0]],
+        );
+        assert_close(channel.sample(0.0), [1.0, 0.0, 0.0, 0.0]);
+        assert_close(channel.sample(5.0), [3.0, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn steps_and_lerps() {
+        let values = vec![[0.0, 2.0, 0.0, 0.0], [4.0, 0.0, 0.0, 0.0]];
+        let step = channel(Property::Scale, Interpolation::Step, values.clone());
+        assert_close(step.sample(1.9), [0.0, 2.0, 0.0, 0.0]);
+        assert_close(step.sample(2.0), [4.0, 0.0, 0.0, 0.0]);
+        let linear = channel(Property::Scale, Interpolation::Linear, values);
+        assert_close(linear.sample(1.25), [1.0, 1.5, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn slerps_rotations() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let rotation = channel(
+            Property::Rotation,
+            Interpolation::Linear,
+            vec![[0.0, 0.0, 0.0, 1.0], [0.0, half, 0.0, half]],
+        );
+        // Half of 90 degrees around y.
+        let (sin, cos) = (std::f32::consts::PI / 8.0).sin_cos();
+        assert_close(rotation.sample(1.5), [0.0, sin, 0.0, cos]);
+    }
+
+    #[test]
+    fn cubic_splines_use_the_tangents() {
+        let key = |value: f32, tangent: f32| {
+            [
+                [tangent, 0.0, 0.0, 0.0],
+                [value, 0.0, 0.0, 0.0],
+                [tangent, 0.0, 0.0, 0.0],
+            ]
+        };
+        let flat = channel(
+            Property::Translation,
+            Interpolation::CubicSpline,
+            [key(0.0, 0.0), key(1.0, 0.0)].concat(),
+        );
+        assert_close(flat.sample(1.0), [0.0; 4]);
+        assert_close(flat.sample(1.25), [0.15625, 0.0, 0.0, 0.0]);
+        // Tangents matching the slope make it a straight line.
+        let straight = channel(
+            Property::Translation,
+            Interpolation::CubicSpline,
+            [key(0.0, 1.0), key(1.0, 1.0)].concat(),
+        );
+        assert_close(straight.sample(1.25), [0.25, 0.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    fn percent_decodes_uris() {
+        assert_eq!(percent_decode("scene.bin").unwrap(), "scene.bin");
+        assert_eq!(
+            percent_decode("my%20scene%23.bin").unwrap(),
+            "my scene#.bin"
+        );
+        assert_eq!(percent_decode("caf%C3%A9.png").unwrap(), "café.png");
+        for uri in ["broken%2", "broken%zz", "broken%FF"] {
+            assert!(percent_decode(uri).is_err(), "{}", uri);
+        }
+    }
+}
@@ -0,0 +1,114 @@
+// Fixed-step clock for updates that must not depend on the frame rate.
+//
+// Simulations advance in whole steps and only see the tick number and the step
+// length, never wall clock time, so a run replays identically from the same
+// start. The ticks follow the playback time, so they stay in sync with the
+// music. Rendering happens somewhere between two ticks and blends by `alpha`.
+
+use std::ops::Range;
+use std::time::Duration;
+
+/// Most steps one frame runs. After a stall the rest runs over the next
+/// frames, so the frame after it isn't late too.
+pub const MAX_STEPS_PER_FRAME: u64 = 5;
+
+/// Deterministic per-effect state like particles or physics.
+pub trait Simulation {
+    /// Advances by one step of `dt` seconds. `tick` counts steps since the start of the demo.
+    fn step(&mut self, tick: u64, dt: f32);
+}
+
+pub struct FixedStep {
+    step: Duration,
+    // Time past the last tick, zero while catching up.
+    accumulator: Duration,
+    caught_up: bool,
+    /// Steps run so far.
+    pub tick: u64,
+}
+
+impl FixedStep {
+    pub fn new(step: Duration, tick: u64) -> Self {
+        Self {
+            step,
+            accumulator: Duration::ZERO,
+            caught_up: true,
+            tick,
+        }
+    }
+
+    /// Returns the ticks to run towards `time` since tick 0, at most
+    /// `max_steps` of them. Never goes back.
+    pub fn advance_to(&mut self, time: Duration, max_steps: u64) -> Range<u64> {
+        let target = (time.as_nanos() / self.step.as_nanos()) as u64;
+
+        let first = self.tick;
+        self.tick = target.min(first.saturating_add(max_steps)).max(first);
+        self.caught_up = self.tick >= target;
+        self.accumulator = if self.tick == target {
+            time - self.step * target as u32
+        } else {
+            Duration::ZERO
+        };
+        first..self.tick
+    }
+
+    /// Whether `time` is before the last tick, like after the music looped.
+    pub fn is_past(&self, time: Duration) -> bool {
+        time.as_nanos() < self.step.as_nanos() * self.tick as u128
+    }
+
+    /// How far rendering is past the last tick, from 0 up to 1.
+    pub fn alpha(&self) -> f32 {
+        self.accumulator.as_secs_f32() / self.step.as_secs_f32()
+    }
+
+    /// How long until the next tick is due, zero while catching up.
+    pub fn until_next_step(&self) -> Duration {
+        if self.caught_up {
+            self.step - self.accumulator
+        } else {
+            Duration::ZERO
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn runs_whole_steps_and_keeps_the_rest() {
+        let mut clock = FixedStep::new(STEP, 0);
+        let ms = Duration::from_millis;
+        assert_eq!(clock.advance_to(ms(25), MAX_STEPS_PER_FRAME), 0..2);
+        assert!((clock.alpha() - 0.5).abs() < 1e-4);
+        assert_eq!(clock.until_next_step(), ms(5));
+        assert_eq!(clock.advance_to(ms(29), MAX_STEPS_PER_FRAME), 2..2);
+        assert_eq!(clock.advance_to(ms(30), MAX_STEPS_PER_FRAME), 2..3);
+    }
+
+    #[test]
+    fn spreads_the_catch_up_over_frames() {
+        let mut clock = FixedStep::new(STEP, 0);
+        assert_eq!(clock.advance_to(STEP * 12, 5), 0..5);
+        assert_eq!(clock.until_next_step(), Duration::ZERO);
+        assert_eq!(clock.alpha(), 0.0);
+        // The rest of the stall runs later, none of it is dropped.
+        assert_eq!(clock.advance_to(STEP * 12, 5), 5..10);
+        assert_eq!(clock.advance_to(STEP * 12, 5), 10..12);
+        assert_eq!(clock.until_next_step(), STEP);
+        // The offline render runs everything at once.
+        assert_eq!(clock.advance_to(STEP * 50, u64::MAX), 12..50);
+    }
+
+    #[test]
+    fn never_goes_back() {
+        let mut clock = FixedStep::new(STEP, 50);
+        assert!(clock.is_past(STEP * 10));
+        assert!(!clock.is_past(STEP * 50));
+        assert_eq!(clock.advance_to(STEP * 10, MAX_STEPS_PER_FRAME), 50..50);
+    }
+}
@@ -0,0 +1,129 @@
+// The demo's built-in soundtrack, played when music.mp3 is not available.
+
+use anyhow::*;
+
+use crate::synth::*;
+
+pub fn song() -> Result<Song> {
+    let kick = Instrument {
+        waveform: Waveform::Sine,
+        envelope: Adsr {
+            attack: 0.001,
+            decay: 0.25,
+            sustain: 0.0,
+            release: 0.05,
+        },
+        filter: None,
+        volume: 0.6,
+        pitch_drop: 36.0,
+        pitch_decay: 0.03,
+        delay_send: 0.0,
+    };
+
+    let hat = Instrument {
+        waveform: Waveform::Noise,
+        envelope: Adsr {
+            attack: 0.001,
+            decay: 0.05,
+            sustain: 0.0,
+            release: 0.02,
+        },
+        filter: Some(FilterSettings {
+            kind: FilterKind::Highpass,
+            cutoff: 6000.0,
+            resonance: 0.2,
+            envelope_amount: 0.0,
+        }),
+        volume: 0.2,
+        pitch_drop: 0.0,
+        pitch_decay: 0.0,
+        delay_send: 0.1,
+    };
+
+    let bass = Instrument {
+        waveform: Waveform::Saw,
+        envelope: Adsr {
+            attack: 0.005,
+            decay: 0.15,
+            sustain: 0.4,
+            release: 0.05,
+        },
+        filter: Some(FilterSettings {
+            kind: FilterKind::Lowpass,
+            cutoff: 200.0,
+            resonance: 0.5,
+            envelope_amount: 1400.0,
+        }),
+        volume: 0.3,
+        pitch_drop: 0.0,
+        pitch_decay: 0.0,
+        delay_send: 0.0,
+    };
+
+    let lead = Instrument {
+        waveform: Waveform::Square,
+        envelope: Adsr {
+            attack: 0.01,
+            decay: 0.2,
+            sustain: 0.3,
+            release: 0.2,
+        },
+        filter: Some(FilterSettings {
+            kind: FilterKind::Lowpass,
+            cutoff: 800.0,
+            resonance: 0.3,
+            envelope_amount: 2500.0,
+        }),
+        volume: 0.15,
+        pitch_drop: 0.0,
+        pitch_decay: 0.0,
+        delay_send: 0.5,
+    };
+
+    let kick_track = Track {
+        instrument: 0,
+        patterns: vec![parse_pattern(
+            "C-2 ... ... ... C-2 ... ... ... C-2 ... ... ... C-2 ... ... ...",
+        )?],
+        order: vec![Some(0); 16],
+    };
+
+    let hat_track = Track {
+        instrument: 1,
+        patterns: vec![parse_pattern(
+            "... ... C-5 ... ... ... C-5 ... ... ... C-5 ... ... ... C-5 C-5",
+        )?],
+        order: [vec![None; 4], vec![Some(0); 12]].concat(),
+    };
+
+    let bass_track = Track {
+        instrument: 2,
+        patterns: vec![
+            parse_pattern("A-1 ... A-2 === A-1 ... A-2 === A-1 ... A-2 === G-1 ... G-2 ===")?,
+            parse_pattern("F-1 ... F-2 === F-1 ... F-2 === E-1 ... E-2 === E-1 ... G-2 ===")?,
+        ],
+        order: [vec![None; 2], [Some(0), Some(1)].repeat(7)].concat(),
+    };
+
+    let lead_track = Track {
+        instrument: 3,
+        patterns: vec![
+            parse_pattern("A-4 ... C-5 ... E-5 ... A-5 ... G-5 ... E-5 ... D-5 ... === ...")?,
+            parse_pattern("C-5 ... A-4 ... F-4 ... A-4 ... B-4 ... G#4 ... E-4 ... === ...")?,
+        ],
+        order: [vec![None; 8], [Some(0), Some(1)].repeat(4)].concat(),
+    };
+
+    Ok(Song {
+        bpm: 125.0,
+        rows_per_beat: 4,
+        rows_per_pattern: 16,
+        instruments: vec![kick, hat, bass, lead],
+        tracks: vec![kick_track, hat_track, bass_track, lead_track],
+        delay: DelaySettings {
+            rows: 3,
+            feedback: 0.45,
+            level: 0.6,
+        },
+    })
+}
@@ -0,0 +1,77 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// Frame times kept for the average and the debug graph.
+const HISTORY: usize = 120;
+// A frame taking this many times the expected interval counts as missed.
+const MISSED_FACTOR: f32 = 1.5;
+
+pub struct FrameStats {
+    last_frame: Option<Instant>,
+    frame_times: VecDeque<Duration>,
+    expected: Option<Duration>,
+    missed: u64,
+}
+
+impl FrameStats {
+    pub fn new() -> Self {
+        Self {
+            last_frame: None,
+            frame_times: VecDeque::with_capacity(HISTORY),
+            expected: None,
+            missed: 0,
+        }
+    }
+
+    /// Frame interval to count missed frames against, from vsync or the frame rate cap.
+    pub fn set_expected(&mut self, expected: Option<Duration>) {
+        self.expected = expected;
+    }
+
+    /// Records a presented frame.
+    pub fn frame(&mut self, now: Instant) {
+        if let Some(last_frame) = self.last_frame {
+            if self.frame_times.len() == HISTORY {
+                self.frame_times.pop_front();
+            }
+            let frame_time = now.duration_since(last_frame);
+            self.frame_times.push_back(frame_time);
+            if let Some(expected) = self.expected {
+                if frame_time > expected.mul_f32(MISSED_FACTOR) {
+                    self.missed += 1;
+                }
+            }
+        }
+        self.last_frame = Some(now);
+    }
+
+    /// Oldest first.
+    pub fn frame_times(&self) -> impl Iterator<Item = Duration> + '_ {
+        self.frame_times.iter().copied()
+    }
+
+    pub fn last_frame_time(&self) -> Option<Duration> {
+        self.frame_times.back().copied()
+    }
+
+    pub fn average_frame_time(&self) -> Duration {
+        if self.frame_times.is_empty() {
+            return Duration::ZERO;
+        }
+        self.frame_times.iter().sum::<Duration>() / self.frame_times.len() as u32
+    }
+
+    /// Frames that took noticeably longer than expected since startup.
+    pub fn missed(&self) -> u64 {
+        self.missed
+    }
+
+    pub fn fps(&self) -> f32 {
+        let average = self.average_frame_time().as_secs_f32();
+        if average > 0.0 {
+            1.0 / average
+        } else {
+            0.0
+        }
+    }
+}
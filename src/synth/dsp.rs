@@ -0,0 +1,204 @@
+// Building blocks for the synth voices. Everything runs per sample and keeps
+// its own state, so a voice is just a chain of these.
+
+use std::f32::consts::TAU;
+
+// The palette is larger than what the soundtrack currently uses.
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum Waveform {
+    Sine,
+    Saw,
+    Square,
+    Triangle,
+    Noise,
+}
+
+pub struct Oscillator {
+    waveform: Waveform,
+    phase: f32,
+    noise_state: u32,
+}
+
+impl Oscillator {
+    pub fn new(waveform: Waveform) -> Self {
+        Self {
+            waveform,
+            phase: 0.0,
+            noise_state: 0x1234_5678,
+        }
+    }
+
+    pub fn reset(&mut self) {
+        self.phase = 0.0;
+    }
+
+    pub fn next(&mut self, frequency: f32, sample_rate: f32) -> f32 {
+        let phase = self.phase;
+        self.phase = (self.phase + frequency / sample_rate).fract();
+
+        match self.waveform {
+            Waveform::Sine => (phase * TAU).sin(),
+            Waveform::Saw => phase * 2.0 - 1.0,
+            Waveform::Square => {
+                if phase < 0.5 {
+                    1.0
+                } else {
+                    -1.0
+                }
+            }
+            Waveform::Triangle => 1.0 - (phase * 4.0 - 2.0).abs(),
+            Waveform::Noise => {
+                // xorshift32
+                self.noise_state ^= self.noise_state << 13;
+                self.noise_state ^= self.noise_state >> 17;
+                self.noise_state ^= self.noise_state << 5;
+                self.noise_state as f32 / u32::MAX as f32 * 2.0 - 1.0
+            }
+        }
+    }
+}
+
+/// Times in seconds, sustain as a level.
+#[derive(Clone, Copy, Debug)]
+pub struct Adsr {
+    pub attack: f32,
+    pub decay: f32,
+    pub sustain: f32,
+    pub release: f32,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Stage {
+    Idle,
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+pub struct Envelope {
+    adsr: Adsr,
+    stage: Stage,
+    level: f32,
+}
+
+impl Envelope {
+    pub fn new(adsr: Adsr) -> Self {
+        Self {
+            adsr,
+            stage: Stage::Idle,
+            level: 0.0,
+        }
+    }
+
+    pub fn gate_on(&mut self) {
+        self.stage = Stage::Attack;
+    }
+
+    pub fn gate_off(&mut self) {
+        if self.stage != Stage::Idle {
+            self.stage = Stage::Release;
+        }
+    }
+
+    pub fn next(&mut self, sample_rate: f32) -> f32 {
+        // Linear segments; a zero length segment completes immediately.
+        let step = |seconds: f32| 1.0 / (seconds * sample_rate).max(1.0);
+
+        match self.stage {
+            Stage::Idle => self.level = 0.0,
+            Stage::Attack => {
+                self.level += step(self.adsr.attack);
+                if self.level >= 1.0 {
+                    self.level = 1.0;
+                    self.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                self.level -= step(self.adsr.decay) * (1.0 - self.adsr.sustain);
+                if self.level <= self.adsr.sustain {
+                    self.level = self.adsr.sustain;
+                    self.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => self.level = self.adsr.sustain,
+            Stage::Release => {
+                self.level -= step(self.adsr.release);
+                if self.level <= 0.0 {
+                    self.level = 0.0;
+                    self.stage = Stage::Idle;
+                }
+            }
+        }
+        self.level
+    }
+}
+
+#[allow(dead_code)]
+#[derive(Clone, Copy, Debug)]
+pub enum FilterKind {
+    Lowpass,
+    Highpass,
+    Bandpass,
+}
+
+/// Chamberlin state variable filter.
+pub struct Filter {
+    kind: FilterKind,
+    low: f32,
+    band: f32,
+}
+
+impl Filter {
+    pub fn new(kind: FilterKind) -> Self {
+        Self {
+            kind,
+            low: 0.0,
+            band: 0.0,
+        }
+    }
+
+    /// `resonance` runs from 0 (none) to just below 1 (self oscillation).
+    pub fn next(&mut self, input: f32, cutoff: f32, resonance: f32, sample_rate: f32) -> f32 {
+        // The SVF becomes unstable above roughly a sixth of the sample rate.
+        let cutoff = cutoff.clamp(20.0, sample_rate / 6.0);
+        let f = 2.0 * (std::f32::consts::PI * cutoff / sample_rate).sin();
+        let q = 2.0 * (1.0 - resonance.clamp(0.0, 0.99));
+
+        self.low += f * self.band;
+        let high = input - self.low - q * self.band;
+        self.band += f * high;
+
+        match self.kind {
+            FilterKind::Lowpass => self.low,
+            FilterKind::Highpass => high,
+            FilterKind::Bandpass => self.band,
+        }
+    }
+}
+
+/// Feedback delay line.
+pub struct Delay {
+    buffer: Vec<f32>,
+    cursor: usize,
+    feedback: f32,
+}
+
+impl Delay {
+    pub fn new(length: usize, feedback: f32) -> Self {
+        Self {
+            buffer: vec![0.0; length.max(1)],
+            cursor: 0,
+            feedback,
+        }
+    }
+
+    /// Returns the delayed signal only.
+    pub fn next(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.cursor];
+        self.buffer[self.cursor] = input + delayed * self.feedback;
+        self.cursor = (self.cursor + 1) % self.buffer.len();
+        delayed
+    }
+}
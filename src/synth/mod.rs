@@ -0,0 +1,159 @@
+// Small software synthesizer for size-coded productions.
+//
+// The whole song is rendered into an `AudioBuffer` at startup and played back
+// like any decoded track; `SongPosition` maps the playback position onto rows
+// and patterns so the visuals can sync to the sequencer.
+
+mod dsp;
+mod song;
+
+pub use dsp::{Adsr, FilterKind, Waveform};
+pub use song::{parse_pattern, DelaySettings, FilterSettings, Instrument, Song, Step, Track};
+
+use anyhow::*;
+
+use crate::audio::AudioBuffer;
+
+use dsp::{Delay, Envelope, Filter, Oscillator};
+use song::note_frequency;
+
+// Rendered past the last row so releases and delay echoes can ring out.
+const TAIL_SECONDS: f32 = 2.0;
+
+struct Voice {
+    instrument: Instrument,
+    oscillator: Oscillator,
+    envelope: Envelope,
+    filter: Option<Filter>,
+    frequency: f32,
+    // Seconds since the last note on, drives the pitch drop.
+    note_time: f32,
+}
+
+impl Voice {
+    fn new(instrument: Instrument) -> Self {
+        Self {
+            instrument,
+            oscillator: Oscillator::new(instrument.waveform),
+            envelope: Envelope::new(instrument.envelope),
+            filter: instrument.filter.map(|settings| Filter::new(settings.kind)),
+            frequency: 0.0,
+            note_time: 0.0,
+        }
+    }
+
+    fn trigger(&mut self, step: Step) {
+        match step {
+            Step::Note(note) => {
+                self.frequency = note_frequency(note);
+                self.note_time = 0.0;
+                self.oscillator.reset();
+                self.envelope.gate_on();
+            }
+            Step::Off => self.envelope.gate_off(),
+            Step::Empty => {}
+        }
+    }
+
+    fn next(&mut self, sample_rate: f32) -> f32 {
+        let drop = self.instrument.pitch_drop
+            * (-self.note_time / self.instrument.pitch_decay.max(0.0001)).exp();
+        let frequency = self.frequency * 2f32.powf(drop / 12.0);
+        self.note_time += 1.0 / sample_rate;
+
+        let level = self.envelope.next(sample_rate);
+        let mut sample = self.oscillator.next(frequency, sample_rate);
+        if let (Some(filter), Some(settings)) = (&mut self.filter, self.instrument.filter) {
+            let cutoff = settings.cutoff + settings.envelope_amount * level;
+            sample = filter.next(sample, cutoff, settings.resonance, sample_rate);
+        }
+
+        sample * level * self.instrument.volume
+    }
+}
+
+/// Renders `song` to mono samples.
+pub fn render(song: &Song, sample_rate: u32) -> Result<Vec<f32>> {
+    song.validate()?;
+
+    let rate = sample_rate as f32;
+    let frames_per_row = song.seconds_per_row() * rate;
+    let rows = song.length() * song.rows_per_pattern;
+    let frames = (rows as f32 * frames_per_row + TAIL_SECONDS * rate) as usize;
+
+    let mut voices: Vec<Voice> = song
+        .tracks
+        .iter()
+        .map(|track| Voice::new(song.instruments[track.instrument]))
+        .collect();
+    let mut delay = Delay::new(
+        (song.delay.rows as f32 * frames_per_row) as usize,
+        song.delay.feedback,
+    );
+
+    let mut samples = Vec::with_capacity(frames);
+    let mut row = 0;
+    for frame in 0..frames {
+        // Trigger every row whose start time has been reached.
+        while row < rows && frame as f32 >= row as f32 * frames_per_row {
+            let position = row / song.rows_per_pattern;
+            let line = row % song.rows_per_pattern;
+            for (voice, track) in voices.iter_mut().zip(&song.tracks) {
+                if let Some(Some(pattern)) = track.order.get(position) {
+                    voice.trigger(track.patterns[*pattern][line]);
+                } else if line == 0 {
+                    voice.trigger(Step::Off);
+                }
+            }
+            row += 1;
+        }
+
+        let mut dry = 0.0;
+        let mut send = 0.0;
+        for voice in &mut voices {
+            let sample = voice.next(rate);
+            dry += sample;
+            send += sample * voice.instrument.delay_send;
+        }
+        let wet = delay.next(send) * song.delay.level;
+
+        samples.push((dry + wet).clamp(-1.0, 1.0));
+    }
+
+    Ok(samples)
+}
+
+/// Renders `song` into a buffer that can be played like any decoded track.
+pub fn render_buffer(song: &Song, sample_rate: u32) -> Result<AudioBuffer> {
+    Ok(AudioBuffer {
+        samples: render(song, sample_rate)?.into(),
+        channels: 1,
+        sample_rate,
+    })
+}
+
+/// Maps playback time onto rows and patterns, for syncing to the sequencer.
+#[derive(Clone)]
+pub struct SongPosition {
+    seconds_per_row: f32,
+    rows_per_pattern: usize,
+}
+
+impl SongPosition {
+    pub fn new(song: &Song) -> Self {
+        Self {
+            seconds_per_row: song.seconds_per_row(),
+            rows_per_pattern: song.rows_per_pattern,
+        }
+    }
+
+    pub fn row(&self, seconds: f32) -> usize {
+        (seconds / self.seconds_per_row) as usize
+    }
+
+    /// Index into the order list and row within that pattern.
+    pub fn pattern_row(&self, seconds: f32) -> (usize, usize) {
+        let row = self.row(seconds);
+        (row / self.rows_per_pattern, row % self.rows_per_pattern)
+    }
+}
@@ -0,0 +1,157 @@
+// Song description: instruments plus tracker-style patterns.
+//
+// A pattern is written as whitespace separated rows, one token per row:
+// `C-4` / `F#3` play a note, `...` keeps whatever is playing and `===`
+// releases it.
+
+use anyhow::*;
+
+use super::dsp::{Adsr, FilterKind, Waveform};
+
+#[derive(Clone, Copy, Debug)]
+pub struct FilterSettings {
+    pub kind: FilterKind,
+    pub cutoff: f32,
+    pub resonance: f32,
+    /// Hz added to the cutoff at full envelope level.
+    pub envelope_amount: f32,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Instrument {
+    pub waveform: Waveform,
+    pub envelope: Adsr,
+    pub filter: Option<FilterSettings>,
+    pub volume: f32,
+    /// Semitones the pitch starts above the note, falling off over `pitch_decay` seconds.
+    /// Gives kicks and toms their punch.
+    pub pitch_drop: f32,
+    pub pitch_decay: f32,
+    /// Amount sent to the song's delay.
+    pub delay_send: f32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Step {
+    Empty,
+    Note(u8),
+    Off,
+}
+
+pub type Pattern = Vec<Step>;
+
+pub fn parse_pattern(source: &str) -> Result<Pattern> {
+    source.split_whitespace().map(parse_step).collect()
+}
+
+fn parse_step(token: &str) -> Result<Step> {
+    match token {
+        "..." => return Ok(Step::Empty),
+        "===" => return Ok(Step::Off),
+        _ => {}
+    }
+
+    let bytes = token.as_bytes();
+    ensure!(bytes.len() == 3, "invalid pattern step '{}'", token);
+
+    let semitone = match bytes[0] {
+        b'C' => 0,
+        b'D' => 2,
+        b'E' => 4,
+        b'F' => 5,
+        b'G' => 7,
+        b'A' => 9,
+        b'B' => 11,
+        _ => bail!("invalid note name in '{}'", token),
+    };
+    let semitone = match bytes[1] {
+        b'-' => semitone,
+        b'#' => semitone + 1,
+        _ => bail!("invalid accidental in '{}'", token),
+    };
+    let octave = match bytes[2] {
+        octave @ b'0'..=b'9' => (octave - b'0') as i32,
+        _ => bail!("invalid octave in '{}'", token),
+    };
+
+    // MIDI numbering, C-4 is 60.
+    Ok(Step::Note(((octave + 1) * 12 + semitone) as u8))
+}
+
+pub fn note_frequency(note: u8) -> f32 {
+    440.0 * 2f32.powf((note as f32 - 69.0) / 12.0)
+}
+
+pub struct Track {
+    pub instrument: usize,
+    pub patterns: Vec<Pattern>,
+    /// Pattern to play at each song position, `None` is silence.
+    pub order: Vec<Option<usize>>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DelaySettings {
+    pub rows: u32,
+    pub feedback: f32,
+    pub level: f32,
+}
+
+pub struct Song {
+    pub bpm: f32,
+    pub rows_per_beat: u32,
+    pub rows_per_pattern: usize,
+    pub instruments: Vec<Instrument>,
+    pub tracks: Vec<Track>,
+    pub delay: DelaySettings,
+}
+
+impl Song {
+    pub fn seconds_per_row(&self) -> f32 {
+        60.0 / (self.bpm * self.rows_per_beat as f32)
+    }
+
+    /// Number of pattern positions in the longest track.
+    pub fn length(&self) -> usize {
+        self.tracks
+            .iter()
+            .map(|track| track.order.len())
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(self.bpm > 0.0, "bpm must be positive");
+        ensure!(self.rows_per_beat > 0, "rows_per_beat must be positive");
+        ensure!(
+            self.rows_per_pattern > 0,
+            "rows_per_pattern must be positive"
+        );
+
+        for (index, track) in self.tracks.iter().enumerate() {
+            ensure!(
+                track.instrument < self.instruments.len(),
+                "track {} uses unknown instrument {}",
+                index,
+                track.instrument
+            );
+            for pattern in &track.patterns {
+                ensure!(
+                    pattern.len() == self.rows_per_pattern,
+                    "track {} has a pattern of {} rows, expected {}",
+                    index,
+                    pattern.len(),
+                    self.rows_per_pattern
+                );
+            }
+            for &pattern in track.order.iter().flatten() {
+                ensure!(
+                    pattern < track.patterns.len(),
+                    "track {} orders unknown pattern {}",
+                    index,
+                    pattern
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,363 @@
+// Temporal anti-aliasing for the scene passes. Every frame the projection
+// moves by another sub-pixel offset of a Halton sequence, so over a few frames
+// each pixel sees several rays. The jittered frame is blended into a history
+// reprojected from the previous camera, clamped to the colors around the pixel
+// so whatever moves on its own doesn't leave a trail.
+//
+// The offline render uses the same pass without reprojection or clamping to
+// average a number of jittered samples of one frame.
+
+use crate::camera::{self, Camera, Matrix};
+use crate::pipelines::{PipelineCache, RenderKey};
+use crate::targets::{RenderTargets, HDR_FORMAT};
+
+// Length of the jitter cycle in real time.
+const JITTER_SAMPLES: u32 = 8;
+// Weight of the new frame against the history.
+const BLEND: f32 = 0.1;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Settings {
+    // Camera space of this frame to clip space of the previous one.
+    reprojection: Matrix,
+    focal: [f32; 2],
+    blend: f32,
+    clamp: u32,
+}
+
+pub struct TemporalAa {
+    layout: wgpu::BindGroupLayout,
+    resolve_pipeline: RenderKey,
+    copy_pipeline: RenderKey,
+    sampler: wgpu::Sampler,
+    settings_buffer: wgpu::Buffer,
+    history: Vec<wgpu::TextureView>,
+    // Blending into the other history, by the one read.
+    resolve_bind_groups: Vec<wgpu::BindGroup>,
+    // Copying each history back into rt_0.
+    copy_bind_groups: Vec<wgpu::BindGroup>,
+    // Which history holds the latest frame.
+    current: usize,
+    // View projection of the last frame without jitter, `None` when there is
+    // no history to blend with.
+    previous: Option<Matrix>,
+    frame: u32,
+}
+
+impl TemporalAa {
+    pub fn new(device: &wgpu::Device, pipelines: &mut PipelineCache) -> Self {
+        pipelines.add_shader(
+            device,
+            "taa",
+            concat!(include_str!("../fullscreen.wgsl"), include_str!("taa.wgsl")),
+        );
+        let texture_entry = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                sample_type,
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+        let float = wgpu::TextureSampleType::Float { filterable: true };
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("taa"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                texture_entry(2, float),
+                texture_entry(3, wgpu::TextureSampleType::Float { filterable: false }),
+                texture_entry(4, float),
+            ],
+        });
+        pipelines.add_layout(device, "taa", &[&layout]);
+
+        Self {
+            resolve_pipeline: RenderKey::fullscreen("taa", "fs_resolve", HDR_FORMAT, None),
+            copy_pipeline: RenderKey::fullscreen("taa", "fs_copy", HDR_FORMAT, None),
+            layout,
+            sampler: device.create_sampler(&wgpu::SamplerDescriptor {
+                label: Some("taa"),
+                mag_filter: wgpu::FilterMode::Linear,
+                min_filter: wgpu::FilterMode::Linear,
+                ..Default::default()
+            }),
+            settings_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some("taa settings"),
+                size: std::mem::size_of::<Settings>() as u64,
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+            history: Vec::new(),
+            resolve_bind_groups: Vec::new(),
+            copy_bind_groups: Vec::new(),
+            current: 0,
+            previous: None,
+            frame: 0,
+        }
+    }
+
+    /// Recreates the history for new render targets, starting over.
+    pub fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        self.history = ["taa history 0", "taa history 1"]
+            .map(|label| {
+                device
+                    .create_texture(&wgpu::TextureDescriptor {
+                        label: Some(label),
+                        size: wgpu::Extent3d {
+                            width: targets.width,
+                            height: targets.height,
+                            depth_or_array_layers: 1,
+                        },
+                        mip_level_count: 1,
+                        sample_count: 1,
+                        dimension: wgpu::TextureDimension::D2,
+                        format: HDR_FORMAT,
+                        usage: wgpu::TextureUsages::TEXTURE_BINDING
+                            | wgpu::TextureUsages::RENDER_ATTACHMENT,
+                        view_formats: &[],
+                    })
+                    .create_view(&Default::default())
+            })
+            .into();
+        self.resolve_bind_groups = (0..2)
+            .map(|read| self.bind_group(device, &targets.rt_0, &targets.depth, read))
+            .collect();
+        self.copy_bind_groups = (0..2)
+            .map(|read| self.bind_group(device, &self.history[read], &targets.depth, 1 - read))
+            .collect();
+        self.previous = None;
+    }
+
+    /// Projection offset for this frame, in NDC.
+    pub fn jitter(&self, targets: &RenderTargets) -> [f32; 2] {
+        halton_jitter(self.frame % JITTER_SAMPLES, targets)
+    }
+
+    /// Blends rt_0, drawn with `camera` and this frame's jitter, into the
+    /// history and leaves the result in rt_0.
+    pub fn resolve(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargets,
+        camera: &Camera,
+    ) {
+        let projection =
+            unjittered(camera.projection(targets.width as f32 / targets.height as f32));
+        let view_projection = camera::multiply(projection, camera.view());
+        let settings = Settings {
+            reprojection: camera::multiply(
+                self.previous.unwrap_or(view_projection),
+                camera.inverse_view(),
+            ),
+            focal: [projection[0][0], projection[1][1]],
+            blend: if self.previous.is_some() { BLEND } else { 1.0 },
+            clamp: 1,
+        };
+        self.draw(device, queue, pipelines, encoder, targets, &settings);
+        self.previous = Some(view_projection);
+        self.frame = self.frame.wrapping_add(1);
+    }
+
+    /// Averages rt_0 as the `sample`th of a set of jittered samples of the
+    /// same frame, leaving the average so far in rt_0.
+    pub fn accumulate(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargets,
+        sample: u32,
+    ) {
+        // A projection with the focal length the pixels are unprojected with
+        // puts each of them back in place.
+        let settings = Settings {
+            reprojection: [
+                [1.0, 0.0, 0.0, 0.0],
+                [0.0, 1.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 1.0],
+                [0.0, 0.0, camera::NEAR, 0.0],
+            ],
+            focal: [1.0, 1.0],
+            blend: 1.0 / (sample + 1) as f32,
+            clamp: 0,
+        };
+        self.draw(device, queue, pipelines, encoder, targets, &settings);
+        // Frames of the offline render don't blend with each other.
+        self.previous = None;
+    }
+
+    fn draw(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        targets: &RenderTargets,
+        settings: &Settings,
+    ) {
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::bytes_of(settings));
+
+        let read = self.current;
+        self.current = 1 - read;
+        let passes = [
+            (
+                &self.history[self.current],
+                &self.resolve_pipeline,
+                &self.resolve_bind_groups[read],
+            ),
+            (
+                &targets.rt_0,
+                &self.copy_pipeline,
+                &self.copy_bind_groups[self.current],
+            ),
+        ];
+        for (view, pipeline, bind_group) in passes {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("taa"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            render_pass.set_pipeline(pipelines.render(device, pipeline));
+            render_pass.set_bind_group(0, bind_group, &[]);
+            render_pass.draw(0..3, 0..1);
+        }
+    }
+
+    fn bind_group(
+        &self,
+        device: &wgpu::Device,
+        source: &wgpu::TextureView,
+        depth: &wgpu::TextureView,
+        history: usize,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("taa"),
+            layout: &self.layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: self.settings_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(source),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(depth),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&self.history[history]),
+                },
+            ],
+        })
+    }
+}
+
+/// The `index`th point of the Halton (2, 3) sequence, as an offset of up to
+/// half a pixel of the targets in NDC.
+pub fn halton_jitter(index: u32, targets: &RenderTargets) -> [f32; 2] {
+    let [x, y] = halton_offset(index);
+    [
+        x * 2.0 / targets.width as f32,
+        y * 2.0 / targets.height as f32,
+    ]
+}
+
+// In pixels, from -0.5 to 0.5.
+fn halton_offset(index: u32) -> [f32; 2] {
+    [2, 3].map(|base| halton(index + 1, base) - 0.5)
+}
+
+fn halton(mut index: u32, base: u32) -> f32 {
+    let mut fraction = 1.0;
+    let mut result = 0.0;
+    while index > 0 {
+        fraction /= base as f32;
+        result += fraction * (index % base) as f32;
+        index /= base;
+    }
+    result
+}
+
+fn unjittered(mut projection: Matrix) -> Matrix {
+    projection[2][0] = 0.0;
+    projection[2][1] = 0.0;
+    projection
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn halton_sequence() {
+        let base_2: Vec<f32> = (1..=4).map(|index| halton(index, 2)).collect();
+        assert_eq!(base_2, [0.5, 0.25, 0.75, 0.125]);
+        let base_3: Vec<f32> = (1..=4).map(|index| halton(index, 3)).collect();
+        for (value, expected) in base_3
+            .iter()
+            .zip([1.0 / 3.0, 2.0 / 3.0, 1.0 / 9.0, 4.0 / 9.0])
+        {
+            assert!((value - expected).abs() < 1e-6, "{} != {}", value, expected);
+        }
+        assert_eq!(halton(0, 2), 0.0);
+    }
+
+    #[test]
+    fn jitter_cycle_covers_the_pixel() {
+        let points: Vec<[f32; 2]> = (0..JITTER_SAMPLES).map(halton_offset).collect();
+        for point in &points {
+            assert!(point.iter().all(|offset| offset.abs() < 0.5), "{:?}", point);
+        }
+        for quadrant in [[false, false], [false, true], [true, false], [true, true]] {
+            assert!(
+                points.iter().any(|[x, y]| [*x > 0.0, *y > 0.0] == quadrant),
+                "no sample in {:?}",
+                quadrant
+            );
+        }
+    }
+
+    #[test]
+    fn unjittered_drops_the_offset() {
+        let mut projection = camera::IDENTITY;
+        projection[2][0] = 0.01;
+        projection[2][1] = -0.02;
+        assert_eq!(unjittered(projection), camera::IDENTITY);
+    }
+}
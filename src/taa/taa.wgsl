@@ -0,0 +1,65 @@
+struct Settings {
+    // Camera space of this frame to clip space of the previous one.
+    reprojection: mat4x4<f32>,
+    // x and y scale of the projection, without jitter
+    focal: vec2<f32>,
+    // Weight of this frame against the history.
+    blend: f32,
+    // Clamp the history to the colors around each pixel.
+    clamp: u32,
+};
+
+@group(0) @binding(0) var<uniform> settings: Settings;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var source: texture_2d<f32>;
+// Read as floats, GL can't load from depth textures.
+@group(0) @binding(3) var depth: texture_2d<f32>;
+@group(0) @binding(4) var history: texture_2d<f32>;
+
+// camera::NEAR, depth is near / z.
+const near = 0.05;
+
+@fragment
+fn fs_resolve(in: VertexOutput) -> @location(0) vec4<f32> {
+    let pixel = vec2<i32>(in.position.xy);
+    let color = textureLoad(source, pixel, 0).rgb;
+    if (settings.blend >= 1.0) {
+        return vec4<f32>(color, 1.0);
+    }
+
+    // Where the pixel was last frame, as if it was drawn without jitter.
+    let ndc = vec2<f32>(in.uv.x * 2.0 - 1.0, 1.0 - in.uv.y * 2.0);
+    let ray = vec3<f32>(ndc / settings.focal, 1.0);
+    let z = textureLoad(depth, pixel, 0).r;
+    // Nothing hit is infinitely far away, only turning the camera moves it.
+    var point = vec4<f32>(ray, 0.0);
+    if (z > 0.0) {
+        point = vec4<f32>(ray * near / z, 1.0);
+    }
+    let clip = settings.reprojection * point;
+    let previous = clip.xy / clip.w * vec2<f32>(0.5, -0.5) + 0.5;
+    if (clip.w <= 0.0 || any(previous < vec2<f32>(0.0)) || any(previous > vec2<f32>(1.0))) {
+        return vec4<f32>(color, 1.0);
+    }
+    var past = textureSampleLevel(history, linear_sampler, previous, 0.0).rgb;
+
+    if (settings.clamp != 0u) {
+        let size = vec2<i32>(textureDimensions(source)) - 1;
+        var low = color;
+        var high = color;
+        for (var y = -1; y <= 1; y++) {
+            for (var x = -1; x <= 1; x++) {
+                let neighbor = textureLoad(source, clamp(pixel + vec2<i32>(x, y), vec2<i32>(0), size), 0).rgb;
+                low = min(low, neighbor);
+                high = max(high, neighbor);
+            }
+        }
+        past = clamp(past, low, high);
+    }
+    return vec4<f32>(mix(past, color, settings.blend), 1.0);
+}
+
+@fragment
+fn fs_copy(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureLoad(source, vec2<i32>(in.position.xy), 0);
+}
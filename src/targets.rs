@@ -0,0 +1,280 @@
+// Offscreen targets for the scene passes, recreated whenever the surface
+// changes size.
+//
+// With a design aspect ratio the scene renders at the largest size of that
+// ratio fitting the surface, and the final pass draws into the matching
+// viewport, leaving black bars around it. The targets can be smaller than the
+// viewport by a render scale, which `DynamicScale` adjusts to the frame time.
+
+use std::time::{Duration, Instant};
+
+/// Linear color with headroom above 1, tone mapped in the final pass.
+pub const HDR_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Reversed depth, 1 at the near plane and 0 at infinity, see `camera::Camera::projection`.
+pub const DEPTH_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Depth32Float;
+
+const MIN_SCALE: f32 = 0.25;
+const SCALE_STEP: f32 = 0.05;
+// Frame times are averaged over this long before changing the scale again.
+const SCALE_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Area of the surface the demo is shown in, in pixels.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Viewport {
+    /// Centered in `width` x `height`, as large as possible with the aspect ratio.
+    pub fn fit(width: u32, height: u32, aspect: Option<f32>) -> Self {
+        let (width, height) = (width as f32, height as f32);
+        let (fit_width, fit_height) = match aspect {
+            Some(aspect) if width / height > aspect => (height * aspect, height),
+            Some(aspect) => (width, width / aspect),
+            None => (width, height),
+        };
+        // Whole pixels, so the scene maps one to one onto the surface.
+        let (fit_width, fit_height) = (fit_width.round().max(1.0), fit_height.round().max(1.0));
+
+        Self {
+            x: ((width - fit_width) * 0.5).floor(),
+            y: ((height - fit_height) * 0.5).floor(),
+            width: fit_width,
+            height: fit_height,
+        }
+    }
+}
+
+pub struct RenderTargets {
+    pub viewport: Viewport,
+    /// Size of the targets, the viewport times the render scale.
+    pub width: u32,
+    pub height: u32,
+    /// HDR color, ping-ponged between the scene, post-processing and composite
+    /// passes, then tone mapped for the display-referred effects.
+    pub rt_0: wgpu::TextureView,
+    pub rt_1: wgpu::TextureView,
+    /// Depth of the scene pass, raymarched and meshes, for everything drawn into rt_0.
+    pub depth: wgpu::TextureView,
+    /// Samples per pixel of the geometry passes.
+    pub sample_count: u32,
+    /// With MSAA the geometry passes draw into these, see `color_attachment`.
+    pub msaa_color: Option<wgpu::TextureView>,
+    pub msaa_depth: Option<wgpu::TextureView>,
+}
+
+impl RenderTargets {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        aspect: Option<f32>,
+        scale: f32,
+        sample_count: u32,
+    ) -> Self {
+        let viewport = Viewport::fit(config.width, config.height, aspect);
+        let width = ((viewport.width * scale).round() as u32).max(1);
+        let height = ((viewport.height * scale).round() as u32).max(1);
+        let descriptor = wgpu::TextureDescriptor {
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: HDR_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            label: None,
+            view_formats: &[],
+        };
+        let create = |label| {
+            device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some(label),
+                    ..descriptor
+                })
+                .create_view(&Default::default())
+        };
+
+        Self {
+            viewport,
+            width,
+            height,
+            rt_0: create("rt_0"),
+            rt_1: create("rt_1"),
+            depth: device
+                .create_texture(&wgpu::TextureDescriptor {
+                    label: Some("depth"),
+                    format: DEPTH_FORMAT,
+                    // Sampled for the reprojection of temporal anti-aliasing.
+                    usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                    ..descriptor
+                })
+                .create_view(&Default::default()),
+            sample_count,
+            msaa_color: (sample_count > 1).then(|| {
+                device
+                    .create_texture(&wgpu::TextureDescriptor {
+                        label: Some("msaa color"),
+                        sample_count,
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                        ..descriptor
+                    })
+                    .create_view(&Default::default())
+            }),
+            msaa_depth: (sample_count > 1).then(|| {
+                device
+                    .create_texture(&wgpu::TextureDescriptor {
+                        label: Some("msaa depth"),
+                        sample_count,
+                        format: DEPTH_FORMAT,
+                        // Read by the depth resolve.
+                        usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                            | wgpu::TextureUsages::TEXTURE_BINDING,
+                        ..descriptor
+                    })
+                    .create_view(&Default::default())
+            }),
+        }
+    }
+
+    /// rt_0 for the geometry passes, or the multisampled color resolving into it.
+    pub fn color_attachment(
+        &self,
+        load: wgpu::LoadOp<wgpu::Color>,
+    ) -> wgpu::RenderPassColorAttachment<'_> {
+        wgpu::RenderPassColorAttachment {
+            view: self.msaa_color.as_ref().unwrap_or(&self.rt_0),
+            resolve_target: self.msaa_color.as_ref().map(|_| &self.rt_0),
+            ops: wgpu::Operations { load, store: true },
+        }
+    }
+
+    /// Depth for the geometry passes, multisampled with MSAA.
+    pub fn depth_attachment(
+        &self,
+        load: wgpu::LoadOp<f32>,
+    ) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        wgpu::RenderPassDepthStencilAttachment {
+            view: self.msaa_depth.as_ref().unwrap_or(&self.depth),
+            depth_ops: Some(wgpu::Operations { load, store: true }),
+            stencil_ops: None,
+        }
+    }
+}
+
+/// Lowers the render scale while frames take longer than the target and
+/// raises it again, up to `max`, once there is headroom.
+///
+/// Frame times are measured on the CPU, so with vsync the scale only goes up
+/// again when frames finish well within the refresh interval. Only frames
+/// since the last change are averaged, so frames rendered at the old scale
+/// don't push it further.
+pub struct DynamicScale {
+    pub scale: f32,
+    max: f32,
+    target: Duration,
+    last_change: Instant,
+    frame_time_sum: Duration,
+    frames: u32,
+}
+
+impl DynamicScale {
+    pub fn new(max: f32, target: Duration) -> Self {
+        Self {
+            scale: max,
+            max,
+            target,
+            last_change: Instant::now(),
+            frame_time_sum: Duration::ZERO,
+            frames: 0,
+        }
+    }
+
+    /// Returns true when the scale changed and the targets need recreating.
+    pub fn update(&mut self, now: Instant, frame_time: Duration) -> bool {
+        self.frame_time_sum += frame_time;
+        self.frames += 1;
+        if now.duration_since(self.last_change) < SCALE_INTERVAL {
+            return false;
+        }
+
+        let average_frame_time = self.frame_time_sum / self.frames;
+        let ratio = average_frame_time.as_secs_f32() / self.target.as_secs_f32();
+        let scale = if ratio > 1.05 {
+            self.scale - SCALE_STEP
+        } else if ratio < 0.8 {
+            self.scale + SCALE_STEP
+        } else {
+            return false;
+        };
+        let scale = scale.clamp(MIN_SCALE.min(self.max), self.max);
+        if scale == self.scale {
+            return false;
+        }
+
+        self.scale = scale;
+        self.last_change = now;
+        self.frame_time_sum = Duration::ZERO;
+        self.frames = 0;
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn viewport(x: f32, y: f32, width: f32, height: f32) -> Viewport {
+        Viewport {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn fills_without_an_aspect() {
+        assert_eq!(
+            Viewport::fit(800, 600, None),
+            viewport(0.0, 0.0, 800.0, 600.0)
+        );
+    }
+
+    #[test]
+    fn letterboxes_and_pillarboxes() {
+        let aspect = Some(16.0 / 9.0);
+        assert_eq!(
+            Viewport::fit(1280, 1024, aspect),
+            viewport(0.0, 152.0, 1280.0, 720.0)
+        );
+        // 711.1 wide rounds to whole pixels, the odd bar pixel goes right.
+        assert_eq!(
+            Viewport::fit(1000, 400, aspect),
+            viewport(144.0, 0.0, 711.0, 400.0)
+        );
+        // Barely wider than 16:9 still fits the height.
+        assert_eq!(
+            Viewport::fit(1921, 1080, aspect),
+            viewport(0.0, 0.0, 1920.0, 1080.0)
+        );
+    }
+
+    #[test]
+    fn keeps_at_least_a_pixel() {
+        assert_eq!(
+            Viewport::fit(1, 1000, Some(16.0 / 9.0)),
+            viewport(0.0, 499.0, 1.0, 1.0)
+        );
+    }
+}
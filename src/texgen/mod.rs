@@ -0,0 +1,364 @@
+// Procedural texture generation.
+//
+// A `TextureGraph` is a list of operators where every node may only reference
+// nodes added before it. `TextureGenerator` evaluates the part of the graph an
+// output depends on with compute shaders and hands back a `resource::Texture`
+// that binds like any loaded image.
+
+use anyhow::*;
+use wgpu::util::DeviceExt;
+
+use crate::pipelines::{ComputeKey, PipelineCache};
+use crate::resource::Texture;
+
+const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+const WORKGROUP_SIZE: u32 = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Clone, Copy, Debug)]
+pub enum NoiseKind {
+    Value,
+    Cellular,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    /// Tileable fractal noise mapped from `low` to `high`. `frequency` is the
+    /// number of cells across the texture for the first octave.
+    Noise {
+        kind: NoiseKind,
+        frequency: u32,
+        octaves: u32,
+        persistence: f32,
+        seed: u32,
+        low: [f32; 4],
+        high: [f32; 4],
+    },
+    /// Offsets lookups into `source` by the red/green channels of `offset`.
+    Distort {
+        source: NodeId,
+        offset: NodeId,
+        strength: f32,
+    },
+}
+
+impl Op {
+    fn inputs(&self) -> Vec<NodeId> {
+        match *self {
+            Op::Noise { .. } => vec![],
+            Op::Distort { source, offset, .. } => vec![source, offset],
+        }
+    }
+
+    fn entry_point(&self) -> &'static str {
+        match self {
+            Op::Noise { .. } => "noise",
+            Op::Distort { .. } => "distort",
+        }
+    }
+
+    fn params(&self) -> Params {
+        let mut params = Params::zeroed();
+        match *self {
+            Op::Noise {
+                kind,
+                frequency,
+                octaves,
+                persistence,
+                seed,
+                low,
+                high,
+            } => {
+                params.color_a = low;
+                params.color_b = high;
+                params.args = [frequency as f32, persistence, 0.0, 0.0];
+                params.mode = kind as u32;
+                params.seed = seed;
+                params.octaves = octaves;
+            }
+            Op::Distort { strength, .. } => params.args = [strength, 0.0, 0.0, 0.0],
+        }
+        params
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Params {
+    color_a: [f32; 4],
+    color_b: [f32; 4],
+    args: [f32; 4],
+    mode: u32,
+    seed: u32,
+    octaves: u32,
+    _pad: u32,
+}
+
+impl Params {
+    fn zeroed() -> Self {
+        bytemuck::Zeroable::zeroed()
+    }
+}
+
+pub struct TextureGraph {
+    width: u32,
+    height: u32,
+    nodes: Vec<Op>,
+}
+
+impl TextureGraph {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            nodes: Vec::new(),
+        }
+    }
+
+    pub fn add(&mut self, op: Op) -> NodeId {
+        self.nodes.push(op);
+        NodeId(self.nodes.len() - 1)
+    }
+
+    /// Marks every node `output` depends on, checking that inputs only point backwards.
+    fn reachable(&self, output: NodeId) -> Result<Vec<bool>> {
+        ensure!(
+            output.0 < self.nodes.len(),
+            "unknown output node {}",
+            output.0
+        );
+
+        let mut needed = vec![false; self.nodes.len()];
+        needed[output.0] = true;
+        for index in (0..self.nodes.len()).rev() {
+            if !needed[index] {
+                continue;
+            }
+            for input in self.nodes[index].inputs() {
+                ensure!(
+                    input.0 < index,
+                    "node {} uses node {} which is not defined before it",
+                    index,
+                    input.0
+                );
+                needed[input.0] = true;
+            }
+        }
+        Ok(needed)
+    }
+}
+
+pub struct TextureGenerator {
+    bind_group_layout: wgpu::BindGroupLayout,
+    sampler: wgpu::Sampler,
+}
+
+impl TextureGenerator {
+    pub fn new(device: &wgpu::Device, pipelines: &mut PipelineCache) -> Self {
+        pipelines.add_shader(device, "texgen", include_str!("texgen.wgsl"));
+
+        let input_entry = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            },
+            count: None,
+        };
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("texgen bind group layout"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                input_entry(2),
+                input_entry(3),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: FORMAT,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+            ],
+        });
+
+        pipelines.add_layout(device, "texgen", &[&bind_group_layout]);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("texgen sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::Repeat,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            bind_group_layout,
+            sampler,
+        }
+    }
+
+    /// Evaluates `output` and everything it depends on.
+    pub fn generate(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        graph: &TextureGraph,
+        output: NodeId,
+        label: Option<&str>,
+    ) -> Result<Texture> {
+        let needed = graph.reachable(output)?;
+
+        let size = wgpu::Extent3d {
+            width: graph.width,
+            height: graph.height,
+            depth_or_array_layers: 1,
+        };
+        let create_target = |label| {
+            device.create_texture(&wgpu::TextureDescriptor {
+                label,
+                size,
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: FORMAT,
+                usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::STORAGE_BINDING,
+                view_formats: &[],
+            })
+        };
+
+        // Bound to input slots an operator does not read.
+        let placeholder = create_target(Some("texgen placeholder"));
+        let placeholder_view = placeholder.create_view(&Default::default());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("texgen encoder"),
+        });
+
+        let mut results: Vec<Option<(wgpu::Texture, wgpu::TextureView)>> =
+            (0..graph.nodes.len()).map(|_| None).collect();
+
+        for (index, op) in graph.nodes.iter().enumerate() {
+            if !needed[index] {
+                continue;
+            }
+
+            let inputs = op.inputs();
+            let input_view = |slot: usize| match inputs.get(slot) {
+                Some(id) => &results[id.0].as_ref().unwrap().1,
+                None => &placeholder_view,
+            };
+
+            let target = create_target(if index == output.0 { label } else { None });
+            let target_view = target.create_view(&Default::default());
+
+            self.dispatch(
+                device,
+                pipelines,
+                &mut encoder,
+                op.entry_point(),
+                &op.params(),
+                [input_view(0), input_view(1)],
+                &target_view,
+                size,
+            );
+
+            results[index] = Some((target, target_view));
+        }
+
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (texture, _) = results[output.0].take().unwrap();
+        Ok(Texture::from_texture(device, texture))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn dispatch(
+        &self,
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        entry_point: &'static str,
+        params: &Params,
+        inputs: [&wgpu::TextureView; 2],
+        output: &wgpu::TextureView,
+        size: wgpu::Extent3d,
+    ) {
+        let params_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("texgen params"),
+            contents: bytemuck::bytes_of(params),
+            usage: wgpu::BufferUsages::UNIFORM,
+        });
+
+        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some("texgen bind group"),
+            layout: &self.bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: params_buffer.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&self.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(inputs[0]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::TextureView(inputs[1]),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(output),
+                },
+            ],
+        });
+
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+            label: Some(entry_point),
+        });
+        pass.set_pipeline(pipelines.compute(
+            device,
+            &ComputeKey {
+                shader: "texgen",
+                layout: "texgen",
+                entry_point,
+            },
+        ));
+        pass.set_bind_group(0, &bind_group, &[]);
+        pass.dispatch_workgroups(
+            size.width.div_ceil(WORKGROUP_SIZE),
+            size.height.div_ceil(WORKGROUP_SIZE),
+            1,
+        );
+    }
+}
@@ -0,0 +1,111 @@
+struct Params {
+    color_a: vec4<f32>,
+    color_b: vec4<f32>,
+    args: vec4<f32>,
+    mode: u32,
+    seed: u32,
+    octaves: u32,
+    _pad: u32,
+};
+
+@group(0) @binding(0) var<uniform> p: Params;
+@group(0) @binding(1) var samp: sampler;
+@group(0) @binding(2) var input_a: texture_2d<f32>;
+@group(0) @binding(3) var input_b: texture_2d<f32>;
+@group(0) @binding(4) var output: texture_storage_2d<rgba16float, write>;
+
+fn hash(x: u32, y: u32, seed: u32) -> f32 {
+    // PCG-style integer hash, stable across drivers.
+    var h = x * 747796405u + y * 2891336453u + seed * 277803737u;
+    h = ((h >> ((h >> 28u) + 4u)) ^ h) * 277803737u;
+    h = (h >> 22u) ^ h;
+    return f32(h) / 4294967295.0;
+}
+
+fn wrap(i: i32, period: i32) -> u32 {
+    return u32(((i % period) + period) % period);
+}
+
+// Value noise that tiles with the given period (in cells).
+fn value_noise(x: vec2<f32>, period: i32, seed: u32) -> f32 {
+    let i = vec2<i32>(floor(x));
+    let f = fract(x);
+    let w = f * f * (3.0 - 2.0 * f);
+
+    let a = hash(wrap(i.x, period), wrap(i.y, period), seed);
+    let b = hash(wrap(i.x + 1, period), wrap(i.y, period), seed);
+    let c = hash(wrap(i.x, period), wrap(i.y + 1, period), seed);
+    let d = hash(wrap(i.x + 1, period), wrap(i.y + 1, period), seed);
+
+    return mix(mix(a, b, w.x), mix(c, d, w.x), w.y);
+}
+
+// Distance to the nearest feature point, tiling with the given period.
+fn cellular_noise(x: vec2<f32>, period: i32, seed: u32) -> f32 {
+    let i = vec2<i32>(floor(x));
+    let f = fract(x);
+    var nearest = 8.0;
+    for (var dy = -1; dy <= 1; dy++) {
+        for (var dx = -1; dx <= 1; dx++) {
+            let cell = i + vec2<i32>(dx, dy);
+            let cx = wrap(cell.x, period);
+            let cy = wrap(cell.y, period);
+            let point = vec2<f32>(hash(cx, cy, seed), hash(cx, cy, seed + 1u));
+            nearest = min(nearest, length(vec2<f32>(f32(dx), f32(dy)) + point - f));
+        }
+    }
+    return clamp(nearest, 0.0, 1.0);
+}
+
+fn uv_of(id: vec2<u32>) -> vec2<f32> {
+    return (vec2<f32>(id) + 0.5) / vec2<f32>(textureDimensions(output));
+}
+
+fn in_bounds(id: vec2<u32>) -> bool {
+    let size = textureDimensions(output);
+    return id.x < size.x && id.y < size.y;
+}
+
+// args: x = frequency in cells, y = persistence
+// mode: 0 = value fbm, 1 = cellular
+@compute @workgroup_size(8, 8)
+fn noise(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (!in_bounds(id.xy)) {
+        return;
+    }
+    let uv = uv_of(id.xy);
+
+    var value = 0.0;
+    var amplitude = 1.0;
+    var total = 0.0;
+    var period = max(i32(p.args.x), 1);
+    for (var octave = 0u; octave < max(p.octaves, 1u); octave++) {
+        let x = uv * f32(period);
+        let seed = p.seed + octave * 1013u;
+        var n = 0.0;
+        if (p.mode == 0u) {
+            n = value_noise(x, period, seed);
+        } else {
+            n = cellular_noise(x, period, seed);
+        }
+        value += n * amplitude;
+        total += amplitude;
+        amplitude *= p.args.y;
+        period *= 2;
+    }
+
+    textureStore(output, id.xy, mix(p.color_a, p.color_b, value / total));
+}
+
+// Offsets lookups into input_a by the red/green channels of input_b.
+// args.x = strength in uv units
+@compute @workgroup_size(8, 8)
+fn distort(@builtin(global_invocation_id) id: vec3<u32>) {
+    if (!in_bounds(id.xy)) {
+        return;
+    }
+    let uv = uv_of(id.xy);
+    let offset = (textureLoad(input_b, id.xy, 0).rg * 2.0 - 1.0) * p.args.x;
+
+    textureStore(output, id.xy, textureSampleLevel(input_a, samp, uv + offset, 0.0));
+}
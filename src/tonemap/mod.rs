@@ -0,0 +1,193 @@
+// Tone mapping from HDR into 0..1. Exposure scales the scene first, then one
+// of the curves in `config::ToneMap` maps it. The display-referred effects run
+// on the result, then the same pass without a curve takes it to the surface.
+// Targets smaller than the viewport are upscaled bicubic on the way.
+//
+// sRGB surfaces encode on write, other unorm surfaces get the encoding in the
+// shader and float surfaces are left linear.
+
+use crate::config::ToneMap;
+use crate::pipelines::{PipelineCache, RenderKey};
+use crate::targets::{RenderTargets, Viewport};
+
+// Curve for colors that are tone mapped already, also in the shader.
+const PASSTHROUGH: u32 = 3;
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct Settings {
+    viewport: [f32; 2],
+    exposure: f32,
+    curve: u32,
+    encode_srgb: u32,
+    _pad: [u32; 3],
+}
+
+pub struct ToneMapper {
+    curve: Option<ToneMap>,
+    encode_srgb: bool,
+    layout: wgpu::BindGroupLayout,
+    pipeline: RenderKey,
+    sampler: wgpu::Sampler,
+    settings_buffer: wgpu::Buffer,
+    // Reading rt_0 and rt_1.
+    bind_groups: Vec<wgpu::BindGroup>,
+}
+
+impl ToneMapper {
+    /// Without a curve the colors pass through as they are.
+    pub fn new(
+        device: &wgpu::Device,
+        pipelines: &mut PipelineCache,
+        format: wgpu::TextureFormat,
+        curve: Option<ToneMap>,
+    ) -> Self {
+        pipelines.add_shader(
+            device,
+            "tonemap",
+            concat!(
+                include_str!("../fullscreen.wgsl"),
+                include_str!("tonemap.wgsl")
+            ),
+        );
+        let layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("tonemap"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+            ],
+        });
+        pipelines.add_layout(device, "tonemap", &[&layout]);
+
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("tonemap"),
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        let settings_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("tonemap settings"),
+            size: std::mem::size_of::<Settings>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            curve,
+            encode_srgb: !format.is_srgb() && !is_float(format),
+            layout,
+            pipeline: RenderKey::fullscreen("tonemap", "fs_main", format, None),
+            sampler,
+            settings_buffer,
+            bind_groups: Vec::new(),
+        }
+    }
+
+    /// Rebuilds the bind groups for new render targets.
+    pub fn resize(&mut self, device: &wgpu::Device, targets: &RenderTargets) {
+        self.bind_groups = [&targets.rt_0, &targets.rt_1]
+            .into_iter()
+            .map(|view| {
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some("tonemap"),
+                    layout: &self.layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: self.settings_buffer.as_entire_binding(),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::Sampler(&self.sampler),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(view),
+                        },
+                    ],
+                })
+            })
+            .collect();
+    }
+
+    /// Draws rt_0 or rt_1 by `source` into `viewport` of `view`, clearing
+    /// around it to black.
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        pipelines: &mut PipelineCache,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        viewport: Viewport,
+        source: usize,
+        exposure: f32,
+    ) {
+        let settings = Settings {
+            viewport: [viewport.width, viewport.height],
+            exposure,
+            curve: self.curve.map_or(PASSTHROUGH, |curve| curve as u32),
+            encode_srgb: self.encode_srgb as u32,
+            _pad: [0; 3],
+        };
+        queue.write_buffer(&self.settings_buffer, 0, bytemuck::bytes_of(&settings));
+
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("tonemap"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        render_pass.set_viewport(
+            viewport.x,
+            viewport.y,
+            viewport.width,
+            viewport.height,
+            0.0,
+            1.0,
+        );
+        render_pass.set_pipeline(pipelines.render(device, &self.pipeline));
+        render_pass.set_bind_group(0, &self.bind_groups[source], &[]);
+        render_pass.draw(0..3, 0..1);
+    }
+}
+
+fn is_float(format: wgpu::TextureFormat) -> bool {
+    matches!(
+        format,
+        wgpu::TextureFormat::Rgba16Float
+            | wgpu::TextureFormat::Rgba32Float
+            | wgpu::TextureFormat::Rg11b10Float
+    )
+}
@@ -0,0 +1,139 @@
+struct Settings {
+    // Size of the viewport in pixels.
+    viewport: vec2<f32>,
+    // In stops.
+    exposure: f32,
+    // config::ToneMap, 0 ACES, 1 Reinhard, 2 AgX, 3 none for colors tone mapped already
+    curve: u32,
+    encode_srgb: u32,
+    _pad0: u32,
+    _pad1: u32,
+    _pad2: u32,
+};
+
+@group(0) @binding(0) var<uniform> settings: Settings;
+@group(0) @binding(1) var linear_sampler: sampler;
+@group(0) @binding(2) var source: texture_2d<f32>;
+
+// Catmull-Rom filtering in 9 bilinear taps, for render targets smaller than the output.
+fn sample_bicubic(uv: vec2<f32>) -> vec4<f32> {
+    let size = vec2<f32>(textureDimensions(source));
+    let position = uv * size;
+    let center = floor(position - 0.5) + 0.5;
+    let f = position - center;
+
+    let w0 = f * (-0.5 + f * (1.0 - 0.5 * f));
+    let w1 = 1.0 + f * f * (-2.5 + 1.5 * f);
+    let w2 = f * (0.5 + f * (2.0 - 1.5 * f));
+    let w3 = f * f * (-0.5 + 0.5 * f);
+    let w12 = w1 + w2;
+
+    let t0 = (center - 1.0) / size;
+    let t3 = (center + 2.0) / size;
+    let t12 = (center + w2 / w12) / size;
+
+    var color = vec4<f32>(0.0);
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t0.x, t0.y), 0.0) * w0.x * w0.y;
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t12.x, t0.y), 0.0) * w12.x * w0.y;
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t3.x, t0.y), 0.0) * w3.x * w0.y;
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t0.x, t12.y), 0.0) * w0.x * w12.y;
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t12.x, t12.y), 0.0) * w12.x * w12.y;
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t3.x, t12.y), 0.0) * w3.x * w12.y;
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t0.x, t3.y), 0.0) * w0.x * w3.y;
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t12.x, t3.y), 0.0) * w12.x * w3.y;
+    color += textureSampleLevel(source, linear_sampler, vec2<f32>(t3.x, t3.y), 0.0) * w3.x * w3.y;
+    return max(color, vec4<f32>(0.0));
+}
+
+// Plain bilinear when the targets match the viewport, bicubic when upscaling.
+fn sample_scene(uv: vec2<f32>) -> vec4<f32> {
+    if (all(vec2<f32>(textureDimensions(source)) == settings.viewport)) {
+        return textureSampleLevel(source, linear_sampler, uv, 0.0);
+    }
+    return sample_bicubic(uv);
+}
+
+// Stephen Hill's fit, sRGB to the ACES RRT input space and back out after the curve.
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let input = mat3x3<f32>(
+        vec3<f32>(0.59719, 0.35458, 0.04823),
+        vec3<f32>(0.07600, 0.90834, 0.01566),
+        vec3<f32>(0.02840, 0.13383, 0.83777),
+    );
+    let output = mat3x3<f32>(
+        vec3<f32>(1.60475, -0.53108, -0.07367),
+        vec3<f32>(-0.10208, 1.10813, -0.00605),
+        vec3<f32>(-0.00327, -0.07276, 1.07602),
+    );
+    // The matrices are written by rows, multiplying from the left transposes them.
+    let v = color * input;
+    let a = v * (v + 0.0245786) - 0.000090537;
+    let b = v * (0.983729 * v + 0.4329510) + 0.238081;
+    return clamp((a / b) * output, vec3<f32>(0.0), vec3<f32>(1.0));
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (1.0 + color);
+}
+
+// Minimal AgX after Benjamin Wrensch, a log encoding in an inset space,
+// a sigmoid fitted as a polynomial, then back out of the inset.
+fn agx_contrast(x: vec3<f32>) -> vec3<f32> {
+    let x2 = x * x;
+    let x4 = x2 * x2;
+    return 15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2
+        + 0.1191 * x - 0.00232;
+}
+
+fn agx(color: vec3<f32>) -> vec3<f32> {
+    let inset = mat3x3<f32>(
+        vec3<f32>(0.842479062253094, 0.0423282422610123, 0.0423756549057051),
+        vec3<f32>(0.0784335999999992, 0.878468636469772, 0.0784336),
+        vec3<f32>(0.0792237451477643, 0.0791661274605434, 0.879142973793104),
+    );
+    let outset = mat3x3<f32>(
+        vec3<f32>(1.19687900512017, -0.0528968517574562, -0.0529716355144438),
+        vec3<f32>(-0.0980208811401368, 1.15190312990417, -0.0980434501171241),
+        vec3<f32>(-0.0990297440797205, -0.0989611768448433, 1.15107367264116),
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    var v = inset * max(color, vec3<f32>(1e-10));
+    v = clamp(log2(v), vec3<f32>(min_ev), vec3<f32>(max_ev));
+    v = (v - min_ev) / (max_ev - min_ev);
+    v = agx_contrast(v);
+    // The curve ends up display encoded, back to linear like the other two.
+    v = outset * v;
+    return pow(max(v, vec3<f32>(0.0)), vec3<f32>(2.2));
+}
+
+fn to_srgb(linear: vec3<f32>) -> vec3<f32> {
+    let low = linear * 12.92;
+    let high = 1.055 * pow(linear, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, linear <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let hdr = sample_scene(in.uv).rgb * exp2(settings.exposure);
+    var color: vec3<f32>;
+    switch settings.curve {
+        case 1u: {
+            color = reinhard(hdr);
+        }
+        case 2u: {
+            color = agx(hdr);
+        }
+        case 3u: {
+            color = hdr;
+        }
+        default: {
+            color = aces(hdr);
+        }
+    }
+    if (settings.encode_srgb != 0u) {
+        color = to_srgb(clamp(color, vec3<f32>(0.0), vec3<f32>(1.0)));
+    }
+    return vec4<f32>(color, 1.0);
+}
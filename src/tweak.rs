@@ -0,0 +1,149 @@
+// Tweak panel for shader parameters, built with the `tweak` feature.
+
+use egui_wgpu::renderer::ScreenDescriptor;
+use winit::event::WindowEvent;
+use winit::window::Window;
+
+use crate::parameters::{ParameterKind, ParameterSet};
+
+pub struct TweakGui {
+    context: egui::Context,
+    state: egui_winit::State,
+    renderer: egui_wgpu::Renderer,
+    visible: bool,
+    status: String,
+}
+
+impl TweakGui {
+    pub fn new(window: &Window, device: &wgpu::Device, format: wgpu::TextureFormat) -> Self {
+        let mut state = egui_winit::State::new(window);
+        state.set_pixels_per_point(window.scale_factor() as f32);
+
+        Self {
+            context: egui::Context::default(),
+            state,
+            renderer: egui_wgpu::Renderer::new(device, format, None, 1),
+            visible: false,
+            status: String::new(),
+        }
+    }
+
+    pub fn toggle(&mut self) {
+        self.visible = !self.visible;
+    }
+
+    pub fn is_visible(&self) -> bool {
+        self.visible
+    }
+
+    /// Returns true when the panel used the event and the demo should ignore it.
+    pub fn on_event(&mut self, event: &WindowEvent) -> bool {
+        if let WindowEvent::ScaleFactorChanged { scale_factor, .. } = event {
+            self.state.set_pixels_per_point(*scale_factor as f32);
+        }
+        if !self.visible {
+            return false;
+        }
+        self.state.on_event(&self.context, event).consumed
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    pub fn draw(
+        &mut self,
+        window: &Window,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        encoder: &mut wgpu::CommandEncoder,
+        view: &wgpu::TextureView,
+        parameters: &mut ParameterSet,
+        path: &str,
+    ) {
+        if !self.visible {
+            return;
+        }
+
+        let input = self.state.take_egui_input(window);
+        let status = &mut self.status;
+        let output = self.context.run(input, |context| {
+            egui::Window::new("Parameters").show(context, |ui| {
+                for parameter in parameters.iter_mut() {
+                    match parameter.kind {
+                        ParameterKind::Float { min, max } => {
+                            ui.add(
+                                egui::Slider::new(&mut parameter.value[0], min..=max)
+                                    .text(parameter.name),
+                            );
+                        }
+                        ParameterKind::Int { min, max } => {
+                            let mut value = parameter.value[0] as i32;
+                            ui.add(egui::Slider::new(&mut value, min..=max).text(parameter.name));
+                            parameter.value[0] = value as f32;
+                        }
+                        ParameterKind::Color => {
+                            ui.horizontal(|ui| {
+                                let mut rgb =
+                                    [parameter.value[0], parameter.value[1], parameter.value[2]];
+                                ui.color_edit_button_rgb(&mut rgb);
+                                parameter.value[..3].copy_from_slice(&rgb);
+                                ui.label(parameter.name);
+                            });
+                        }
+                    }
+                }
+
+                ui.separator();
+                ui.horizontal(|ui| {
+                    if ui.button("Save").clicked() {
+                        *status = match parameters.save(path) {
+                            Ok(()) => format!("Saved to {}", path),
+                            Err(error) => format!("Saving failed: {:#}", error),
+                        };
+                    }
+                    if ui.button("Reset").clicked() {
+                        parameters.reset();
+                        status.clear();
+                    }
+                });
+                if !status.is_empty() {
+                    ui.label(status.as_str());
+                }
+            });
+        });
+
+        self.state
+            .handle_platform_output(window, &self.context, output.platform_output);
+
+        let primitives = self.context.tessellate(output.shapes);
+        let size = window.inner_size();
+        let screen = ScreenDescriptor {
+            size_in_pixels: [size.width, size.height],
+            pixels_per_point: window.scale_factor() as f32,
+        };
+
+        for (id, delta) in &output.textures_delta.set {
+            self.renderer.update_texture(device, queue, *id, delta);
+        }
+        self.renderer
+            .update_buffers(device, queue, encoder, &primitives, &screen);
+
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("tweak gui"),
+                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                    view,
+                    resolve_target: None,
+                    ops: wgpu::Operations {
+                        load: wgpu::LoadOp::Load,
+                        store: true,
+                    },
+                })],
+                depth_stencil_attachment: None,
+            });
+            self.renderer.render(&mut render_pass, &primitives, &screen);
+        }
+
+        for id in &output.textures_delta.free {
+            self.renderer.free_texture(id);
+        }
+    }
+}